@@ -52,8 +52,45 @@ pub const NO_OP_STORAGE_PRUNER_CONFIG: StoragePrunerConfig = StoragePrunerConfig
     state_store_prune_window: None,
     ledger_prune_window: None,
     pruning_batch_size: 10_000,
+    pruning_rate_limit_bytes_per_sec: None,
+    pruning_write_retries: None,
+    pruning_slow_batch_threshold_ms: None,
+    pruning_window_utc_hours: None,
+    archive_pruned_ledger: false,
+    pruning_min_interval_ms: 0,
+    keep_latest_event_per_key: false,
+    log_pruned_events: false,
+    event_accumulator_retain_margin: 0,
+    event_deletion_order: EventDeletionOrder::Ascending,
+    ledger_pruning_commit_threshold: None,
+    prune_transaction_accumulator: false,
+    epoch_aligned_ledger_pruning: false,
+    isolate_event_accumulator_failures: false,
+    never_prune_genesis: false,
+    warm_up_pruners: false,
+    parallel_event_pruning: false,
 };
 
+/// The order the pruner adds the range deletions of the events to the batch in, e.g. to spread
+/// the tombstones over the keyspace rather than piling them up at its low end, where iteration
+/// starts. What's deleted is the same whatever the order.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventDeletionOrder {
+    /// The whole range in one deletion.
+    Ascending,
+    /// Split up into chunks, from the last one back.
+    Descending,
+    /// Split up into chunks, alternately from either end towards the middle.
+    Interleaved,
+}
+
+impl Default for EventDeletionOrder {
+    fn default() -> Self {
+        Self::Ascending
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct StoragePrunerConfig {
@@ -67,6 +104,73 @@ pub struct StoragePrunerConfig {
     /// Batch size of the versions to be sent to the pruner - this is to avoid slowdown due to
     /// issuing too many DB calls and batch prune instead.
     pub pruning_batch_size: usize,
+    /// If set, caps the bytes the pruner writes per second, for it not to starve the main write
+    /// path of disk IO.
+    #[serde(default)]
+    pub pruning_rate_limit_bytes_per_sec: Option<u64>,
+    /// If set, the writes the pruner makes itself are retried up to that many times on transient
+    /// failures, backing off exponentially in between.
+    #[serde(default)]
+    pub pruning_write_retries: Option<u32>,
+    /// If set, pruning rounds taking longer than that many milliseconds are warned about.
+    #[serde(default)]
+    pub pruning_slow_batch_threshold_ms: Option<u64>,
+    /// If set, pruning only runs daily from the first to the second hour of the day in UTC, e.g.
+    /// to keep it to low traffic hours. The window spans midnight if it ends before it starts.
+    #[serde(default)]
+    pub pruning_window_utc_hours: Option<(u8, u8)>,
+    /// If set, the events and write sets pruned are moved into the `ledger_archive` DB under the
+    /// DB root rather than just deleted.
+    #[serde(default)]
+    pub archive_pruned_ledger: bool,
+    /// The ledger pruner does nothing when asked to prune within that long of its last commit,
+    /// bounding how often it writes to the DB however often it's called. 0 leaves it unbounded.
+    #[serde(default)]
+    pub pruning_min_interval_ms: u64,
+    /// If set, the ledger pruner keeps the latest event of each event key, e.g. for keys acting
+    /// as counters, deleting it only once a later one is pruned.
+    #[serde(default)]
+    pub keep_latest_event_per_key: bool,
+    /// If set, the ledger pruner logs how many events of each event key it deleted, once the
+    /// deletions are committed, e.g. to keep an audit trail of the pruning.
+    #[serde(default)]
+    pub log_pruned_events: bool,
+    /// The event accumulator of that many versions right below the pruning target is kept while
+    /// their events are pruned, so that event range proofs can still be served for them.
+    #[serde(default)]
+    pub event_accumulator_retain_margin: u64,
+    /// The order the range deletions of the events pruned are added to the batch in.
+    #[serde(default)]
+    pub event_deletion_order: EventDeletionOrder,
+    /// If set, the ledger pruner commits its deletions itself in the middle of a round once at
+    /// least that many are collected, for a round over a large range not to build up a huge
+    /// batch in memory.
+    #[serde(default)]
+    pub ledger_pruning_commit_threshold: Option<usize>,
+    /// If set, the ledger pruner also prunes the transaction accumulator nodes only the pruned
+    /// versions need, at the cost of the consistency proofs from them.
+    #[serde(default)]
+    pub prune_transaction_accumulator: bool,
+    /// If set, the ledger pruner only ever settles right after the end of an epoch, every epoch
+    /// being either pruned whole or left whole, e.g. for nodes serving epoch change proofs.
+    #[serde(default)]
+    pub epoch_aligned_ledger_pruning: bool,
+    /// If set, a failure pruning the event accumulator leaves the ledger pruner to go on with the
+    /// events, the accumulator being retried in the next rounds rather than failing them.
+    #[serde(default)]
+    pub isolate_event_accumulator_failures: bool,
+    /// If set, the ledger and state store pruners the pruning worker runs keep the data of
+    /// genesis, i.e. version 0, rather than pruning it along with the rest.
+    #[serde(default)]
+    pub never_prune_genesis: bool,
+    /// If set, the pruning worker pre-reads what the first round of each pruner covers on start,
+    /// for that round to find the block cache warm.
+    #[serde(default)]
+    pub warm_up_pruners: bool,
+    /// If set, the ledger pruner prunes the events, their indices and the event accumulator in
+    /// parallel, trading cores of the shared rayon pool for the speed of large batches.
+    #[serde(default)]
+    pub parallel_event_pruning: bool,
 }
 
 impl StoragePrunerConfig {
@@ -79,6 +183,23 @@ impl StoragePrunerConfig {
             state_store_prune_window,
             ledger_prune_window: ledger_store_prune_window,
             pruning_batch_size,
+            pruning_rate_limit_bytes_per_sec: None,
+            pruning_write_retries: None,
+            pruning_slow_batch_threshold_ms: None,
+            pruning_window_utc_hours: None,
+            archive_pruned_ledger: false,
+            pruning_min_interval_ms: 0,
+            keep_latest_event_per_key: false,
+            log_pruned_events: false,
+            event_accumulator_retain_margin: 0,
+            event_deletion_order: EventDeletionOrder::Ascending,
+            ledger_pruning_commit_threshold: None,
+            prune_transaction_accumulator: false,
+            epoch_aligned_ledger_pruning: false,
+            isolate_event_accumulator_failures: false,
+            never_prune_genesis: false,
+            warm_up_pruners: false,
+            parallel_event_pruning: false,
         }
     }
 }
@@ -100,6 +221,23 @@ impl Default for StorageConfig {
                 state_store_prune_window: Some(1_000_000),
                 ledger_prune_window: Some(10_000_000),
                 pruning_batch_size: 500,
+                pruning_rate_limit_bytes_per_sec: None,
+                pruning_write_retries: None,
+                pruning_slow_batch_threshold_ms: None,
+                pruning_window_utc_hours: None,
+                archive_pruned_ledger: false,
+                pruning_min_interval_ms: 0,
+                keep_latest_event_per_key: false,
+                log_pruned_events: false,
+                event_accumulator_retain_margin: 0,
+                event_deletion_order: EventDeletionOrder::Ascending,
+                ledger_pruning_commit_threshold: None,
+                prune_transaction_accumulator: false,
+                epoch_aligned_ledger_pruning: false,
+                isolate_event_accumulator_failures: false,
+                never_prune_genesis: false,
+                warm_up_pruners: false,
+                parallel_event_pruning: false,
             },
             data_dir: PathBuf::from("/opt/aptos/data"),
             // Default read/write/connection timeout, in milliseconds
@@ -0,0 +1,118 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_temppath::TempPath;
+use aptosdb::{test_helper, AptosDB};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const NUM_VERSIONS: u64 = 10_000;
+const EVENTS_PER_VERSION: usize = 10;
+
+const NUM_SPARSE_VERSIONS: u64 = 1_000_000;
+const SPARSE_EVENT_INTERVAL: u64 = 100_000;
+const VERSIONS_PER_ROUND: u64 = 100;
+
+fn event_pruning(c: &mut Criterion) {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    test_helper::put_events(&db, NUM_VERSIONS, EVENTS_PER_VERSION);
+    let num_events = NUM_VERSIONS as usize * EVENTS_PER_VERSION;
+
+    let mut group = c.benchmark_group("event_pruning");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(num_events as u64));
+    for parallel in [false, true] {
+        let name = if parallel { "parallel" } else { "serial" };
+        group.bench_function(BenchmarkId::new(name, num_events), |b| {
+            // The batch is only built, never written, so every iteration prunes the same events.
+            b.iter(|| test_helper::prune_events(&db, NUM_VERSIONS, parallel))
+        });
+    }
+    group.finish();
+}
+
+fn sparse_event_pruning(c: &mut Criterion) {
+    // Events written through the store are tracked, so the rounds known to hold none skip the DB.
+    let tracked_dir = TempPath::new();
+    let tracked_db = AptosDB::new_for_test(&tracked_dir);
+    test_helper::put_sparse_events(&tracked_db, NUM_SPARSE_VERSIONS, SPARSE_EVENT_INTERVAL);
+    // Once reopened, nothing is known of the events before, so every round seeks the DB.
+    let untracked_dir = TempPath::new();
+    test_helper::put_sparse_events(
+        &AptosDB::new_for_test(&untracked_dir),
+        NUM_SPARSE_VERSIONS,
+        SPARSE_EVENT_INTERVAL,
+    );
+    let untracked_db = AptosDB::new_for_test(&untracked_dir);
+
+    let mut group = c.benchmark_group("sparse_event_pruning");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(
+        NUM_SPARSE_VERSIONS / VERSIONS_PER_ROUND,
+    ));
+    for (name, db) in [("untracked", &untracked_db), ("tracked", &tracked_db)] {
+        group.bench_function(BenchmarkId::new(name, NUM_SPARSE_VERSIONS), |b| {
+            b.iter(|| {
+                test_helper::prune_events_in_rounds(db, NUM_SPARSE_VERSIONS, VERSIONS_PER_ROUND)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn pruning_read_options(c: &mut Criterion) {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    test_helper::put_events(&db, NUM_VERSIONS, EVENTS_PER_VERSION);
+    test_helper::flush_all(&db);
+    let num_events = NUM_VERSIONS as usize * EVENTS_PER_VERSION;
+
+    let mut group = c.benchmark_group("pruning_read_options");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(num_events as u64));
+    // Leaving the cache alone goes first, so the blocks it reads aren't already cached.
+    for fill_cache in [false, true] {
+        let name = if fill_cache {
+            "fill_cache"
+        } else {
+            "no_fill_cache"
+        };
+        group.bench_function(BenchmarkId::new(name, num_events), |b| {
+            b.iter(|| test_helper::scan_pruning_candidates(&db, NUM_VERSIONS, fill_cache))
+        });
+    }
+    group.finish();
+}
+
+fn event_deletion_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("event_deletion_order");
+    group.sample_size(10);
+    for interleaved in [false, true] {
+        let name = if interleaved {
+            "interleaved"
+        } else {
+            "ascending"
+        };
+        let tmp_dir = TempPath::new();
+        let db = AptosDB::new_for_test(&tmp_dir);
+        test_helper::put_events(&db, NUM_VERSIONS, EVENTS_PER_VERSION);
+        test_helper::flush_all(&db);
+        // The first half is pruned, so seeking from the start goes over its tombstones, which are
+        // flushed along with the rest, as they would be on a node, but left uncompacted.
+        test_helper::prune_event_schema(&db, NUM_VERSIONS / 2, interleaved);
+        test_helper::flush_all(&db);
+        group.bench_function(BenchmarkId::new(name, NUM_VERSIONS), |b| {
+            b.iter(|| test_helper::seek_first_event(&db))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    event_pruning,
+    sparse_event_pruning,
+    pruning_read_options,
+    event_deletion_order
+);
+criterion_main!(benches);
@@ -2,10 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    get_first_seq_num_and_limit, test_helper,
+    get_first_seq_num_and_limit,
+    schema::DB_METADATA_CF_NAME,
+    test_helper,
     test_helper::{arb_blocks_to_commit, put_as_state_root, put_transaction_info},
     AptosDB, ROCKSDB_PROPERTIES,
 };
+use aptos_config::config::{RocksdbConfig, NO_OP_STORAGE_PRUNER_CONFIG};
 use aptos_crypto::{hash::CryptoHash, HashValue};
 use aptos_temppath::TempPath;
 use aptos_types::{
@@ -14,6 +17,7 @@ use aptos_types::{
     transaction::{ExecutionStatus, TransactionInfo, PRE_GENESIS_VERSION},
 };
 use proptest::prelude::*;
+use schemadb::{Options, DB};
 use std::time::Duration;
 use storage_interface::{DbReader, Order, TreeState};
 use test_helper::{test_save_blocks_impl, test_sync_transactions_impl};
@@ -137,3 +141,35 @@ fn test_rocksdb_properties_reporter() {
     std::thread::sleep(Duration::from_secs(1));
     assert_eq!(get_metric(), 1);
 }
+
+#[test]
+fn test_open_readonly_without_db_metadata() {
+    let tmp_dir = TempPath::new();
+    // A DB created before the pruner progress got its column family.
+    let mut db_opts = Options::default();
+    db_opts.create_if_missing(true);
+    db_opts.create_missing_column_families(true);
+    let column_families = AptosDB::column_families()
+        .into_iter()
+        .filter(|cf_name| *cf_name != DB_METADATA_CF_NAME)
+        .collect();
+    drop(
+        DB::open(
+            tmp_dir.path().join("aptosdb"),
+            "aptosdb",
+            column_families,
+            &db_opts,
+        )
+        .unwrap(),
+    );
+
+    let db = AptosDB::open(
+        &tmp_dir,
+        true, /* readonly */
+        NO_OP_STORAGE_PRUNER_CONFIG,
+        RocksdbConfig::default(),
+    )
+    .unwrap();
+    assert!(!db.db.column_families().contains(&DB_METADATA_CF_NAME));
+    db.update_rocksdb_properties().unwrap();
+}
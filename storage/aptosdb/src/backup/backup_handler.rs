@@ -22,6 +22,7 @@ use aptos_types::{
     transaction::{Transaction, TransactionInfo, Version},
 };
 use itertools::zip_eq;
+use schemadb::ReadOptions;
 use serde::{Deserialize, Serialize};
 use std::{fmt, sync::Arc};
 
@@ -62,9 +63,11 @@ impl BackupHandler {
         let txn_info_iter = self
             .ledger_store
             .get_transaction_info_iter(start_version, num_transactions)?;
-        let events_iter = self
-            .event_store
-            .get_events_by_version_iter(start_version, num_transactions)?;
+        let events_iter = self.event_store.get_events_by_version_iter(
+            start_version,
+            num_transactions,
+            ReadOptions::default(),
+        )?;
 
         let zipped = zip_eq(zip_eq(txn_iter, txn_info_iter), events_iter)
             .enumerate()
@@ -10,6 +10,7 @@ use crate::{
     change_set::ChangeSet,
     errors::AptosDbError,
     ledger_counters::{LedgerCounter, LedgerCounterBumps},
+    pruner::{deletion_order::DeletionOrder, prune_read_options::PruneReadOptions},
     schema::{
         event::EventSchema, event_accumulator::EventAccumulatorSchema,
         event_by_key::EventByKeySchema, event_by_version::EventByVersionSchema,
@@ -21,31 +22,206 @@ use aptos_crypto::{
     hash::{CryptoHash, EventAccumulatorHasher},
     HashValue,
 };
+use aptos_infallible::Mutex;
+use aptos_logger::warn;
 use aptos_types::{
     account_address::AccountAddress,
     account_config::NewBlockEvent,
     block_metadata::new_block_event_key,
     contract_event::ContractEvent,
     event::EventKey,
-    proof::{position::Position, EventAccumulatorProof, EventProof},
+    proof::{position::Position, AccumulatorRangeProof, EventAccumulatorProof, EventProof},
     transaction::Version,
 };
+use move_deps::move_core_types::language_storage::TypeTag;
 use schemadb::{schema::ValueCodec, ReadOptions, SchemaBatch, SchemaIterator, DB};
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    cmp::{max, min},
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
     convert::{TryFrom, TryInto},
     iter::Peekable,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
+/// Number of versions sharing one entry in the event presence set.
+const EVENT_PRESENCE_BUCKET_SIZE: Version = 1024;
+
+/// Keeps track of the buckets of versions that might hold events, so that ranges of versions known
+/// to be empty can be skipped without seeking the DB. Nothing is known of the versions before
+/// `known_since`, which are treated as if they all held events. It's only told of the events
+/// written by `EventStore::put_events()`, which every write of events is to go through.
+#[derive(Debug)]
+struct EventPresence {
+    known_since: Version,
+    non_empty_buckets: BTreeSet<u64>,
+}
+
+impl EventPresence {
+    fn new(known_since: Version) -> Self {
+        Self {
+            known_since,
+            non_empty_buckets: BTreeSet::new(),
+        }
+    }
+
+    fn mark_non_empty(&mut self, version: Version) {
+        self.non_empty_buckets
+            .insert(version / EVENT_PRESENCE_BUCKET_SIZE);
+    }
+
+    /// Returns the first version in [start, end) that might hold events, if any.
+    fn first_possibly_non_empty(&self, start: Version, end: Version) -> Option<Version> {
+        if start >= end {
+            return None;
+        }
+        if start < self.known_since {
+            return Some(start);
+        }
+        let bucket = self
+            .non_empty_buckets
+            .range(start / EVENT_PRESENCE_BUCKET_SIZE..)
+            .next()?;
+        let version = max(start, bucket * EVENT_PRESENCE_BUCKET_SIZE);
+        if version < end {
+            Some(version)
+        } else {
+            None
+        }
+    }
+
+    /// Stops tracking the buckets entirely before `version`, which are treated as unknown from
+    /// then on.
+    fn forget_before(&mut self, version: Version) {
+        let first_bucket = version / EVENT_PRESENCE_BUCKET_SIZE;
+        self.known_since = max(self.known_since, first_bucket * EVENT_PRESENCE_BUCKET_SIZE);
+        self.non_empty_buckets = self.non_empty_buckets.split_off(&first_bucket);
+    }
+}
+
+/// How `prune_events_with_options()` prunes a range of versions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PruneEventsOptions {
+    /// Whether the events of the version the range ends at are pruned as well, i.e. whether the
+    /// range is [start, end] rather than [start, end).
+    pub inclusive_end: bool,
+    /// Whether the events, their indices and the event accumulator are pruned in parallel, each
+    /// into a batch of its own, rather than one after another. Off by default, since it takes
+    /// cores from the rayon pool shared with the rest of the node, for nodes with cores to spare
+    /// on large batches to opt in.
+    pub parallel: bool,
+    /// Whether the latest event of each key, i.e. the one with the highest sequence number in the
+    /// DB, is kept even in the range pruned, see `EventStore::deleted_events()`.
+    pub keep_latest_per_key: bool,
+    /// Whether the event accumulator is pruned along with the events, rather than left to be
+    /// pruned apart by `EventStore::prune_lagging_event_accumulator()`.
+    pub with_accumulator: bool,
+    /// The keys whose events are never pruned, along with their by key and by version index
+    /// entries. The event accumulator is pruned all the same, so the events kept below the least
+    /// readable version can still be read, but no longer proven.
+    pub retain_keys: HashSet<EventKey>,
+}
+
+impl Default for PruneEventsOptions {
+    fn default() -> Self {
+        Self {
+            inclusive_end: false,
+            parallel: false,
+            keep_latest_per_key: false,
+            with_accumulator: true,
+            retain_keys: HashSet::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EventStore {
     db: Arc<DB>,
+    /// Since all events are written via `put_events()`, the presence of events is only tracked
+    /// beyond the last event found in the DB on start up.
+    event_presence: Mutex<EventPresence>,
+    /// Whether the DB may hold event accumulator nodes, told once on start up and set as soon as
+    /// `put_events()` writes some, so that pruning doesn't seek the accumulator every round.
+    has_event_accumulator: AtomicBool,
+    /// Number of versions right below the pruning target whose event accumulator is kept while
+    /// their events are pruned, see `with_accumulator_retain_margin()`.
+    accumulator_retain_margin: Version,
+    /// How the events about to be pruned are read, see `with_prune_read_options()`.
+    prune_read_options: PruneReadOptions,
+    /// If set, called between the sub-components pruned, see `with_yields_between_sub_prunes()`.
+    sub_prune_yield: Option<fn()>,
+    /// The order the events and their by version index are deleted in, see
+    /// `with_deletion_order()`.
+    deletion_order: DeletionOrder,
 }
 
 impl EventStore {
     pub fn new(db: Arc<DB>) -> Self {
-        Self { db }
+        // If the DB can't be read, nothing is known about the presence of events.
+        let known_since = Self::first_version_after_events(&db).unwrap_or(Version::MAX);
+        // If the DB can't be read, the accumulator is assumed to be there.
+        let has_event_accumulator = Self::has_event_accumulator(&db).unwrap_or(true);
+        Self {
+            db,
+            event_presence: Mutex::new(EventPresence::new(known_since)),
+            has_event_accumulator: AtomicBool::new(has_event_accumulator),
+            accumulator_retain_margin: 0,
+            prune_read_options: PruneReadOptions::default(),
+            sub_prune_yield: None,
+            deletion_order: DeletionOrder::default(),
+        }
+    }
+
+    /// Keeps the event accumulator of the `accumulator_retain_margin` versions right below the
+    /// pruning target, while their events are pruned as usual, so that range proofs overlapping
+    /// the pruned history can still be served cheaply off the frozen nodes, see
+    /// `get_event_range_proof()`. The trade-off is storage: the nodes of up to that many more
+    /// versions are kept, roughly two hashes per event.
+    pub fn with_accumulator_retain_margin(mut self, accumulator_retain_margin: Version) -> Self {
+        self.accumulator_retain_margin = accumulator_retain_margin;
+        self
+    }
+
+    /// Sets how the iterators going through the events about to be pruned read the DB, which by
+    /// default leave the block cache alone.
+    pub fn with_prune_read_options(mut self, prune_read_options: PruneReadOptions) -> Self {
+        self.prune_read_options = prune_read_options;
+        self
+    }
+
+    /// Sets the order the range deletions of the events and of their by version index entries
+    /// are added to the batch in, which by default is a single range, in version order.
+    pub fn with_deletion_order(mut self, deletion_order: DeletionOrder) -> Self {
+        self.deletion_order = deletion_order;
+        self
+    }
+
+    /// Has pruning yield the thread between the sub-components it prunes, i.e. the events, their
+    /// indices and the event accumulator, so that a long round doesn't hold a core for its whole
+    /// length. They are then pruned one after another rather than in parallel, which costs
+    /// throughput, so it's meant for latency sensitive nodes.
+    #[allow(dead_code)]
+    pub fn with_yields_between_sub_prunes(self) -> Self {
+        self.with_sub_prune_yield(std::thread::yield_now)
+    }
+
+    /// Same as `with_yields_between_sub_prunes()`, with `yield_now` called in place of
+    /// `std::thread::yield_now()`.
+    fn with_sub_prune_yield(mut self, yield_now: fn()) -> Self {
+        self.sub_prune_yield = Some(yield_now);
+        self
+    }
+
+    /// Returns the version right after the last one holding events, or 0 if there are none.
+    fn first_version_after_events(db: &DB) -> Result<Version> {
+        let mut iter = db.iter::<EventSchema>(ReadOptions::default())?;
+        iter.seek_to_last();
+        Ok(iter
+            .next()
+            .transpose()?
+            .map_or(0, |((version, _index), _event)| version + 1))
     }
 
     /// Get all of the events given a transaction version.
@@ -67,12 +243,15 @@ impl EventStore {
         Ok(events)
     }
 
+    /// Iterates the events of `num_versions` versions from `start_version` on, reading the DB
+    /// with `read_options`, e.g. to not fill the block cache when going through a large range once.
     pub fn get_events_by_version_iter(
         &self,
         start_version: Version,
         num_versions: usize,
+        read_options: ReadOptions,
     ) -> Result<EventsByVersionIter> {
-        let mut iter = self.db.iter::<EventSchema>(Default::default())?;
+        let mut iter = self.db.iter::<EventSchema>(read_options)?;
         iter.seek(&start_version)?;
 
         Ok(EventsByVersionIter {
@@ -120,6 +299,26 @@ impl EventStore {
         Ok((event, proof))
     }
 
+    /// Returns a proof that the `num_leaves` events from `first_index` on are part of the event
+    /// accumulator of the transaction at `version`, which holds `num_events` events in total.
+    /// Since it's only built off the accumulator, it can also be served for the versions within
+    /// the accumulator retain margin after their events are pruned.
+    #[allow(dead_code)]
+    pub fn get_event_range_proof(
+        &self,
+        version: Version,
+        num_events: u64,
+        first_index: Option<u64>,
+        num_leaves: u64,
+    ) -> Result<AccumulatorRangeProof<EventAccumulatorHasher>> {
+        Accumulator::get_range_proof(
+            &EventHashReader::new(self, version),
+            num_events,
+            first_index,
+            num_leaves,
+        )
+    }
+
     pub fn get_txn_ver_by_seq_num(&self, event_key: &EventKey, seq_num: u64) -> Result<u64> {
         let (ver, _) = self
             .db
@@ -229,6 +428,10 @@ impl EventStore {
 
     /// Save contract events yielded by the transaction at `version` and return root hash of the
     /// event accumulator formed by these events.
+    ///
+    /// Every write of events to the DB is to go through here, restores included, since it's what
+    /// the presence of events is tracked from after start up, see `EventPresence`. Events written
+    /// otherwise in a bucket of versions thought empty would be skipped by pruning, and left.
     pub fn put_events(
         &self,
         version: u64,
@@ -237,6 +440,11 @@ impl EventStore {
     ) -> Result<HashValue> {
         cs.counter_bumps(version)
             .bump(LedgerCounter::EventsCreated, events.len());
+        if !events.is_empty() {
+            // Marked before the write is committed, which in the worst case only makes a range
+            // with no events look possibly non-empty.
+            self.event_presence.lock().mark_non_empty(version);
+        }
 
         // Event table and indices updates
         events
@@ -258,6 +466,9 @@ impl EventStore {
         // EventAccumulatorSchema updates
         let event_hashes: Vec<HashValue> = events.iter().map(ContractEvent::hash).collect();
         let (root_hash, writes) = EmptyAccumulator::append(&EmptyReader, 0, &event_hashes)?;
+        if !writes.is_empty() {
+            self.has_event_accumulator.store(true, Ordering::Relaxed);
+        }
         writes.into_iter().try_for_each(|(pos, hash)| {
             cs.batch
                 .put::<EventAccumulatorSchema>(&(version, pos), &hash)
@@ -362,31 +573,58 @@ impl EventStore {
             .ok_or_else(|| format_err!("A block with non-zero seq num started at version 0."))
     }
 
-    /// Prunes the events by key store for a set of events
-    pub fn prune_events_by_key(
-        &self,
+    /// Computes, in a single pass over a set of events, the event keys they are emitted under
+    /// along with the range of sequence numbers `(min, max)` each key covers, in whatever order the
+    /// events come
+    pub fn sequence_range_by_event_keys(
         candidate_events: &[ContractEvent],
-        db_batch: &mut SchemaBatch,
-    ) -> anyhow::Result<()> {
+    ) -> HashMap<EventKey, (u64, u64)> {
         let mut sequence_range_by_event_keys: HashMap<EventKey, (u64, u64)> = HashMap::new();
 
         candidate_events.iter().for_each(|event| {
             let event_key = event.key();
-            // Events should be sorted by sequence numbers, so the first sequence number for the
-            // event key should be the minimum
+            let seq_num = event.sequence_number();
+            // Events are expected in the order of sequence numbers, but the range doesn't rely on
+            // it, since getting it wrong would have the by-key pruning delete the wrong events.
             match sequence_range_by_event_keys.entry(*event_key) {
                 Entry::Occupied(mut occupied) => {
-                    occupied.insert((occupied.get().0, event.sequence_number()));
+                    let (min_seq_num, max_seq_num) = *occupied.get();
+                    occupied.insert((min(min_seq_num, seq_num), max(max_seq_num, seq_num)));
                 }
                 Entry::Vacant(vacant) => {
-                    vacant.insert((event.sequence_number(), event.sequence_number()));
+                    vacant.insert((seq_num, seq_num));
                 }
             }
         });
+        sequence_range_by_event_keys
+    }
+
+    /// Prunes the events by key store for a set of events
+    pub fn prune_events_by_key(
+        &self,
+        candidate_events: &[ContractEvent],
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        self.prune_event_key_index(
+            &Self::sequence_range_by_event_keys(candidate_events),
+            db_batch,
+        )
+    }
 
+    /// Prunes the events by key store for the ranges of sequence numbers computed by
+    /// `sequence_range_by_event_keys()`, or given by hand. Only the index is touched, the events
+    /// themselves and their by version entries are left as is, e.g. to drop stale entries of a
+    /// rebuilt index.
+    pub fn prune_event_key_index(
+        &self,
+        sequence_range_by_event_keys: &HashMap<EventKey, (u64, u64)>,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
         for (event_key, (min, max)) in sequence_range_by_event_keys {
-            db_batch
-                .delete_range_inclusive::<EventByKeySchema>(&(event_key, min), &(event_key, max));
+            db_batch.delete_range_inclusive::<EventByKeySchema>(
+                &(*event_key, *min),
+                &(*event_key, *max),
+            );
         }
         Ok(())
     }
@@ -398,14 +636,194 @@ impl EventStore {
         end: Version,
         db_batch: &mut SchemaBatch,
     ) -> anyhow::Result<()> {
+        if !self.has_event_accumulator.load(Ordering::Relaxed) {
+            // E.g. a DB from before event accumulators existed, there's nothing to prune nor to
+            // verify.
+            return Ok(());
+        }
         let mut iter = self.db.iter::<EventAccumulatorSchema>(Default::default())?;
         iter.seek(&(begin, Position::from_inorder_index(0)))?;
         while let Some(((version, position), _)) = iter.next().transpose()? {
             if version >= end {
-                return Ok(());
+                break;
             }
             db_batch.delete::<EventAccumulatorSchema>(&(version, position))?;
         }
+        // Reads the events of the version and recomputes its root, which is a debugging aid too
+        // costly for every round in release builds.
+        if cfg!(debug_assertions) {
+            self.verify_event_accumulator(end, db_batch)?;
+        }
+        Ok(())
+    }
+
+    /// True if the DB holds any event accumulator node at all.
+    fn has_event_accumulator(db: &DB) -> Result<bool> {
+        let mut iter = db.iter::<EventAccumulatorSchema>(Default::default())?;
+        iter.seek_to_first();
+        Ok(iter.next().transpose()?.is_some())
+    }
+
+    /// True if any event accumulator node of the range of versions [begin, end) is left.
+    fn has_event_accumulator_nodes(&self, begin: Version, end: Version) -> Result<bool> {
+        if end <= begin {
+            return Ok(false);
+        }
+        let mut iter = self.db.iter::<EventAccumulatorSchema>(Default::default())?;
+        iter.seek(&(begin, Position::from_inorder_index(0)))?;
+        Ok(match iter.next().transpose()? {
+            Some(((version, _position), _hash)) => version < end,
+            None => false,
+        })
+    }
+
+    /// Returns the root hash of the event accumulator of `version` as recomputed from the nodes
+    /// stored in the DB, failing if any is missing. A version without events has the placeholder
+    /// hash.
+    pub fn get_event_accumulator_root_hash(&self, version: Version) -> Result<HashValue> {
+        let num_events = self.get_events_by_version(version)?.len() as u64;
+        Accumulator::get_root_hash(&EventHashReader::new(self, version), num_events)
+    }
+
+    /// Makes sure the event accumulator root at `version` can still be recomputed from the nodes
+    /// left once `db_batch` is committed, so that the events of the first version left readable
+    /// after pruning can still be proven. A version whose accumulator is gone while some of its
+    /// events are left was pruned selectively, e.g. keeping the latest event of each key, and its
+    /// events no longer come with a proof, so there is nothing to check.
+    fn verify_event_accumulator(
+        &self,
+        version: Version,
+        db_batch: &SchemaBatch,
+    ) -> anyhow::Result<()> {
+        let event_hashes: Vec<HashValue> = self
+            .get_events_by_version(version)?
+            .iter()
+            .map(ContractEvent::hash)
+            .collect();
+        if event_hashes.is_empty()
+            || !self.has_event_accumulator_nodes(version, version.saturating_add(1))?
+        {
+            return Ok(());
+        }
+        let (expected_root_hash, _) = EmptyAccumulator::append(&EmptyReader, 0, &event_hashes)?;
+        let root_hash = Accumulator::get_root_hash(
+            &EventHashReader::new(self, version).without_deleted(db_batch)?,
+            event_hashes.len() as u64,
+        )
+        .map_err(|e| {
+            format_err!(
+                "Event accumulator at version {} is not recomputable: {}",
+                version,
+                e
+            )
+        })?;
+        ensure!(
+            root_hash == expected_root_hash,
+            "Event accumulator root at version {} is {}, expected {}.",
+            version,
+            root_hash,
+            expected_root_hash,
+        );
+        Ok(())
+    }
+
+    /// Makes sure everything needed to read and prove the events at `version` is in the DB: the
+    /// events, their by key index, and the accumulator nodes to prove them with. Meant to be run
+    /// on the first version left after pruning, to catch anything deleted beyond the range.
+    pub fn verify_events_readable(&self, version: Version) -> anyhow::Result<()> {
+        let events = self.get_events_by_version(version)?;
+        if events.is_empty() {
+            let mut iter = self.db.iter::<EventAccumulatorSchema>(Default::default())?;
+            iter.seek(&(version, Position::from_inorder_index(0)))?;
+            let accumulator_left = matches!(
+                iter.next().transpose()?,
+                Some(((accumulator_version, _), _)) if accumulator_version == version
+            );
+            ensure!(
+                !accumulator_left,
+                "Events at version {} are gone while their accumulator is left.",
+                version,
+            );
+            return Ok(());
+        }
+
+        let event_hashes: Vec<HashValue> = events.iter().map(ContractEvent::hash).collect();
+        let (root_hash, _) = EmptyAccumulator::append(&EmptyReader, 0, &event_hashes)?;
+        for (index, event) in events.iter().enumerate() {
+            let index = index as u64;
+            self.get_event_with_proof_by_version_and_index(version, index)
+                .and_then(|(_, proof)| proof.verify(root_hash, event.hash(), index))
+                .map_err(|e| {
+                    format_err!(
+                        "Event {} at version {} is not provable: {}",
+                        index,
+                        version,
+                        e
+                    )
+                })?;
+            let indexed_version = self
+                .get_txn_ver_by_seq_num(event.key(), event.sequence_number())
+                .map_err(|e| {
+                    format_err!(
+                        "Event {} at version {} is not indexed: {}",
+                        index,
+                        version,
+                        e
+                    )
+                })?;
+            ensure!(
+                indexed_version == version,
+                "Event {} at version {} is indexed at version {}.",
+                index,
+                version,
+                indexed_version,
+            );
+        }
+        Ok(())
+    }
+
+    /// Makes sure the events `db_batch` deletes one by one are exactly the ones whose by key and
+    /// by version index entries it deletes, failing otherwise. The events are read from the DB,
+    /// so this must run before `db_batch` is committed. Range deletions aren't looked at. Meant
+    /// for tests, pruning doesn't pay for these reads.
+    #[cfg(test)]
+    pub fn verify_event_deletions(&self, db_batch: &SchemaBatch) -> anyhow::Result<()> {
+        let mut expected_by_key = HashSet::new();
+        let mut expected_by_version = HashSet::new();
+        for (version, index) in db_batch.deleted_keys::<EventSchema>()? {
+            let event = self
+                .db
+                .get::<EventSchema>(&(version, index))?
+                .ok_or_else(|| {
+                    format_err!(
+                        "Event {} at version {} is deleted but not found.",
+                        index,
+                        version
+                    )
+                })?;
+            expected_by_key.insert((*event.key(), event.sequence_number()));
+            expected_by_version.insert((*event.key(), version, event.sequence_number()));
+        }
+        let by_key = db_batch
+            .deleted_keys::<EventByKeySchema>()?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        ensure!(
+            by_key == expected_by_key,
+            "Event by key deletions don't match the events deleted, {} vs {} entries.",
+            by_key.len(),
+            expected_by_key.len(),
+        );
+        let by_version = db_batch
+            .deleted_keys::<EventByVersionSchema>()?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        ensure!(
+            by_version == expected_by_version,
+            "Event by version deletions don't match the events deleted, {} vs {} entries.",
+            by_version.len(),
+            expected_by_version.len(),
+        );
         Ok(())
     }
 
@@ -416,35 +834,503 @@ impl EventStore {
         end: Version,
         db_batch: &mut SchemaBatch,
     ) -> anyhow::Result<()> {
-        let mut current_version = start;
-        for events in self.get_events_by_version_iter(start, (end - start) as usize)? {
-            for (current_index, event) in (events?).into_iter().enumerate() {
-                db_batch.delete::<EventByVersionSchema>(&(
-                    *event.key(),
-                    current_version as u64,
-                    event.sequence_number(),
-                ))?;
-                db_batch.delete::<EventByKeySchema>(&(*event.key(), event.sequence_number()))?;
-                db_batch.delete::<EventSchema>(&(current_version as u64, current_index as u64))?;
+        self.prune_events_with_options(start, end, &PruneEventsOptions::default(), db_batch)
+            .map(|_deleted_events| ())
+    }
+
+    /// Prunes the part of the event accumulator `prune_events()` does when pruning the range of
+    /// versions [start, end), i.e. lagging behind by the retain margin, for it to be pruned apart
+    /// from the events, see `PruneEventsOptions::with_accumulator`.
+    pub fn prune_lagging_event_accumulator(
+        &self,
+        start: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        let accumulator_start = start.saturating_sub(self.accumulator_retain_margin);
+        let accumulator_end = end.saturating_sub(self.accumulator_retain_margin);
+        if accumulator_end <= accumulator_start
+            || self
+                .event_presence
+                .lock()
+                .first_possibly_non_empty(accumulator_start, accumulator_end)
+                .is_none()
+        {
+            return Ok(());
+        }
+        self.prune_event_accumulator(accumulator_start, accumulator_end, db_batch)
+    }
+
+    /// Same as `prune_events()`, pruned as `options` tell, and returns the events deleted, see
+    /// `deleted_events()`.
+    pub fn prune_events_with_options(
+        &self,
+        start: Version,
+        end: Version,
+        options: &PruneEventsOptions,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<Vec<(Version, u64, ContractEvent)>> {
+        let end = if options.inclusive_end {
+            end.checked_add(1)
+                .ok_or_else(|| format_err!("Event pruning range ends beyond the last version."))?
+        } else {
+            end
+        };
+        if end < start {
+            warn!(
+                start = start,
+                end = end,
+                "Event pruning range ends before it starts, skipped."
+            );
+            return Ok(vec![]);
+        }
+        // The accumulator lags behind the events by the retain margin, the nodes kept last time
+        // being due now.
+        let accumulator_start = start.saturating_sub(self.accumulator_retain_margin);
+        let accumulator_end = end.saturating_sub(self.accumulator_retain_margin);
+        let presence_start = if options.with_accumulator {
+            accumulator_start
+        } else {
+            start
+        };
+        if self
+            .event_presence
+            .lock()
+            .first_possibly_non_empty(presence_start, end)
+            .is_none()
+        {
+            // Known to hold no events, the range has neither events nor accumulator to prune.
+            return Ok(vec![]);
+        }
+        let deleted_events = self.deleted_events(start, end, options)?;
+        if deleted_events.is_empty()
+            && !(options.with_accumulator
+                && self.has_event_accumulator_nodes(accumulator_start, accumulator_end)?)
+        {
+            // Nothing left to delete, e.g. the range was pruned already but the progress wasn't
+            // persisted, so no deletion of keys long gone is issued again.
+            return Ok(deleted_events);
+        }
+
+        // Encoding the deletions is CPU bound, so each sub-component gets its own batch to be
+        // filled in parallel.
+        let prune_by_version = || Self::delete_events_by_version(&deleted_events);
+        let prune_by_key = || Self::delete_events_by_key(&deleted_events);
+        let prune_schema = || Self::delete_events(&deleted_events);
+        let prune_accumulator = || -> Result<SchemaBatch> {
+            let mut batch = SchemaBatch::new();
+            if options.with_accumulator {
+                self.prune_event_accumulator(accumulator_start, accumulator_end, &mut batch)?;
+            }
+            Ok(batch)
+        };
+        let ((by_version_batch, by_key_batch), (schema_batch, accumulator_batch)) =
+            if let Some(yield_now) = self.sub_prune_yield {
+                let by_version_batch = prune_by_version();
+                yield_now();
+                let by_key_batch = prune_by_key();
+                yield_now();
+                let schema_batch = prune_schema();
+                yield_now();
+                (
+                    (by_version_batch, by_key_batch),
+                    (schema_batch, prune_accumulator()),
+                )
+            } else if options.parallel {
+                rayon::join(
+                    || rayon::join(prune_by_version, prune_by_key),
+                    || rayon::join(prune_schema, prune_accumulator),
+                )
+            } else {
+                (
+                    (prune_by_version(), prune_by_key()),
+                    (prune_schema(), prune_accumulator()),
+                )
+            };
+        // Merged in a fixed order, so the resulting batch doesn't depend on scheduling.
+        for batch in [
+            by_version_batch?,
+            by_key_batch?,
+            schema_batch?,
+            accumulator_batch?,
+        ] {
+            db_batch.merge(batch);
+        }
+        Ok(deleted_events)
+    }
+
+    /// Returns the events pruning the range of versions [start, end) as `options` tell deletes, in
+    /// the order of versions, i.e. its pruning candidates. The end is always exclusive here.
+    ///
+    /// If the latest event of each key is kept, it's left out of them, and stays once a later
+    /// event is emitted under its key until the range holding that event is pruned. The event
+    /// kept before `start` is then deleted, so only up to an event per key is ever left. The
+    /// events kept can still be read by version or by key below the least readable version, but
+    /// no longer come with a proof. Telling the latest events takes two seeks per key found in
+    /// the range. The events of genesis are never taken for kept ones, since genesis itself may
+    /// be kept, see `LedgerPruner::with_genesis_kept()`.
+    pub fn deleted_events(
+        &self,
+        start: Version,
+        end: Version,
+        options: &PruneEventsOptions,
+    ) -> Result<Vec<(Version, u64, ContractEvent)>> {
+        let mut candidates = self
+            .pruning_candidates(start, end)?
+            .collect::<Result<Vec<_>>>()?;
+        if !options.retain_keys.is_empty() {
+            candidates
+                .retain(|(_version, _index, event)| !options.retain_keys.contains(event.key()));
+        }
+        if !options.keep_latest_per_key {
+            return Ok(candidates);
+        }
+        let mut deleted_events = self.superseded_latest_events(start, &candidates)?;
+        deleted_events.extend(self.without_latest_per_key(candidates)?);
+        Ok(deleted_events)
+    }
+
+    /// Returns the events left before `start` as the latest of their key, which `candidates`, the
+    /// pruning candidates of a range from `start` on, supersede, in the order of versions. There
+    /// is at most one per key, the one right before its first candidate, since every earlier one
+    /// was superseded in turn.
+    fn superseded_latest_events(
+        &self,
+        start: Version,
+        candidates: &[(Version, u64, ContractEvent)],
+    ) -> Result<Vec<(Version, u64, ContractEvent)>> {
+        let mut min_seq_num_by_key: HashMap<EventKey, u64> = HashMap::new();
+        for (_version, _index, event) in candidates {
+            let min_seq_num = min_seq_num_by_key.entry(*event.key()).or_insert(u64::MAX);
+            *min_seq_num = min(*min_seq_num, event.sequence_number());
+        }
+        let mut iter = self
+            .db
+            .rev_iter::<EventByKeySchema>(self.prune_read_options.read_options())?;
+        let mut superseded_events = vec![];
+        for (event_key, min_seq_num) in min_seq_num_by_key {
+            let prev_seq_num = match min_seq_num.checked_sub(1) {
+                Some(prev_seq_num) => prev_seq_num,
+                None => continue,
+            };
+            iter.seek_for_prev(&(event_key, prev_seq_num))?;
+            if let Some(((key, _seq_num), (version, index))) = iter.next().transpose()? {
+                if key == event_key && version > 0 && version < start {
+                    let event = self.get_event_by_version_and_index(version, index)?;
+                    superseded_events.push((version, index, event));
+                }
             }
-            current_version += 1;
+        }
+        superseded_events.sort_unstable_by_key(|(version, index, _event)| (*version, *index));
+        Ok(superseded_events)
+    }
+
+    /// Same as `prune_events()`, but with the candidate events gathered from the high end of the
+    /// range downward, for evicting the newest versions first, and returns the events deleted,
+    /// newest first. The accumulator retain margin doesn't apply, since nothing is pruned below
+    /// the range. The events under one of `retain_keys` are kept, see
+    /// `PruneEventsOptions::retain_keys`.
+    pub fn prune_events_descending(
+        &self,
+        start: Version,
+        end: Version,
+        retain_keys: &HashSet<EventKey>,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<Vec<(Version, u64, ContractEvent)>> {
+        if end <= start {
+            return Ok(vec![]);
+        }
+        let mut candidates = self
+            .pruning_candidates_rev(start, end)?
+            .collect::<Result<Vec<_>>>()?;
+        candidates.retain(|(_version, _index, event)| !retain_keys.contains(event.key()));
+        for batch in [
+            Self::delete_events_by_version(&candidates)?,
+            Self::delete_events_by_key(&candidates)?,
+            Self::delete_events(&candidates)?,
+        ] {
+            db_batch.merge(batch);
         }
         self.prune_event_accumulator(start, end, db_batch)?;
+        Ok(candidates)
+    }
+
+    /// Prunes only the events of type `type_tag` in the range of versions [start, end), along
+    /// with their by key and by version index entries, while events of other types are kept.
+    /// Every candidate event is decoded to tell its type. The event accumulator is left intact
+    /// since the events left in each version still need it for their proofs.
+    pub fn prune_events_by_type(
+        &self,
+        type_tag: &TypeTag,
+        start: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        self.prune_events_matching(start, end, |event| event.type_tag() == type_tag, db_batch)
+    }
+
+    /// Prunes every event emitted under `event_key`, whatever its version, along with its by key
+    /// and by version index entries, e.g. once the resource emitting them is gone, and returns
+    /// the number of events pruned. Finding them takes a scan of the key's by key index entries.
+    /// The event accumulator is left intact, since it's shared by the events of all the keys in
+    /// each version, so none of the other keys' proofs are affected.
+    pub fn prune_all_events_for_key(
+        &self,
+        event_key: &EventKey,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<usize> {
+        let mut iter = self
+            .db
+            .iter::<EventByKeySchema>(self.prune_read_options.read_options())?;
+        iter.seek(&(*event_key, 0))?;
+        let mut num_events = 0;
+        for res in iter {
+            let ((key, _seq_num), (version, index)) = res?;
+            if key != *event_key {
+                break;
+            }
+            db_batch.delete::<EventSchema>(&(version, index))?;
+            num_events += 1;
+        }
+        if num_events > 0 {
+            db_batch.delete_range_inclusive::<EventByKeySchema>(
+                &(*event_key, 0),
+                &(*event_key, u64::MAX),
+            )?;
+            db_batch.delete_range_inclusive::<EventByVersionSchema>(
+                &(*event_key, 0, 0),
+                &(*event_key, Version::MAX, u64::MAX),
+            )?;
+        }
+        Ok(num_events)
+    }
+
+    /// Same as `prune_events_by_type()`, but pruning the events `predicate` holds for.
+    pub fn prune_events_matching(
+        &self,
+        start: Version,
+        end: Version,
+        predicate: impl Fn(&ContractEvent) -> bool,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        let mut candidates = self
+            .pruning_candidates(start, end)?
+            .collect::<Result<Vec<_>>>()?;
+        candidates.retain(|(_version, _index, event)| predicate(event));
+        for batch in [
+            Self::delete_events_by_version(&candidates)?,
+            Self::delete_events_by_key(&candidates)?,
+            Self::delete_events(&candidates)?,
+        ] {
+            db_batch.merge(batch);
+        }
         Ok(())
     }
 
+    /// Same as `pruning_candidates()`, but from the last event in the range of versions
+    /// [start, end) backward.
+    pub fn pruning_candidates_rev(
+        &self,
+        start: Version,
+        end: Version,
+    ) -> Result<impl Iterator<Item = Result<(Version, u64, ContractEvent)>> + '_> {
+        let mut iter = self
+            .db
+            .rev_iter::<EventSchema>(self.prune_read_options.read_options())?;
+        // Seeking by version alone lands on the last event of the versions before `end`.
+        iter.seek_for_prev(&end)?;
+        Ok(iter
+            .map(|res| res.map(|((version, index), event)| (version, index, event)))
+            .take_while(move |res| {
+                res.as_ref()
+                    .map_or(true, |(version, _, _)| *version >= start)
+            }))
+    }
+
+    /// Returns the events the pruner considers when pruning the range of versions [start, end),
+    /// each along with its version and index within the version. Events are read from the DB
+    /// lazily, as the iterator advances.
+    pub fn pruning_candidates(
+        &self,
+        start: Version,
+        end: Version,
+    ) -> Result<impl Iterator<Item = Result<(Version, u64, ContractEvent)>> + '_> {
+        // Ranges known to hold no events are skipped, going straight to the first bucket that
+        // might have some.
+        let first_version = self
+            .event_presence
+            .lock()
+            .first_possibly_non_empty(start, end);
+        let iter = match first_version {
+            Some(first_version) => {
+                let mut iter = self
+                    .db
+                    .iter::<EventSchema>(self.prune_read_options.read_options())?;
+                iter.seek(&first_version)?;
+                Some(iter)
+            }
+            None => None,
+        };
+        Ok(iter
+            .into_iter()
+            .flatten()
+            .map(|res| res.map(|((version, index), event)| (version, index, event)))
+            .take_while(move |res| res.as_ref().map_or(true, |(version, _, _)| *version < end)))
+    }
+
+    /// Returns the number of events in the range of versions [start, end), which is what pruning
+    /// the range deletes. Only the keys are read, so it's cheaper than going through
+    /// `pruning_candidates()`.
+    pub fn count_events_in_version_range(
+        &self,
+        start: Version,
+        end: Version,
+    ) -> anyhow::Result<usize> {
+        let first_version = match self
+            .event_presence
+            .lock()
+            .first_possibly_non_empty(start, end)
+        {
+            Some(first_version) => first_version,
+            None => return Ok(0),
+        };
+        let mut iter = self
+            .db
+            .iter::<EventSchema>(self.prune_read_options.read_options())?;
+        iter.seek(&first_version)?;
+        let mut count = 0;
+        while let Some((version, _index)) = iter.next_key().transpose()? {
+            if version >= end {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Stops tracking the presence of events before `version`, for a pruner that has moved past
+    /// it. The memory it takes is then bounded by the versions not pruned yet.
+    pub fn forget_event_presence_before(&self, version: Version) {
+        self.event_presence.lock().forget_before(version);
+    }
+
+    /// Leaves the latest event of each key, i.e. the one with the highest sequence number in the
+    /// DB, out of the pruning candidates if it's among them. Telling whether there is a later one
+    /// takes a seek per key.
+    fn without_latest_per_key(
+        &self,
+        mut candidates: Vec<(Version, u64, ContractEvent)>,
+    ) -> Result<Vec<(Version, u64, ContractEvent)>> {
+        let mut max_seq_num_by_key: HashMap<EventKey, u64> = HashMap::new();
+        for (_version, _index, event) in &candidates {
+            let max_seq_num = max_seq_num_by_key.entry(*event.key()).or_insert(0);
+            *max_seq_num = max(*max_seq_num, event.sequence_number());
+        }
+        let mut iter = self
+            .db
+            .iter::<EventByKeySchema>(self.prune_read_options.read_options())?;
+        let mut latest_events = HashSet::new();
+        for (event_key, max_seq_num) in max_seq_num_by_key {
+            let has_later_event = match max_seq_num.checked_add(1) {
+                Some(next_seq_num) => {
+                    iter.seek(&(event_key, next_seq_num))?;
+                    matches!(
+                        iter.next().transpose()?,
+                        Some(((key, _seq_num), _)) if key == event_key
+                    )
+                }
+                None => false,
+            };
+            if !has_later_event {
+                latest_events.insert((event_key, max_seq_num));
+            }
+        }
+        if !latest_events.is_empty() {
+            candidates.retain(|(_version, _index, event)| {
+                !latest_events.contains(&(*event.key(), event.sequence_number()))
+            });
+        }
+        Ok(candidates)
+    }
+
+    /// Deletes the event by version index entries of the pruning candidates.
+    fn delete_events_by_version(
+        candidates: &[(Version, u64, ContractEvent)],
+    ) -> Result<SchemaBatch> {
+        let mut batch = SchemaBatch::new();
+        for (version, _index, event) in candidates {
+            batch.delete::<EventByVersionSchema>(&(
+                *event.key(),
+                *version,
+                event.sequence_number(),
+            ))?;
+        }
+        Ok(batch)
+    }
+
+    /// Deletes the event by key index entries of the pruning candidates.
+    fn delete_events_by_key(candidates: &[(Version, u64, ContractEvent)]) -> Result<SchemaBatch> {
+        let mut batch = SchemaBatch::new();
+        for (_version, _index, event) in candidates {
+            batch.delete::<EventByKeySchema>(&(*event.key(), event.sequence_number()))?;
+        }
+        Ok(batch)
+    }
+
+    /// Deletes the pruning candidates themselves.
+    fn delete_events(candidates: &[(Version, u64, ContractEvent)]) -> Result<SchemaBatch> {
+        let mut batch = SchemaBatch::new();
+        for (version, index, _event) in candidates {
+            batch.delete::<EventSchema>(&(*version, *index))?;
+        }
+        Ok(batch)
+    }
+
+    /// Prunes the event by version index entries of `event_keys` in the range of versions
+    /// [begin, end), returning the highest version an entry is deleted at, `None` if none is.
+    /// Finding it takes a seek per key.
     pub fn prune_events_by_version(
         &self,
         event_keys: HashSet<EventKey>,
         begin: Version,
         end: Version,
         db_batch: &mut SchemaBatch,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Option<Version>> {
+        let mut max_version = None;
+        let ranges = self.deletion_order.ranges(begin, end);
         for event_key in event_keys {
-            db_batch
-                .delete_range::<EventByVersionSchema>(&(event_key, begin, 0), &(event_key, end, 0));
+            for (range_begin, range_end) in &ranges {
+                db_batch.delete_range::<EventByVersionSchema>(
+                    &(event_key, *range_begin, 0),
+                    &(event_key, *range_end, 0),
+                )?;
+            }
+            let mut iter = self
+                .db
+                .rev_iter::<EventByVersionSchema>(self.prune_read_options.read_options())?;
+            iter.seek_for_prev(&(event_key, end, 0))?;
+            for item in iter {
+                let ((key, version, _seq_num), _index) = item?;
+                if key != event_key || version < begin {
+                    break;
+                }
+                // Only the entry with sequence number 0 at `end` itself may come first.
+                if version < end {
+                    max_version = max(max_version, Some(version));
+                    break;
+                }
+            }
         }
-        Ok(())
+        Ok(max_version)
+    }
+
+    /// Commits the deletions gathered in `db_batch`, for pruning outside of the regular rounds,
+    /// whose deletions the pruner commits along with its progress.
+    pub fn write_pruning_batch(&self, db_batch: SchemaBatch) -> Result<()> {
+        if db_batch.is_empty() {
+            return Ok(());
+        }
+        self.db.write_schemas(db_batch)
     }
 
     /// Prunes the event schema for a range of version in [begin, end)
@@ -454,7 +1340,68 @@ impl EventStore {
         end: Version,
         db_batch: &mut SchemaBatch,
     ) -> anyhow::Result<()> {
-        db_batch.delete_range::<EventSchema>(&(begin, 0_u64), &(end, 0_u64))
+        for (range_begin, range_end) in self.deletion_order.ranges(begin, end) {
+            db_batch.delete_range::<EventSchema>(&(range_begin, 0_u64), &(range_end, 0_u64))?;
+        }
+        Ok(())
+    }
+
+    /// Prunes whatever is left of the events before `least_readable_version`, including the
+    /// event by key and event by version indices that no longer have their event. These can only
+    /// be found by scanning the whole index schemas, which makes this rather costly. The events
+    /// under one of `retain_keys` are kept along with their indices, see
+    /// `PruneEventsOptions::retain_keys`.
+    pub fn prune_dangling_events(
+        &self,
+        least_readable_version: Version,
+        retain_keys: &HashSet<EventKey>,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        if retain_keys.is_empty() {
+            self.prune_event_schema(0, least_readable_version, db_batch)?;
+        } else {
+            let mut iter = self
+                .db
+                .iter::<EventSchema>(self.prune_read_options.read_options())?;
+            iter.seek_to_first();
+            for res in iter {
+                let ((version, index), event) = res?;
+                if version >= least_readable_version {
+                    break;
+                }
+                if !retain_keys.contains(event.key()) {
+                    db_batch.delete::<EventSchema>(&(version, index))?;
+                }
+            }
+        }
+        self.prune_event_accumulator(
+            0,
+            least_readable_version.saturating_sub(self.accumulator_retain_margin),
+            db_batch,
+        )?;
+
+        let mut iter = self
+            .db
+            .iter::<EventByKeySchema>(self.prune_read_options.read_options())?;
+        iter.seek_to_first();
+        for res in iter {
+            let ((event_key, seq_num), (version, _index)) = res?;
+            if version < least_readable_version && !retain_keys.contains(&event_key) {
+                db_batch.delete::<EventByKeySchema>(&(event_key, seq_num))?;
+            }
+        }
+
+        let mut iter = self
+            .db
+            .iter::<EventByVersionSchema>(self.prune_read_options.read_options())?;
+        iter.seek_to_first();
+        for res in iter {
+            let ((event_key, version, seq_num), _index) = res?;
+            if version < least_readable_version && !retain_keys.contains(&event_key) {
+                db_batch.delete::<EventByVersionSchema>(&(event_key, version, seq_num))?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -463,16 +1410,37 @@ type Accumulator<'a> = MerkleAccumulator<EventHashReader<'a>, EventAccumulatorHa
 struct EventHashReader<'a> {
     store: &'a EventStore,
     version: Version,
+    /// Nodes read as if they were gone, see `without_deleted()`.
+    deleted_positions: HashSet<Position>,
 }
 
 impl<'a> EventHashReader<'a> {
     fn new(store: &'a EventStore, version: Version) -> Self {
-        Self { store, version }
+        Self {
+            store,
+            version,
+            deleted_positions: HashSet::new(),
+        }
+    }
+
+    /// Reads the nodes as they are once `db_batch` is committed, leaving out the ones it deletes
+    /// one by one.
+    fn without_deleted(mut self, db_batch: &SchemaBatch) -> Result<Self> {
+        self.deleted_positions = db_batch
+            .deleted_keys::<EventAccumulatorSchema>()?
+            .into_iter()
+            .filter(|(version, _position)| *version == self.version)
+            .map(|(_version, position)| position)
+            .collect();
+        Ok(self)
     }
 }
 
 impl<'a> HashReader for EventHashReader<'a> {
     fn get(&self, position: Position) -> Result<HashValue> {
+        if self.deleted_positions.contains(&position) {
+            return Err(format_err!("Hash at position {:?} is deleted.", position));
+        }
         self.store
             .db
             .get::<EventAccumulatorSchema>(&(self.version, position))?
@@ -20,7 +20,10 @@ use proptest::{
     strategy::Union,
 };
 use rand::Rng;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 fn save(store: &EventStore, version: Version, events: &[ContractEvent]) -> HashValue {
     let mut cs = ChangeSet::new();
@@ -57,6 +60,548 @@ fn test_error_on_get_from_empty() {
         .is_err());
 }
 
+#[test]
+fn test_sequence_range_by_event_keys() {
+    let keys: Vec<EventKey> = (0..3)
+        .map(|salt| EventKey::new_from_address(&AccountAddress::random(), salt))
+        .collect();
+    // Events of the keys interleaved, with increasing sequence numbers within each key.
+    let mut next_seq_nums = vec![5, 0, 10];
+    let events: Vec<ContractEvent> = [0, 1, 0, 2, 2, 1, 0, 2, 1, 1]
+        .iter()
+        .map(|&i| {
+            let event = ContractEvent::new(keys[i], next_seq_nums[i], TypeTag::Bool, vec![]);
+            next_seq_nums[i] += 1;
+            event
+        })
+        .collect();
+
+    // Two passes: collect the keys first, then the range of each key.
+    let expected: HashMap<EventKey, (u64, u64)> = events
+        .iter()
+        .map(|event| *event.key())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|key| {
+            let seq_nums: Vec<u64> = events
+                .iter()
+                .filter(|event| *event.key() == key)
+                .map(ContractEvent::sequence_number)
+                .collect();
+            (key, (seq_nums[0], *seq_nums.last().unwrap()))
+        })
+        .collect();
+
+    let sequence_range_by_event_keys = EventStore::sequence_range_by_event_keys(&events);
+    assert_eq!(sequence_range_by_event_keys, expected);
+    assert_eq!(sequence_range_by_event_keys[&keys[0]], (5, 7));
+    assert_eq!(sequence_range_by_event_keys[&keys[1]], (0, 3));
+    assert_eq!(sequence_range_by_event_keys[&keys[2]], (10, 12));
+}
+
+#[test]
+fn test_sequence_range_by_event_keys_out_of_order() {
+    let keys: Vec<EventKey> = (0..2)
+        .map(|salt| EventKey::new_from_address(&AccountAddress::random(), salt))
+        .collect();
+    // Neither the first nor the last event of a key holds its min or max sequence number.
+    let events: Vec<ContractEvent> = [
+        (0, 4),
+        (1, 8),
+        (0, 2),
+        (0, 9),
+        (1, 3),
+        (1, 11),
+        (0, 6),
+        (1, 5),
+    ]
+    .iter()
+    .map(|&(i, seq_num)| ContractEvent::new(keys[i], seq_num, TypeTag::Bool, vec![]))
+    .collect();
+
+    let sequence_range_by_event_keys = EventStore::sequence_range_by_event_keys(&events);
+    assert_eq!(sequence_range_by_event_keys.len(), 2);
+    assert_eq!(sequence_range_by_event_keys[&keys[0]], (2, 9));
+    assert_eq!(sequence_range_by_event_keys[&keys[1]], (3, 11));
+}
+
+#[test]
+fn test_accumulator_retain_margin() {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = EventStore::new(Arc::clone(&db.db)).with_accumulator_retain_margin(3);
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 10;
+    let events_per_version = 4;
+    let mut event_hashes = vec![];
+    let mut root_hashes = vec![];
+    for version in 0..num_versions {
+        let events: Vec<ContractEvent> = (0..events_per_version)
+            .map(|i| {
+                ContractEvent::new(key, version * events_per_version + i, TypeTag::Bool, vec![])
+            })
+            .collect();
+        event_hashes.push(events.iter().map(CryptoHash::hash).collect::<Vec<_>>());
+        root_hashes.push(save(&store, version, &events));
+    }
+    let verify_range_proof = |version: Version| -> Result<()> {
+        let hashes = &event_hashes[version as usize][1..3];
+        store
+            .get_event_range_proof(version, events_per_version, Some(1), 2)?
+            .verify(root_hashes[version as usize], Some(1), hashes)
+    };
+
+    let mut db_batch = SchemaBatch::new();
+    store.prune_events(0, 5, &mut db_batch).unwrap();
+    db.db.write_schemas(db_batch).unwrap();
+
+    // The events are all pruned, but proofs can still be served within the margin.
+    for version in 0..5 {
+        assert!(store.get_events_by_version(version).unwrap().is_empty());
+        assert_eq!(verify_range_proof(version).is_ok(), version >= 2);
+    }
+    for version in 5..num_versions {
+        verify_range_proof(version).unwrap();
+    }
+
+    // The accumulator kept by the last round is pruned by the next one.
+    let mut db_batch = SchemaBatch::new();
+    store.prune_events(5, 7, &mut db_batch).unwrap();
+    db.db.write_schemas(db_batch).unwrap();
+    for version in 0..num_versions {
+        assert_eq!(verify_range_proof(version).is_ok(), version >= 4);
+    }
+}
+
+#[test]
+fn test_yields_between_sub_prunes() {
+    static NUM_YIELDS: AtomicUsize = AtomicUsize::new(0);
+    fn count_yield() {
+        NUM_YIELDS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = EventStore::new(Arc::clone(&db.db)).with_sub_prune_yield(count_yield);
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    for version in 0..4 {
+        save(
+            &store,
+            version,
+            &[ContractEvent::new(key, version, TypeTag::Bool, vec![])],
+        );
+    }
+
+    let mut db_batch = SchemaBatch::new();
+    store.prune_events(0, 2, &mut db_batch).unwrap();
+    db.db.write_schemas(db_batch).unwrap();
+    // Once between each of the four sub-components.
+    assert_eq!(NUM_YIELDS.load(Ordering::Relaxed), 3);
+    for version in 0..4 {
+        assert_eq!(
+            store.get_events_by_version(version).unwrap().is_empty(),
+            version < 2
+        );
+    }
+
+    // Nothing left to prune, nothing to yield between.
+    let mut db_batch = SchemaBatch::new();
+    store.prune_events(0, 2, &mut db_batch).unwrap();
+    assert_eq!(NUM_YIELDS.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn test_prune_events_in_range() {
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 6;
+    let boundary_version = 3;
+    for inclusive_end in [false, true] {
+        let tmp_dir = TempPath::new();
+        let db = AptosDB::new_for_test(&tmp_dir);
+        let store = &db.event_store;
+        for version in 0..num_versions {
+            save(
+                store,
+                version,
+                &[ContractEvent::new(key, version, TypeTag::Bool, vec![])],
+            );
+        }
+
+        let options = PruneEventsOptions {
+            inclusive_end,
+            ..PruneEventsOptions::default()
+        };
+        let mut db_batch = SchemaBatch::new();
+        store
+            .prune_events_with_options(0, boundary_version, &options, &mut db_batch)
+            .unwrap();
+        store.db.write_schemas(db_batch).unwrap();
+
+        // The events of the boundary version are pruned only if the end is inclusive.
+        for version in 0..num_versions {
+            let pruned =
+                version < boundary_version || (version == boundary_version && inclusive_end);
+            assert_eq!(
+                store.get_events_by_version(version).unwrap().is_empty(),
+                pruned
+            );
+        }
+    }
+
+    // The end can't be made exclusive past the last version.
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    assert!(db
+        .event_store
+        .prune_events_with_options(
+            0,
+            Version::MAX,
+            &PruneEventsOptions {
+                inclusive_end: true,
+                ..PruneEventsOptions::default()
+            },
+            &mut SchemaBatch::new()
+        )
+        .is_err());
+}
+
+#[test]
+fn test_prune_events_in_parallel() {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = &db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    for version in 0..10 {
+        save(
+            store,
+            version,
+            &[
+                ContractEvent::new(key, 2 * version, TypeTag::Bool, vec![]),
+                ContractEvent::new(key, 2 * version + 1, TypeTag::Bool, vec![]),
+            ],
+        );
+    }
+    let prune = |parallel| {
+        let options = PruneEventsOptions {
+            parallel,
+            ..PruneEventsOptions::default()
+        };
+        let mut db_batch = SchemaBatch::new();
+        store
+            .prune_events_with_options(0, 5, &options, &mut db_batch)
+            .unwrap();
+        db_batch
+    };
+
+    // Serial unless opted in, with the same deletions either way.
+    assert!(!PruneEventsOptions::default().parallel);
+    let (serial_batch, parallel_batch) = (prune(false), prune(true));
+    assert_eq!(
+        serial_batch.len_by_column_family(),
+        parallel_batch.len_by_column_family()
+    );
+    assert_eq!(
+        serial_batch.deleted_keys::<EventSchema>().unwrap(),
+        parallel_batch.deleted_keys::<EventSchema>().unwrap()
+    );
+    assert_eq!(
+        serial_batch.deleted_keys::<EventSchema>().unwrap().len(),
+        10
+    );
+}
+
+#[test]
+fn test_pruning_candidates() {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = &db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    // Two events at every version but version 2.
+    let mut events = vec![];
+    let mut seq_num = 0;
+    for version in 0..5 {
+        let events_for_version: Vec<_> = (0..if version == 2 { 0 } else { 2 })
+            .map(|_| {
+                seq_num += 1;
+                ContractEvent::new(key, seq_num - 1, TypeTag::Bool, vec![])
+            })
+            .collect();
+        save(store, version, &events_for_version);
+        for (index, event) in events_for_version.into_iter().enumerate() {
+            events.push((version, index as u64, event));
+        }
+    }
+
+    let candidates: Vec<_> = store
+        .pruning_candidates(1, 4)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(candidates, events[2..6]);
+    // Nothing beyond what's taken has to be read.
+    let candidates: Vec<_> = store
+        .pruning_candidates(0, 5)
+        .unwrap()
+        .take(3)
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(candidates, events[..3]);
+    assert_eq!(store.pruning_candidates(3, 3).unwrap().count(), 0);
+
+    // The reverse iteration yields the same events, newest first.
+    let candidates: Vec<_> = store
+        .pruning_candidates_rev(1, 4)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(
+        candidates,
+        events[2..6].iter().rev().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(store.pruning_candidates_rev(3, 3).unwrap().count(), 0);
+}
+
+#[test]
+fn test_count_events_in_version_range() {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = &db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    // `version` events at every version.
+    let mut seq_num = 0;
+    for version in 0..5 {
+        let events: Vec<_> = (0..version)
+            .map(|_| {
+                seq_num += 1;
+                ContractEvent::new(key, seq_num - 1, TypeTag::Bool, vec![])
+            })
+            .collect();
+        save(store, version, &events);
+    }
+
+    assert_eq!(store.count_events_in_version_range(0, 5).unwrap(), 10);
+    assert_eq!(store.count_events_in_version_range(2, 4).unwrap(), 5);
+    assert_eq!(store.count_events_in_version_range(0, 1).unwrap(), 0);
+    assert_eq!(store.count_events_in_version_range(4, 100).unwrap(), 4);
+    // Empty ranges hold nothing.
+    assert_eq!(store.count_events_in_version_range(3, 3).unwrap(), 0);
+    assert_eq!(store.count_events_in_version_range(4, 2).unwrap(), 0);
+}
+
+#[test]
+fn test_prune_events_by_version_sparse() {
+    let tmp_dir = TempPath::new();
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = &db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let other_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    // Events at versions 2, 5 and 9 only.
+    for (seq_num, version) in [2, 5, 9].iter().enumerate() {
+        save(
+            store,
+            *version,
+            &[ContractEvent::new(
+                key,
+                seq_num as u64,
+                TypeTag::Bool,
+                vec![],
+            )],
+        );
+    }
+    let highest_pruned = |keys: &[EventKey], begin: Version, end: Version| {
+        store
+            .prune_events_by_version(
+                keys.iter().cloned().collect(),
+                begin,
+                end,
+                &mut SchemaBatch::new(),
+            )
+            .unwrap()
+    };
+
+    assert_eq!(highest_pruned(&[key, other_key], 0, 8), Some(5));
+    assert_eq!(highest_pruned(&[key], 0, 100), Some(9));
+    assert_eq!(highest_pruned(&[key], 3, 5), None);
+    // The entry right at the end of the range, with sequence number 0, is beyond it.
+    assert_eq!(highest_pruned(&[key], 0, 2), None);
+    assert_eq!(highest_pruned(&[key], 5, 6), Some(5));
+    assert_eq!(highest_pruned(&[key], 6, 9), None);
+    assert_eq!(highest_pruned(&[other_key], 0, 100), None);
+
+    let mut db_batch = SchemaBatch::new();
+    store
+        .prune_events_by_version(HashSet::from([key]), 0, 8, &mut db_batch)
+        .unwrap();
+    store.db.write_schemas(db_batch).unwrap();
+    for (version, seq_num, is_pruned) in [(2, 0, true), (5, 1, true), (9, 2, false)] {
+        assert_eq!(
+            store
+                .db
+                .get::<EventByVersionSchema>(&(key, version, seq_num))
+                .unwrap()
+                .is_none(),
+            is_pruned
+        );
+    }
+}
+
+#[test]
+fn test_event_presence() {
+    let bucket_size = EVENT_PRESENCE_BUCKET_SIZE;
+    let mut presence = EventPresence::new(bucket_size);
+    presence.mark_non_empty(3 * bucket_size + 1);
+    presence.mark_non_empty(5 * bucket_size);
+
+    // Nothing is known before `known_since`.
+    assert_eq!(presence.first_possibly_non_empty(0, 10), Some(0));
+    assert_eq!(presence.first_possibly_non_empty(5, 5), None);
+    // Beyond it, empty buckets are skipped.
+    assert_eq!(
+        presence.first_possibly_non_empty(bucket_size, 10 * bucket_size),
+        Some(3 * bucket_size)
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(3 * bucket_size + 2, 10 * bucket_size),
+        Some(3 * bucket_size + 2)
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(4 * bucket_size, 10 * bucket_size),
+        Some(5 * bucket_size)
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(bucket_size, 3 * bucket_size),
+        None
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(5 * bucket_size + 1, 10 * bucket_size),
+        Some(5 * bucket_size + 1)
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(6 * bucket_size, 10 * bucket_size),
+        None
+    );
+
+    // Forgotten buckets are unknown again.
+    presence.forget_before(4 * bucket_size + 1);
+    assert_eq!(presence.non_empty_buckets.len(), 1);
+    assert_eq!(
+        presence.first_possibly_non_empty(bucket_size, 2 * bucket_size),
+        Some(bucket_size)
+    );
+    assert_eq!(
+        presence.first_possibly_non_empty(4 * bucket_size, 10 * bucket_size),
+        Some(5 * bucket_size)
+    );
+}
+
+#[test]
+fn test_pruning_candidates_after_reopen() {
+    let tmp_dir = TempPath::new();
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 3 * EVENT_PRESENCE_BUCKET_SIZE;
+    let sparse_versions = [1, EVENT_PRESENCE_BUCKET_SIZE + 5, num_versions - 1];
+    let events: Vec<_> = sparse_versions
+        .iter()
+        .enumerate()
+        .map(|(seq_num, version)| {
+            (
+                *version,
+                0,
+                ContractEvent::new(key, seq_num as u64, TypeTag::Bool, vec![]),
+            )
+        })
+        .collect();
+    {
+        let db = AptosDB::new_for_test(&tmp_dir);
+        for (version, _index, event) in &events {
+            save(&db.event_store, *version, &[event.clone()]);
+        }
+        let candidates: Vec<_> = db
+            .event_store
+            .pruning_candidates(0, num_versions)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(candidates, events);
+    }
+
+    // Events written before the store was opened are still found, and new ones are tracked.
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let new_event = (
+        num_versions + EVENT_PRESENCE_BUCKET_SIZE,
+        0,
+        ContractEvent::new(key, events.len() as u64, TypeTag::Bool, vec![]),
+    );
+    save(&db.event_store, new_event.0, &[new_event.2.clone()]);
+    let candidates: Vec<_> = db
+        .event_store
+        .pruning_candidates(0, new_event.0 + 1)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(candidates[..events.len()], events[..]);
+    assert_eq!(candidates[events.len()], new_event);
+}
+
+#[test]
+fn test_prune_events_after_reopen() {
+    let tmp_dir = TempPath::new();
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let old_versions = [1, EVENT_PRESENCE_BUCKET_SIZE + 5];
+    let new_versions = [
+        3 * EVENT_PRESENCE_BUCKET_SIZE,
+        5 * EVENT_PRESENCE_BUCKET_SIZE + 1,
+    ];
+    {
+        let db = AptosDB::new_for_test(&tmp_dir);
+        for (seq_num, version) in old_versions.iter().enumerate() {
+            save(
+                &db.event_store,
+                *version,
+                &[ContractEvent::new(
+                    key,
+                    seq_num as u64,
+                    TypeTag::Bool,
+                    vec![],
+                )],
+            );
+        }
+    }
+
+    // Both the events written before the store was reopened and those written since are pruned,
+    // the empty buckets in between being skipped.
+    let db = AptosDB::new_for_test(&tmp_dir);
+    let store = &db.event_store;
+    for (seq_num, version) in new_versions.iter().enumerate() {
+        save(
+            store,
+            *version,
+            &[ContractEvent::new(
+                key,
+                (old_versions.len() + seq_num) as u64,
+                TypeTag::Bool,
+                vec![],
+            )],
+        );
+    }
+    let end = new_versions[1];
+    let mut db_batch = SchemaBatch::new();
+    store.prune_events(0, end, &mut db_batch).unwrap();
+    db.db.write_schemas(db_batch).unwrap();
+    for version in old_versions.iter().chain(&new_versions[..1]) {
+        assert!(store.get_events_by_version(*version).unwrap().is_empty());
+    }
+    assert_eq!(store.get_events_by_version(end).unwrap().len(), 1);
+    assert_eq!(
+        store
+            .pruning_candidates(0, end)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap(),
+        vec![]
+    );
+}
+
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(10))]
 
@@ -199,7 +744,7 @@ fn test_index_get_impl(event_batches: Vec<Vec<ContractEvent>>) {
 
     assert_eq!(
         store
-            .get_events_by_version_iter(0, event_batches.len())
+            .get_events_by_version_iter(0, event_batches.len(), ReadOptions::default())
             .unwrap()
             .collect::<Result<Vec<_>>>()
             .unwrap(),
@@ -23,8 +23,10 @@ use aptos_types::{
     epoch_state::EpochState,
     ledger_info::LedgerInfoWithSignatures,
     proof::{
-        definition::LeafCount, position::Position, AccumulatorConsistencyProof,
-        TransactionAccumulatorProof, TransactionAccumulatorRangeProof, TransactionInfoWithProof,
+        definition::LeafCount,
+        position::{FrozenSubTreeIterator, Position},
+        AccumulatorConsistencyProof, TransactionAccumulatorProof, TransactionAccumulatorRangeProof,
+        TransactionInfoWithProof,
     },
     transaction::{TransactionInfo, Version},
 };
@@ -94,6 +96,22 @@ impl LedgerStore {
         })
     }
 
+    /// Returns the greatest version ending an epoch below `version`, `None` if no epoch ends
+    /// before it.
+    pub fn get_epoch_ending_version_before(&self, version: Version) -> Result<Option<Version>> {
+        if version == 0 {
+            return Ok(None);
+        }
+        let mut iter = self
+            .db
+            .iter::<EpochByVersionSchema>(ReadOptions::default())?;
+        iter.seek_for_prev(&(version - 1))?;
+        Ok(iter
+            .next()
+            .transpose()?
+            .map(|(epoch_end_version, _epoch)| epoch_end_version))
+    }
+
     /// Gets ledger info at specified version and ensures it's an epoch ending.
     pub fn get_epoch_ending_ledger_info(
         &self,
@@ -356,6 +374,57 @@ impl LedgerStore {
         Accumulator::get_root_hash(self, version + 1)
     }
 
+    /// Prunes the transaction accumulator nodes only the versions in the range [begin, end) need.
+    /// The frozen subtree roots of the first `end` leaves are kept, since the proofs of the
+    /// versions from `end` on have them as siblings and the root hashes from there on are computed
+    /// from them, so everything is still provable from `end` on, while the consistency proofs from
+    /// anything before can no longer be served. The frozen subtree roots of the first `begin`
+    /// leaves are expected to be all that was left of the nodes below `begin`.
+    pub fn prune_transaction_accumulator(
+        &self,
+        begin: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> Result<()> {
+        if begin >= end {
+            return Ok(());
+        }
+        // The nodes whose subtree only has leaves among the first `num_leaves` come first in
+        // postorder, which the schema is keyed by, so they are deleted by ranges in between the
+        // ones kept.
+        let num_frozen_nodes =
+            |num_leaves: LeafCount| 2 * num_leaves - num_leaves.count_ones() as u64;
+        let kept: Vec<Position> = FrozenSubTreeIterator::new(end).collect();
+        let mut range_start = num_frozen_nodes(begin);
+        let range_end = num_frozen_nodes(end);
+        for kept_index in kept
+            .iter()
+            .map(|position| position.to_postorder_index())
+            .filter(|kept_index| *kept_index >= range_start)
+        {
+            if range_start < kept_index {
+                db_batch.delete_range::<TransactionAccumulatorSchema>(
+                    &Position::from_postorder_index(range_start)?,
+                    &Position::from_postorder_index(kept_index)?,
+                )?;
+            }
+            range_start = kept_index + 1;
+        }
+        if range_start < range_end {
+            db_batch.delete_range::<TransactionAccumulatorSchema>(
+                &Position::from_postorder_index(range_start)?,
+                &Position::from_postorder_index(range_end)?,
+            )?;
+        }
+        // The roots kept below `begin` are left behind once merged into bigger subtrees.
+        for position in FrozenSubTreeIterator::new(begin) {
+            if !kept.contains(&position) {
+                db_batch.delete::<TransactionAccumulatorSchema>(&position)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Prune the ledger counters stored in DB in the range [being, end)
     pub fn prune_ledger_counters(
         &self,
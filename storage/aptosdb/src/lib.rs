@@ -12,6 +12,9 @@
 // Used in this and other crates for testing.
 #[cfg(any(test, feature = "fuzzing"))]
 pub mod test_helper;
+// Used in other crates for testing what drives pruners, without a DB.
+#[cfg(any(test, feature = "testing"))]
+pub use pruner::{mock_pruner, DBPruner};
 
 pub mod backup;
 pub mod errors;
@@ -41,7 +44,7 @@ use crate::{
         API_LATENCY_SECONDS, COMMITTED_TXNS, LATEST_TXN_VERSION, LEDGER_VERSION, NEXT_BLOCK_EPOCH,
         OTHER_TIMERS_SECONDS, ROCKSDB_PROPERTIES, STATE_ITEM_COUNT,
     },
-    pruner::{utils, Pruner},
+    pruner::{pruner_manager::PrunerManager, utils, Pruner},
     schema::*,
     state_store::StateStore,
     system_store::SystemStore,
@@ -80,10 +83,10 @@ use itertools::zip_eq;
 use once_cell::sync::Lazy;
 use schemadb::{ColumnFamilyName, Options, SchemaBatch, DB, DEFAULT_CF_NAME};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     iter::Iterator,
     path::Path,
-    sync::{mpsc, Arc},
+    sync::{atomic::AtomicBool, mpsc, Arc},
     thread,
     thread::JoinHandle,
     time::{Duration, Instant},
@@ -154,7 +157,7 @@ fn update_rocksdb_properties(db: &DB) -> Result<()> {
     let _timer = OTHER_TIMERS_SECONDS
         .with_label_values(&["update_rocksdb_properties"])
         .start_timer();
-    for cf_name in AptosDB::column_families() {
+    for &cf_name in db.column_families() {
         for (rockdb_property_name, aptos_rocksdb_property_name) in &*ROCKSDB_PROPERTY_MAP {
             ROCKSDB_PROPERTIES
                 .with_label_values(&[cf_name, aptos_rocksdb_property_name])
@@ -226,6 +229,7 @@ impl AptosDB {
     fn column_families() -> Vec<ColumnFamilyName> {
         vec![
             /* LedgerInfo CF = */ DEFAULT_CF_NAME,
+            DB_METADATA_CF_NAME,
             EPOCH_BY_VERSION_CF_NAME,
             EVENT_ACCUMULATOR_CF_NAME,
             EVENT_BY_KEY_CF_NAME,
@@ -244,10 +248,44 @@ impl AptosDB {
         ]
     }
 
-    fn new_with_db(db: DB, storage_pruner_config: StoragePrunerConfig) -> Self {
+    /// The column families to open the existing DB at `path` with, without creating any. The
+    /// `DB_METADATA_CF_NAME` one is left out of a DB created before it was added, since only the
+    /// pruners read it and none run on such an open. It's created on the next read-write open.
+    fn existing_column_families(path: &Path, db_opts: &Options) -> Result<Vec<ColumnFamilyName>> {
+        let existing: HashSet<_> = DB::list_column_families(path, db_opts)?
+            .into_iter()
+            .collect();
+        Ok(Self::column_families()
+            .into_iter()
+            .filter(|cf_name| *cf_name != DB_METADATA_CF_NAME || existing.contains(*cf_name))
+            .collect())
+    }
+
+    /// The column families of the archive DB the ledger pruner moves what it prunes into.
+    fn archive_column_families() -> Vec<ColumnFamilyName> {
+        vec![
+            DEFAULT_CF_NAME,
+            EVENT_BY_KEY_CF_NAME,
+            EVENT_BY_VERSION_CF_NAME,
+            EVENT_CF_NAME,
+            WRITE_SET_CF_NAME,
+        ]
+    }
+
+    fn new_with_db(
+        db: DB,
+        storage_pruner_config: StoragePrunerConfig,
+        archive_db: Option<DB>,
+    ) -> Self {
         let db = Arc::new(db);
         let transaction_store = Arc::new(TransactionStore::new(Arc::clone(&db)));
-        let event_store = Arc::new(EventStore::new(Arc::clone(&db)));
+        let event_store = Arc::new(
+            EventStore::new(Arc::clone(&db))
+                .with_accumulator_retain_margin(
+                    storage_pruner_config.event_accumulator_retain_margin,
+                )
+                .with_deletion_order(storage_pruner_config.event_deletion_order.into()),
+        );
         let ledger_store = Arc::new(LedgerStore::new(Arc::clone(&db)));
         let system_store = Arc::new(SystemStore::new(Arc::clone(&db)));
 
@@ -266,6 +304,7 @@ impl AptosDB {
                     transaction_store,
                     ledger_store,
                     event_store,
+                    archive_db.map(Arc::new),
                 )),
             },
             _rocksdb_property_reporter: RocksdbPropertyReporter::new(Arc::clone(&db)),
@@ -292,7 +331,7 @@ impl AptosDB {
             DB::open_readonly(
                 path.clone(),
                 "aptosdb_ro",
-                Self::column_families(),
+                Self::existing_column_families(&path, &rocksdb_opts)?,
                 &rocksdb_opts,
             )?
         } else {
@@ -306,7 +345,18 @@ impl AptosDB {
             )?
         };
 
-        let ret = Self::new_with_db(db, storage_pruner_config);
+        let archive_db = if storage_pruner_config.archive_pruned_ledger {
+            Some(DB::open(
+                db_root_path.as_ref().join("ledger_archive"),
+                "ledger_archive",
+                Self::archive_column_families(),
+                &rocksdb_opts,
+            )?)
+        } else {
+            None
+        };
+
+        let ret = Self::new_with_db(db, storage_pruner_config, archive_db);
         info!(
             path = path,
             time_ms = %instant.elapsed().as_millis(),
@@ -328,13 +378,14 @@ impl AptosDB {
 
         Ok(Self::new_with_db(
             DB::open_as_secondary(
-                primary_path,
+                primary_path.clone(),
                 secondary_path,
                 "aptosdb_sec",
-                Self::column_families(),
+                Self::existing_column_families(&primary_path, &rocksdb_opts)?,
                 &rocksdb_opts,
             )?,
             NO_OP_STORAGE_PRUNER_CONFIG,
+            None, /* archive_db */
         ))
     }
 
@@ -684,6 +735,34 @@ impl AptosDB {
             pruner.maybe_wake_pruner(latest_version)
         }
     }
+
+    /// Pauses the pruning of all the stores, if enabled, see `Pruner::pause()`.
+    pub fn pause_pruning(&self) {
+        if let Some(pruner) = self.pruner.as_ref() {
+            pruner.pause()
+        }
+    }
+
+    /// Resumes the pruning paused by `pause_pruning()`.
+    pub fn resume_pruning(&self) {
+        if let Some(pruner) = self.pruner.as_ref() {
+            pruner.resume()
+        }
+    }
+
+    /// True if the pruning is paused by `pause_pruning()`.
+    pub fn is_pruning_paused(&self) -> bool {
+        self.pruner.as_ref().map_or(false, Pruner::is_paused)
+    }
+
+    /// Returns the least readable version across all the stores pruned, if pruning is enabled,
+    /// which reads spanning the stores, e.g. of a transaction along with its state, are to be
+    /// gated by rather than by the first version of any one store.
+    pub fn get_combined_least_readable_version(&self) -> Option<Version> {
+        self.pruner
+            .as_ref()
+            .map(Pruner::get_combined_least_readable_version)
+    }
 }
 
 impl DbReader for AptosDB {
@@ -1437,17 +1516,27 @@ impl DbWriter for AptosDB {
                 self.transaction_store.clone(),
                 self.ledger_store.clone(),
                 self.event_store.clone(),
+                Arc::new(AtomicBool::new(false)), /* stop_flag */
+                NO_OP_STORAGE_PRUNER_CONFIG,
+                None,  /* archive_db */
+                false, /* never_prune_genesis */
             );
 
             // Execute each pruner to clean up the genesis state
             let target_version = 1; // The genesis version is 0. Delete [0,1) (exclusive).
-            let max_version = 1; // We should only really be pruning at a single version.
+            let max_versions_per_round = db_pruners.len() as Version; // A single version each.
+            let mut pruner_manager = PrunerManager::new(db_pruners, max_versions_per_round);
             let mut db_batch = SchemaBatch::new();
-            for db_pruner in db_pruners {
-                db_pruner.lock().set_target_version(target_version);
-                db_pruner.lock().prune(&mut db_batch, max_version)?;
+            pruner_manager.set_target_version(target_version);
+            // There is no next round to retry in, so any failure is the caller's.
+            let result = pruner_manager
+                .prune_without_retries(&mut db_batch)
+                .and_then(|()| self.db.write_schemas(db_batch));
+            match &result {
+                Ok(()) => pruner_manager.on_committed(),
+                Err(_) => pruner_manager.on_discarded(),
             }
-            self.db.write_schemas(db_batch)
+            result
         })
     }
 }
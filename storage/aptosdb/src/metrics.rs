@@ -2,8 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use aptos_metrics::{
-    register_histogram_vec, register_int_counter, register_int_gauge, register_int_gauge_vec,
-    HistogramVec, IntCounter, IntGauge, IntGaugeVec,
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -84,6 +84,149 @@ pub static PRUNER_LEAST_READABLE_VERSION: Lazy<IntGaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Least readable version across all the DB pruners, below which cross store reads may fail
+pub static PRUNER_COMBINED_FLOOR: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        // metric name
+        "aptos_pruner_combined_floor",
+        // metric description
+        "Aptos pruner least readable version across all the pruners"
+    )
+    .unwrap()
+});
+
+/// DB pruner keys deleted since process start, by the column family they were deleted from
+pub static PRUNER_KEYS_DELETED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        // metric name
+        "aptos_pruner_keys_deleted_total",
+        // metric description
+        "Aptos pruner number of keys deleted",
+        // metric labels (dimensions)
+        &["pruner_name", "sub_component"]
+    )
+    .unwrap()
+});
+
+/// DB pruner keys skipped since process start for failing to decode, e.g. written in a legacy
+/// layout, by the column family they were read from
+pub static PRUNER_UNDECODABLE_KEYS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        // metric name
+        "aptos_pruner_undecodable_keys_total",
+        // metric description
+        "Aptos pruner number of keys skipped for failing to decode",
+        // metric labels (dimensions)
+        &["pruner_name", "sub_component"]
+    )
+    .unwrap()
+});
+
+/// DB pruner time spent per pruning round
+pub static PRUNER_BATCH_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        // metric name
+        "aptos_pruner_batch_duration_seconds",
+        // metric description
+        "Aptos pruner time spent per pruning round in seconds",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner number of batches committed in the middle of a round, once grown past the threshold
+pub static PRUNER_INTERMEDIATE_COMMITS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        // metric name
+        "aptos_pruner_intermediate_commits_total",
+        // metric description
+        "Aptos pruner number of batches committed before the end of a pruning round",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner rounds cut short of the prunable target by their `max_versions`, which keep coming
+/// as long as the pruner is behind
+pub static PRUNER_BATCH_CLAMPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        // metric name
+        "aptos_pruner_batch_clamped_total",
+        // metric description
+        "Aptos pruner number of rounds limited by max_versions short of the prunable target",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner rounds taking longer than the threshold they are configured with
+pub static PRUNER_SLOW_BATCHES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        // metric name
+        "aptos_pruner_slow_batches_total",
+        // metric description
+        "Aptos pruner number of rounds taking longer than the slow batch threshold",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner starvation, 1 while the versions left to prune keep growing round after round
+pub static PRUNER_STARVING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        // metric name
+        "aptos_pruner_starving",
+        // metric description
+        "Aptos pruner falling further behind its target every round, 0 or 1",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner time since the last round which did work failed, -1 while it succeeded
+pub static PRUNER_LAST_ERROR_AGE_SECONDS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        // metric name
+        "aptos_pruner_last_error_age_seconds",
+        // metric description
+        "Aptos pruner seconds since the last round failed, -1 if it succeeded",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner lag behind the wall clock, i.e. the age of the least readable version
+pub static PRUNER_LAG_SECONDS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        // metric name
+        "aptos_pruner_lag_seconds",
+        // metric description
+        "Aptos pruner time since the block of the least readable version in seconds",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
+/// DB pruner lag behind its target, as of the data actually left in the DB
+pub static PRUNER_OLDEST_LIVE_VERSION_LAG: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        // metric name
+        "aptos_pruner_oldest_live_version_lag",
+        // metric description
+        "Aptos pruner number of versions between the oldest one left in the DB and the target",
+        // metric labels (dimensions)
+        &["pruner_name",]
+    )
+    .unwrap()
+});
+
 pub static PRUNER_BATCH_SIZE: Lazy<IntGauge> =
     Lazy::new(|| register_int_gauge!("pruner_batch_size", "Aptos pruner batch size").unwrap());
 
@@ -0,0 +1,12 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_types::transaction::Version;
+
+/// Tells the versions of the latest durable checkpoints, for the pruner manager to derive the
+/// target version from, see `PrunerManager::with_checkpoint_target()`.
+pub trait CheckpointProvider {
+    /// Returns the versions of the latest `count` durable checkpoints, newest first, or as many
+    /// as there are if fewer.
+    fn latest_checkpoints(&self, count: usize) -> anyhow::Result<Vec<Version>>;
+}
@@ -0,0 +1,42 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_infallible::Mutex;
+use aptos_types::transaction::Version;
+use std::collections::BTreeMap;
+
+/// Keeps track of the versions of the state checkpoints restores replay the write sets from, for
+/// the write set pruner given the registry not to prune the write sets from the oldest of them on.
+#[derive(Default)]
+pub struct CheckpointRegistry {
+    /// Number of times each checkpoint version is registered.
+    checkpoints: Mutex<BTreeMap<Version, usize>>,
+}
+
+impl CheckpointRegistry {
+    /// Keeps the write sets from `version` on until it's unregistered as many times as it's
+    /// registered. Write sets already pruned by then aren't brought back.
+    pub fn register(&self, version: Version) {
+        *self.checkpoints.lock().entry(version).or_insert(0) += 1;
+    }
+
+    /// Releases a registration of `version`, returning false if there was none.
+    pub fn unregister(&self, version: Version) -> bool {
+        let mut checkpoints = self.checkpoints.lock();
+        match checkpoints.get_mut(&version) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    checkpoints.remove(&version);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the oldest checkpoint version registered, `None` if none is.
+    pub fn oldest_checkpoint(&self) -> Option<Version> {
+        self.checkpoints.lock().keys().next().copied()
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_infallible::duration_since_epoch;
+#[cfg(test)]
+use aptos_infallible::Mutex;
+use std::{thread, time::Duration};
+
+/// Tells the time and waits, for the pruner's pacing to be driven by a frozen clock in tests.
+pub trait Clock {
+    /// Returns the time elapsed since the UNIX epoch.
+    fn now(&self) -> Duration;
+
+    /// Waits for `duration` to elapse.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        duration_since_epoch()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Stands still but for the time slept.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FrozenClock {
+    pub now: Mutex<Duration>,
+    pub slept: Mutex<Vec<Duration>>,
+}
+
+#[cfg(test)]
+impl Clock for FrozenClock {
+    fn now(&self) -> Duration {
+        *self.now.lock()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        *self.now.lock() += duration;
+        self.slept.lock().push(duration);
+    }
+}
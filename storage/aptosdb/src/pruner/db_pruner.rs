@@ -1,10 +1,164 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use aptos_logger::{error, info};
+use crate::{
+    errors::AptosDbError,
+    metrics::{
+        PRUNER_LAST_ERROR_AGE_SECONDS, PRUNER_OLDEST_LIVE_VERSION_LAG, PRUNER_SLOW_BATCHES_TOTAL,
+    },
+    pruner::pruner_kind::PrunerKind,
+    LedgerStore, TransactionStore,
+};
+use aptos_logger::{error, info, warn};
 use aptos_types::transaction::Version;
 use schemadb::SchemaBatch;
-use std::{cmp::min, thread::sleep, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::min,
+    sync::Arc,
+    thread::{sleep, yield_now},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::Span;
+
+/// The outcome of a single pruning round.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PruneProgress {
+    /// The least readable version after the round.
+    pub version: Version,
+    /// Number of DB write operations issued by the round, which callers can use to throttle
+    /// themselves according to the work actually done.
+    pub keys_pruned: usize,
+}
+
+/// How a pruner is configured, as of the time it's read, for introspection.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrunerConfig {
+    pub name: &'static str,
+    /// Number of versions right before the target version that are never pruned.
+    pub prune_window: Version,
+    pub target_version: Version,
+    /// The `max_versions` the last round was allowed to prune, `None` until a round does work.
+    pub max_versions: Option<u64>,
+    /// The version the last round set out to prune up to, after the prune strategy had its say,
+    /// `None` until a round does work.
+    pub batch_target: Option<Version>,
+}
+
+/// How the last pruning round which did work went, for pruners keeping track of it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PruneStatus {
+    /// How long the round took.
+    pub duration: Duration,
+    /// The error the round failed with, `None` if it didn't. An interrupted round didn't fail.
+    pub error: Option<String>,
+    /// When the round ended.
+    pub finished_at: Instant,
+}
+
+impl PruneStatus {
+    /// Returns the status of a round which just ended with `result` after `duration`.
+    pub fn new(result: &Result<PruneProgress, PrunerError>, duration: Duration) -> Self {
+        let error = match result {
+            Ok(_) | Err(PrunerError::Interrupted(_)) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        Self {
+            duration,
+            error,
+            finished_at: Instant::now(),
+        }
+    }
+}
+
+/// How a pruner is doing, as of the time it's read, e.g. for an admin endpoint to report.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrunerHealth {
+    pub name: &'static str,
+    pub least_readable_version: Version,
+    pub target_version: Version,
+    pub remaining_versions: Version,
+    pub enabled: bool,
+    /// How long the last round which did work took, `None` until one does or if the pruner
+    /// doesn't keep track of it.
+    pub last_batch_duration: Option<Duration>,
+    /// The error the last round which did work failed with, `None` if it didn't.
+    pub last_error: Option<String>,
+}
+
+/// The progress of a pruner, as of the time it's taken, e.g. for a controller to collect from each
+/// node and reason about the retention across the cluster.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProgressSnapshot {
+    pub kind: PrunerKind,
+    pub least_readable: Version,
+    pub target: Version,
+    /// When the least readable version last changed, since the UNIX epoch, `None` if it hasn't
+    /// since the pruner was created.
+    pub updated_at: Option<Duration>,
+}
+
+/// Invoked with the version reached and the number of keys deleted at the end of each successful
+/// pruning round, for observers wanting progress pushed to them rather than polling the metrics.
+/// It runs on the pruning thread, so it must be quick to not hold up the pruning.
+pub type PruneProgressCallback = Box<dyn Fn(Version, usize) + Send + Sync>;
+
+/// Returns the latest version committed, `None` if nothing is. It's called each time the target
+/// is set, so it must be quick, e.g. reading an atomic.
+pub type LatestVersionCallback = Box<dyn Fn() -> Option<Version> + Send + Sync>;
+
+/// Warns about a round of `pruner_name` pruning the range of versions [begin, end) which took
+/// `elapsed`, if that's longer than `slow_batch_threshold`, e.g. a huge sparse range scanned.
+pub(crate) fn warn_if_slow_batch(
+    pruner_name: &str,
+    slow_batch_threshold: Option<Duration>,
+    elapsed: Duration,
+    begin: Version,
+    end: Version,
+    keys_pruned: usize,
+) {
+    if slow_batch_threshold.map_or(false, |slow_batch_threshold| elapsed > slow_batch_threshold) {
+        warn!(
+            begin = begin,
+            end = end,
+            keys_pruned = keys_pruned,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "{} slow pruning round.",
+            pruner_name,
+        );
+        PRUNER_SLOW_BATCHES_TOTAL
+            .with_label_values(&[pruner_name])
+            .inc();
+    }
+}
+
+/// Errors a pruning round can end with, telling the transient failures worth retrying from the
+/// fatal ones.
+#[derive(Debug, Error)]
+pub enum PrunerError {
+    /// Accessing the DB failed, which is likely transient and worth retrying. This is what any
+    /// error not classified otherwise ends up as.
+    #[error("Pruner IO error: {0}")]
+    Io(anyhow::Error),
+    /// An entry read from the DB failed to decode.
+    #[error("Pruner failed decoding schema: {0}")]
+    SchemaDecode(anyhow::Error),
+    /// The round was stopped early on request. Its deletions up to the version reached are in the
+    /// batch, which must be committed as usual.
+    #[error("Pruning interrupted at version {}.", .0.version)]
+    Interrupted(PruneProgress),
+}
+
+impl From<anyhow::Error> for PrunerError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.is::<bcs::Error>() {
+            PrunerError::SchemaDecode(error)
+        } else {
+            PrunerError::Io(error)
+        }
+    }
+}
 
 /// Defines the trait for pruner for different DB
 pub trait DBPruner {
@@ -34,39 +188,405 @@ pub trait DBPruner {
             }
         }
     }
-    fn name(&self) -> &'static str;
+    /// Returns the kind of the pruner, telling its name and the label of the metrics about it.
+    fn kind(&self) -> PrunerKind;
+
+    fn name(&self) -> &'static str {
+        self.kind().name()
+    }
 
     /// Performs the actual pruning, a target version is passed, which is the target the pruner
     /// tries to prune.
-    fn prune(&self, db_batch: &mut SchemaBatch, max_versions: u64) -> anyhow::Result<Version>;
+    ///
+    /// Overlapping calls on the same pruner are serialized, each one waiting for the previous to
+    /// return and then pruning on from where it left off, so the ranges pruned never overlap.
+    fn prune(
+        &self,
+        db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError>;
+
+    /// Runs rounds of at most `max_versions` versions each until the pruner catches up with its
+    /// target, yielding the CPU in between, and returns the least readable version reached. Each
+    /// round's deletions are committed on their own, see `commit()`, so memory stays bounded by a
+    /// round however far the target is.
+    ///
+    /// Returns early once a stop is requested, or if a round makes no progress.
+    fn prune_to_target(&self, max_versions: Version) -> anyhow::Result<Version> {
+        while self.is_pruning_pending() && !self.is_stop_requested() {
+            let least_readable_version = self.least_readable_version();
+            let mut db_batch = SchemaBatch::new();
+            let (progress, interrupted) = match self.prune(&mut db_batch, max_versions) {
+                Ok(progress) => (progress, false),
+                Err(PrunerError::Interrupted(progress)) => (progress, true),
+                Err(e) => {
+                    self.on_discarded();
+                    return Err(e.into());
+                }
+            };
+            if let Err(e) = self.round_span().in_scope(|| self.commit(db_batch)) {
+                self.on_discarded();
+                return Err(e);
+            }
+            self.on_committed();
+            if interrupted || progress.version <= least_readable_version {
+                break;
+            }
+            yield_now();
+        }
+        Ok(self.least_readable_version())
+    }
+
+    /// Writes `db_batch`, holding the deletions of rounds of this pruner, to the DB it prunes.
+    fn commit(&self, db_batch: SchemaBatch) -> anyhow::Result<()>;
+
+    /// True if the pruner is configured with a stop flag which is set, for pruning in progress to
+    /// stop early.
+    fn is_stop_requested(&self) -> bool {
+        false
+    }
+
+    /// Called once the deletions of a round pruning versions [begin, end) are committed. Pruners
+    /// set to do so compact the pruned range to get rid of the tombstones early, and return the
+    /// range compacted.
+    fn compact_pruned_range(
+        &self,
+        _begin: Version,
+        _end: Version,
+    ) -> anyhow::Result<Option<(Version, Version)>> {
+        Ok(None)
+    }
 
     /// Initializes the least readable version stored in underlying DB storage
     fn initialize_least_readable_version(&self) -> anyhow::Result<Version>;
 
+    /// Same as `initialize_least_readable_version()`, but tries `hint` first, e.g. the least
+    /// readable version last known to a coordinator, sparing the seek for pruners that check it
+    /// is right before trusting it. A stale hint falls back to the seek.
+    fn initialize_least_readable_version_with_hint(
+        &self,
+        _hint: Version,
+    ) -> anyhow::Result<Version> {
+        self.initialize_least_readable_version()
+    }
+
+    /// Same as `initialize_least_readable_version()`, and also removes anything a partially
+    /// committed pruning round left behind before that version. This is opt-in since it may
+    /// need to scan whole schemas.
+    fn initialize_and_repair(&self) -> anyhow::Result<Version> {
+        self.initialize_least_readable_version()
+    }
+
+    /// Persists the least readable version right away, for pruners persisting their progress,
+    /// e.g. on shutdown after it was moved without a round, like by a reset. The deletions a round
+    /// handed over must be committed by then, for the progress not to go past the data left.
+    fn flush_progress(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Reads, writing nothing, the keys of the `max_versions` versions from the least readable
+    /// one on, filling the block cache so that the next round doesn't pay a disk seek for each,
+    /// e.g. the first round after a restart. It doesn't wait for the target, which is usually not
+    /// set yet on a restart. Returns the number of keys read, none for pruners without warmup.
+    fn warmup(&self, _max_versions: Version) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
     /// Returns the least readable version stores in the DB pruner
+    ///
+    /// Pruners load it with `Acquire` ordering, pairing with the `Release` store in
+    /// `record_progress()`, so that a thread seeing a version recorded, e.g. to gate a read on it,
+    /// also sees whatever the recording thread wrote before, like the deletions a pruner commits
+    /// itself. Pruners handing deletions over to the caller record the progress they make only
+    /// on `on_committed()`, once the caller has written them.
     fn least_readable_version(&self) -> Version;
 
+    /// True if the pruner left `version` readable, a cheap check before a read that would fail
+    /// otherwise. A version past the latest one committed is reported readable.
+    fn is_version_readable(&self, version: Version) -> bool {
+        version >= self.least_readable_version()
+    }
+
     /// Sets the target version for the pruner
     fn set_target_version(&self, target_version: Version);
 
     /// Returns the target version for the DB pruner
     fn target_version(&self) -> Version;
 
+    /// Returns the least readable version together with the target version, i.e. where the
+    /// pruning is and how far it's set to go. The versions from the least readable one on are
+    /// readable, those below the target only until they are pruned. Neither tells the latest
+    /// version readable, which is up to what's committed.
+    fn readable_version_range(&self) -> (Version, Version) {
+        (self.least_readable_version(), self.target_version())
+    }
+
+    /// Sets the target version to the last version committed in a block with a timestamp no
+    /// larger than `timestamp_usecs`, and returns it. Only the versions from the least readable
+    /// one on are searched, the ones in a block whose start is pruned already counting as before
+    /// the first block. A timestamp before the first block left gives the least readable version,
+    /// and one after the latest block gives the latest version.
+    fn set_target_by_timestamp(
+        &self,
+        timestamp_usecs: u64,
+        ledger_store: &LedgerStore,
+        transaction_store: &TransactionStore,
+    ) -> anyhow::Result<Version> {
+        let latest_version = ledger_store
+            .get_latest_ledger_info_option()
+            .map_or(0, |li| li.ledger_info().version());
+        // Block timestamps never decrease along versions, and are 0 before the first block.
+        let block_timestamp = |version| -> anyhow::Result<u64> {
+            match transaction_store.get_block_metadata(version) {
+                Ok(block_meta) => {
+                    Ok(block_meta.map_or(0, |(_version, block_meta)| block_meta.timestamp_usecs()))
+                }
+                Err(e) => match e.downcast_ref::<AptosDbError>() {
+                    Some(AptosDbError::NotFound(_)) => Ok(0),
+                    _ => Err(e),
+                },
+            }
+        };
+
+        // Binary search with the invariant that the answer is in [begin, end].
+        let mut begin = min(self.least_readable_version(), latest_version);
+        let mut end = latest_version;
+        while begin < end {
+            let mid = begin + (end - begin + 1) / 2;
+            if block_timestamp(mid)? <= timestamp_usecs {
+                begin = mid;
+            } else {
+                end = mid - 1;
+            }
+        }
+        self.set_target_version(begin);
+        Ok(begin)
+    }
+
+    /// Returns the `max_versions` and the target of the last round which did work, for pruners
+    /// keeping track of them.
+    fn last_batch(&self) -> Option<(u64, Version)> {
+        None
+    }
+
+    /// Returns how the last round which did work went, for pruners keeping track of it.
+    fn last_status(&self) -> Option<PruneStatus> {
+        None
+    }
+
+    /// Returns when the last round which did work failed and the error it failed with, `None` if
+    /// it succeeded, e.g. for the failures a loop retrying them swallows not to go unnoticed. The
+    /// next round which does work succeeding clears it.
+    fn last_error(&self) -> Option<(Instant, String)> {
+        self.last_status()
+            .and_then(|status| status.error.map(|error| (status.finished_at, error)))
+    }
+
+    /// Sets the last error age gauge of the pruner to the whole seconds since `last_error()`, -1
+    /// if there is none, and returns it.
+    fn record_last_error_age(&self) -> Option<Duration> {
+        let age = self
+            .last_error()
+            .map(|(failed_at, _error)| failed_at.elapsed());
+        PRUNER_LAST_ERROR_AGE_SECONDS
+            .with_label_values(&[self.kind().metric_label()])
+            .set(age.map_or(-1, |age| age.as_secs() as i64));
+        age
+    }
+
+    /// Returns how the pruner is doing, all in one.
+    fn health(&self) -> PrunerHealth {
+        let last_status = self.last_status();
+        PrunerHealth {
+            name: self.name(),
+            least_readable_version: self.least_readable_version(),
+            target_version: self.target_version(),
+            remaining_versions: self.estimated_remaining_versions(),
+            enabled: self.is_enabled(),
+            last_batch_duration: last_status.as_ref().map(|status| status.duration),
+            last_error: last_status.and_then(|status| status.error),
+        }
+    }
+
+    /// Returns a snapshot of the progress of the pruner, without touching the DB.
+    fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            kind: self.kind(),
+            least_readable: self.least_readable_version(),
+            target: self.target_version(),
+            updated_at: self.progress_updated_at(),
+        }
+    }
+
+    /// Returns the live configuration of the pruner.
+    fn config(&self) -> PrunerConfig {
+        let last_batch = self.last_batch();
+        PrunerConfig {
+            name: self.name(),
+            prune_window: self.prune_window(),
+            target_version: self.target_version(),
+            max_versions: last_batch.map(|(max_versions, _)| max_versions),
+            batch_target: last_batch.map(|(_, batch_target)| batch_target),
+        }
+    }
+
+    /// Returns the number of versions right before the target version that are never pruned,
+    /// keeping recent history readable.
+    fn prune_window(&self) -> Version;
+
+    /// Returns the lowest version in-flight reads need, which the pruner must not prune past,
+    /// `None` if there is no such read or the pruner doesn't track them.
+    fn read_floor(&self) -> Option<Version> {
+        None
+    }
+
+    /// Returns the version the pruner settles for when it could prune up to `target`, no greater
+    /// than it, for pruners keeping what they leave readable in whole units, e.g. whole epochs.
+    fn align_target(&self, target: Version) -> Version {
+        target
+    }
+
+    /// Returns the version the pruner can actually prune up to, which is the target version
+    /// minus the prune window, held back at the read floor if any, and then aligned.
+    fn prunable_target_version(&self) -> Version {
+        let prunable_target_version = self.target_version().saturating_sub(self.prune_window());
+        self.align_target(
+            self.read_floor()
+                .map_or(prunable_target_version, |read_floor| {
+                    min(prunable_target_version, read_floor)
+                }),
+        )
+    }
+
+    /// True if the pruner keeps the data of version 0, i.e. genesis, forever, which
+    /// `current_batch_target()` enforces by leaving the rounds nothing to prune for as long as the
+    /// least readable version is 0. Once there is data past genesis, such a pruner starts its
+    /// rounds past genesis itself, the least readable version moving past it only along with the
+    /// deletions of the round, and keeps what the later rounds would delete of genesis, e.g. the
+    /// state nodes of genesis once replaced.
+    fn never_prune_genesis(&self) -> bool {
+        false
+    }
+
     /// Returns the target version for the current pruning round - this might be different from the
-    /// target_version() because we need to keep max_version in account.
-    fn get_currrent_batch_target(&self, max_versions: Version) -> Version {
+    /// target_version() because we need to keep max_version and the prune window in account.
+    fn current_batch_target(&self, max_versions: Version) -> Version {
+        let least_readable_version = self.round_start_version();
+        // A round starting at genesis would prune it.
+        if self.never_prune_genesis() && least_readable_version == 0 {
+            return 0;
+        }
+        self.batch_target_from(least_readable_version, max_versions)
+    }
+
+    /// Returns the target version of a round starting at `start_version`, e.g. past genesis for a
+    /// round skipping it, see `never_prune_genesis()`.
+    fn batch_target_from(&self, start_version: Version, max_versions: Version) -> Version {
         // Current target version  might be less than the target version to ensure we don't prune
         // more than max_version in one go.
         min(
-            self.least_readable_version() + max_versions as u64,
-            self.target_version(),
+            start_version.saturating_add(max_versions),
+            self.prunable_target_version(),
         )
     }
-    /// Records the current progress of the pruner by updating the least readable version
-    fn record_progress(&self, least_readable_version: Version);
 
-    /// True if there is pruning work pending to be done
+    /// Returns an estimate of the number of keys the next round of at most `max_versions` versions
+    /// deletes, for pruners able to tell it without doing the round, `None` otherwise.
+    fn estimate_next_batch_size(&self, _max_versions: Version) -> anyhow::Result<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Records the current progress of the pruner by updating the least readable version, and
+    /// returns the number of versions it advanced by, 0 if it didn't advance, e.g. for logging
+    /// how far a round clamped by `current_batch_target()` went.
+    fn record_progress(&self, least_readable_version: Version) -> Version;
+
+    /// Returns when `record_progress()` last changed the least readable version, since the UNIX
+    /// epoch, `None` if it hasn't since the pruner was created.
+    fn progress_updated_at(&self) -> Option<Duration>;
+
+    /// Enables or disables the pruner at runtime. A disabled pruner keeps its target but has no
+    /// pending work, so its rounds do nothing until it's enabled again.
+    fn set_enabled(&self, enabled: bool);
+
+    /// True unless the pruner is disabled, pruners are enabled upon creation.
+    fn is_enabled(&self) -> bool;
+
+    /// Returns the version the next round starts from. That's the least readable version, unless
+    /// the pruner records the progress of rounds only once their deletions are committed, see
+    /// `on_committed()`, and some are yet to be.
+    fn round_start_version(&self) -> Version {
+        self.least_readable_version()
+    }
+
+    /// True if there is pruning work pending to be done, which is never the case while disabled
     fn is_pruning_pending(&self) -> bool {
-        self.target_version() > self.least_readable_version()
+        self.is_enabled() && self.prunable_target_version() > self.round_start_version()
+    }
+
+    /// Returns the number of versions the pruner still needs to prune to reach its target
+    fn estimated_remaining_versions(&self) -> Version {
+        self.prunable_target_version()
+            .saturating_sub(self.least_readable_version())
+    }
+
+    /// Returns the span of the round whose deletions were handed over, for the caller to enter
+    /// while committing them, so that the round's span covers its commit too. Pruners committing
+    /// their rounds themselves have none.
+    fn round_span(&self) -> Span {
+        Span::none()
+    }
+
+    /// Called once the deletions the rounds handed over so far are committed, for pruners
+    /// reporting what they deleted, or recording the progress they made, only then.
+    fn on_committed(&self) {}
+
+    /// Called once the deletions the rounds handed over since the last commit are dropped
+    /// instead, e.g. when the round or the commit fails.
+    fn on_discarded(&self) {}
+
+    /// Returns the oldest version whose data is left in the DB, as read off the DB rather than
+    /// recorded, for pruners able to tell it, `None` otherwise or if there is no data at all.
+    fn oldest_live_version(&self) -> anyhow::Result<Option<Version>> {
+        Ok(None)
     }
+
+    /// Sets the oldest live version lag gauge of the pruner to the number of versions between
+    /// `oldest_live_version()` and the target, and returns it. Unlike the least readable version
+    /// recorded, this catches data left behind, e.g. by deletions that failed to commit, telling
+    /// a pruner stuck with a backlog from one with nothing to do. The gauge is left as it was if
+    /// the oldest live version isn't known. Since it costs a seek, it's meant to be refreshed
+    /// every now and then rather than after each round.
+    fn record_oldest_live_version_lag(&self) -> anyhow::Result<Option<Version>> {
+        let lag = self
+            .oldest_live_version()?
+            .map(|oldest_live_version| self.target_version().saturating_sub(oldest_live_version));
+        if let Some(lag) = lag {
+            PRUNER_OLDEST_LIVE_VERSION_LAG
+                .with_label_values(&[self.kind().metric_label()])
+                .set(lag as i64);
+        }
+        Ok(lag)
+    }
+}
+
+/// Same as `DBPruner::prune()`, but runs on the blocking thread pool of the current tokio runtime
+/// to keep the DB work off the async executor. Since the pruning outlives the caller's borrow, the
+/// pruner has to be `Arc`-cloned to call this, e.g. `prune_async(Arc::clone(&pruner), ..)`. The
+/// batch is moved in and handed back along with the progress, for the caller to commit, which is
+/// also the case for an interrupted round.
+#[allow(dead_code)]
+pub async fn prune_async(
+    db_pruner: Arc<dyn DBPruner + Send + Sync>,
+    mut db_batch: SchemaBatch,
+    max_versions: u64,
+) -> anyhow::Result<(SchemaBatch, PruneProgress)> {
+    tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let progress = match db_pruner.prune(&mut db_batch, max_versions) {
+            Ok(progress) | Err(PrunerError::Interrupted(progress)) => progress,
+            Err(e) => return Err(e.into()),
+        };
+        Ok((db_batch, progress))
+    })
+    .await?
 }
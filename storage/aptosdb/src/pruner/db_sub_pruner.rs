@@ -1,6 +1,7 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+use aptos_types::transaction::Version;
 use schemadb::SchemaBatch;
 
 /// Defines the trait for sub-pruner of a parent DB pruner
@@ -13,4 +14,34 @@ pub trait DBSubPruner {
         least_readable_version: u64,
         target_version: u64,
     ) -> anyhow::Result<()>;
+
+    /// Cleans up the data left behind before `least_readable_version` by a pruning round that was
+    /// only partially committed. Nothing to do by default.
+    fn repair(
+        &self,
+        _db_batch: &mut SchemaBatch,
+        _least_readable_version: Version,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Puts into `archive_batch` what pruning the range of versions [least_readable_version,
+    /// target_version) deletes, for it to be kept in the archive DB, see
+    /// `LedgerPruner::with_archive_db()`. Nothing is archived by default.
+    fn archive(
+        &self,
+        _archive_batch: &mut SchemaBatch,
+        _least_readable_version: Version,
+        _target_version: Version,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the deletions the pruner put into the batches so far are committed. Nothing
+    /// to do by default.
+    fn on_committed(&self) {}
+
+    /// Called once the deletions the pruner put into the batches since the last commit are
+    /// dropped instead, e.g. on dry run or when the round fails. Nothing to do by default.
+    fn on_discarded(&self) {}
 }
@@ -0,0 +1,73 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_config::config::EventDeletionOrder;
+use aptos_types::transaction::Version;
+use std::cmp::min;
+
+/// Number of versions each range deletion covers once the range pruned is split up.
+const DELETION_CHUNK_VERSIONS: Version = 1000;
+
+/// The order the range deletions of the versions pruned are added to the batch in, e.g. to
+/// spread the tombstones over the memtable rather than piling them up at the low end of the
+/// keyspace, where iteration starts. What's deleted is the same whatever the order. Since the
+/// tombstones are keyed, the order only changes how they are laid out until the next flush, so
+/// any gain is to be measured, see the `event_deletion_order` benchmark.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeletionOrder {
+    /// The whole range in one deletion, the default.
+    Ascending,
+    /// Split up into chunks, from the last one back.
+    Descending,
+    /// Split up into chunks, alternately from either end towards the middle.
+    Interleaved,
+}
+
+impl Default for DeletionOrder {
+    fn default() -> Self {
+        Self::Ascending
+    }
+}
+
+impl From<EventDeletionOrder> for DeletionOrder {
+    fn from(event_deletion_order: EventDeletionOrder) -> Self {
+        match event_deletion_order {
+            EventDeletionOrder::Ascending => Self::Ascending,
+            EventDeletionOrder::Descending => Self::Descending,
+            EventDeletionOrder::Interleaved => Self::Interleaved,
+        }
+    }
+}
+
+impl DeletionOrder {
+    /// Returns the ranges the range of versions [begin, end) is deleted in, in order.
+    pub fn ranges(&self, begin: Version, end: Version) -> Vec<(Version, Version)> {
+        if end.saturating_sub(begin) <= DELETION_CHUNK_VERSIONS {
+            return vec![(begin, end)];
+        }
+        let chunks = || -> Vec<_> {
+            (begin..end)
+                .step_by(DELETION_CHUNK_VERSIONS as usize)
+                .map(|chunk_begin| (chunk_begin, min(chunk_begin + DELETION_CHUNK_VERSIONS, end)))
+                .collect()
+        };
+        match self {
+            Self::Ascending => vec![(begin, end)],
+            Self::Descending => chunks().into_iter().rev().collect(),
+            Self::Interleaved => {
+                let chunks = chunks();
+                let mut ranges = Vec::with_capacity(chunks.len());
+                let (mut front, mut back) = (0, chunks.len());
+                while front < back {
+                    ranges.push(chunks[front]);
+                    front += 1;
+                    if front < back {
+                        back -= 1;
+                        ranges.push(chunks[back]);
+                    }
+                }
+                ranges
+            }
+        }
+    }
+}
@@ -0,0 +1,12 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_types::contract_event::ContractEvent;
+
+/// Receives the events about to be pruned, e.g. to stream them to cold storage so the history
+/// isn't lost for good.
+pub trait ArchiveSink {
+    /// Archives `events`, which are in version order. Failing aborts the pruning round, leaving
+    /// the events in the DB.
+    fn archive_events(&self, events: &[ContractEvent]) -> anyhow::Result<()>;
+}
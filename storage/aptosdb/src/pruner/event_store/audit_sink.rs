@@ -0,0 +1,34 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_logger::info;
+use aptos_types::{event::EventKey, transaction::Version};
+use std::{collections::HashMap, ops::Range};
+
+/// What pruning a range of versions deleted of the events.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AuditRecord {
+    pub version_range: Range<Version>,
+    /// The number of events deleted under each key, the keys none were deleted of left out.
+    pub per_key_counts: HashMap<EventKey, usize>,
+}
+
+/// Receives what the event pruning deleted, e.g. to keep an audit log of it.
+pub trait AuditSink {
+    /// Records `record`, once the deletions it accounts for are committed. Nothing is retried if
+    /// this fails, so the sink is to handle its failures itself.
+    fn record(&self, record: AuditRecord);
+}
+
+/// Logs each record, see `StoragePrunerConfig::log_pruned_events`.
+pub struct LogAuditSink;
+
+impl AuditSink for LogAuditSink {
+    fn record(&self, record: AuditRecord) {
+        info!(
+            version_range = ?record.version_range,
+            per_key_counts = ?record.per_key_counts,
+            "Events pruned."
+        );
+    }
+}
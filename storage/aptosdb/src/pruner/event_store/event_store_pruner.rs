@@ -1,11 +1,121 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
-use crate::{pruner::db_sub_pruner::DBSubPruner, EventStore};
+use crate::{
+    event_store::PruneEventsOptions,
+    pruner::{
+        db_sub_pruner::DBSubPruner,
+        event_store::{
+            archive_sink::ArchiveSink,
+            audit_sink::{AuditRecord, AuditSink},
+        },
+    },
+    schema::{
+        event::EventSchema, event_by_key::EventByKeySchema, event_by_version::EventByVersionSchema,
+    },
+    EventStore,
+};
+use anyhow::{bail, ensure};
+use aptos_crypto::HashValue;
+use aptos_infallible::Mutex;
+use aptos_logger::warn;
+use aptos_types::{
+    contract_event::ContractEvent,
+    event::EventKey,
+    transaction::{AtomicVersion, Version},
+};
+use move_deps::move_core_types::language_storage::TypeTag;
 use schemadb::SchemaBatch;
-use std::sync::Arc;
+use std::{
+    cmp::{max, min},
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::{atomic::Ordering, Arc},
+};
+
+/// The order in which the event store pruner evicts versions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PruneDirection {
+    /// Oldest first, the pruned history growing from the bottom up.
+    Ascending,
+    /// Newest first, e.g. for a capped-size archive, the pruned history growing from the top down.
+    #[allow(dead_code)]
+    Descending,
+}
+
+impl Default for PruneDirection {
+    fn default() -> Self {
+        PruneDirection::Ascending
+    }
+}
+
+/// What pruning a range of versions deletes, see `EventStorePruner::preview()`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PrunePreview {
+    /// For each event key, the number of its events deleted along with the range of their
+    /// sequence numbers `(count, min, max)`.
+    pub events_by_key: HashMap<EventKey, (usize, u64, u64)>,
+    /// The first and the last version events are deleted at, `None` if none are.
+    pub version_span: Option<(Version, Version)>,
+}
+
+impl PrunePreview {
+    /// Returns the number of events deleted.
+    #[allow(dead_code)]
+    pub fn num_events(&self) -> usize {
+        self.events_by_key
+            .values()
+            .map(|(count, _min_seq_num, _max_seq_num)| count)
+            .sum()
+    }
+
+    fn add(&mut self, version: Version, event_key: EventKey, seq_num: u64) {
+        match self.events_by_key.entry(event_key) {
+            Entry::Occupied(mut occupied) => {
+                let (count, min_seq_num, max_seq_num) = *occupied.get();
+                occupied.insert((
+                    count + 1,
+                    min(min_seq_num, seq_num),
+                    max(max_seq_num, seq_num),
+                ));
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert((1, seq_num, seq_num));
+            }
+        }
+        // Candidates come in the order of versions.
+        self.version_span = Some(
+            self.version_span
+                .map_or((version, version), |(first, _last)| (first, version)),
+        );
+    }
+}
 
 pub struct EventStorePruner {
     event_store: Arc<EventStore>,
+    /// If set, `verify_post_prune()` reads back what's left, which costs extra reads.
+    verify: bool,
+    direction: PruneDirection,
+    /// Versions from this one on are evicted, in the descending direction only.
+    readable_end: AtomicVersion,
+    /// If set, gets the events of each range before they are deleted.
+    archive_sink: Option<Arc<dyn ArchiveSink + Send + Sync>>,
+    /// If set, told what each range deleted, see `with_audit_sink()`.
+    audit_sink: Option<Arc<dyn AuditSink + Send + Sync>>,
+    /// What the ranges pruned since the last commit deleted, for the audit sink to be told once
+    /// they are committed.
+    pending_audit_records: Mutex<Vec<AuditRecord>>,
+    /// The events deleted by the ranges pruned since the last commit and not yet handed over by
+    /// `archive()`.
+    unarchived_events: Mutex<Vec<(Version, u64, ContractEvent)>>,
+    /// How the ranges are pruned, see `with_latest_event_per_key_kept()`.
+    prune_options: PruneEventsOptions,
+    /// If set, a failure pruning the event accumulator doesn't fail the round, see
+    /// `with_accumulator_failure_isolation()`.
+    isolate_accumulator_failures: bool,
+    /// Where the event accumulator is left unpruned from since a failure, to be retried next
+    /// round.
+    pending_accumulator_start: Mutex<Option<Version>>,
+    /// The ranges of versions pruned by `prune_ranges()`, sorted by their start.
+    pruned_ranges: Mutex<Vec<(Version, Version)>>,
 }
 
 impl DBSubPruner for EventStorePruner {
@@ -15,14 +125,518 @@ impl DBSubPruner for EventStorePruner {
         least_readable_version: u64,
         target_version: u64,
     ) -> anyhow::Result<()> {
-        self.event_store
-            .prune_events(least_readable_version, target_version, db_batch)?;
+        // Gathered apart, so a failing archive sink leaves them out of `db_batch`.
+        let mut events_batch = SchemaBatch::new();
+        let deleted_events = match self.direction {
+            PruneDirection::Ascending if self.isolate_accumulator_failures => self
+                .prune_isolating_accumulator(
+                    &mut events_batch,
+                    least_readable_version,
+                    target_version,
+                )?,
+            PruneDirection::Ascending => {
+                self.event_store
+                    .forget_event_presence_before(least_readable_version);
+                self.event_store.prune_events_with_options(
+                    least_readable_version,
+                    target_version,
+                    &self.prune_options,
+                    &mut events_batch,
+                )?
+            }
+            // The range [least_readable_version, target_version) is evicted as the top of what's
+            // readable, leaving the versions below it.
+            PruneDirection::Descending => self.event_store.prune_events_descending(
+                least_readable_version,
+                target_version,
+                &self.prune_options.retain_keys,
+                &mut events_batch,
+            )?,
+        };
+        if self.direction == PruneDirection::Descending {
+            self.readable_end
+                .fetch_min(least_readable_version, Ordering::Relaxed);
+        }
+        if let Some(archive_sink) = &self.archive_sink {
+            if !deleted_events.is_empty() {
+                let events: Vec<_> = deleted_events
+                    .iter()
+                    .map(|(_version, _index, event)| event.clone())
+                    .collect();
+                // Failing leaves the deletions out of the batch.
+                archive_sink.archive_events(&events)?;
+            }
+        }
+        db_batch.merge(events_batch);
+        if self.audit_sink.is_some() {
+            self.pending_audit_records.lock().push(Self::audit_record(
+                least_readable_version,
+                target_version,
+                &deleted_events,
+            ));
+        }
+        self.unarchived_events.lock().extend(deleted_events);
+        Ok(())
+    }
+
+    fn archive(
+        &self,
+        archive_batch: &mut SchemaBatch,
+        least_readable_version: Version,
+        target_version: Version,
+    ) -> anyhow::Result<()> {
+        // What the range deleted is kept from pruning it, so it isn't read again. Events left
+        // below the range, e.g. by a dry run, are never archived.
+        let mut unarchived_events = self.unarchived_events.lock();
+        let (events, later_events): (Vec<_>, Vec<_>) = unarchived_events
+            .drain(..)
+            .filter(|(version, _index, _event)| *version >= least_readable_version)
+            .partition(|(version, _index, _event)| *version < target_version);
+        *unarchived_events = later_events;
+        // The event accumulator isn't archived, so the events archived no longer come with a
+        // proof.
+        for (version, index, event) in events {
+            let key = *event.key();
+            let seq_num = event.sequence_number();
+            archive_batch.put::<EventSchema>(&(version, index), &event)?;
+            archive_batch.put::<EventByKeySchema>(&(key, seq_num), &(version, index))?;
+            archive_batch.put::<EventByVersionSchema>(&(key, version, seq_num), &index)?;
+        }
         Ok(())
     }
+
+    fn on_committed(&self) {
+        self.unarchived_events.lock().clear();
+        if let Some(audit_sink) = &self.audit_sink {
+            for audit_record in self.pending_audit_records.lock().drain(..) {
+                audit_sink.record(audit_record);
+            }
+        }
+    }
+
+    fn on_discarded(&self) {
+        self.unarchived_events.lock().clear();
+        self.pending_audit_records.lock().clear();
+    }
+
+    fn repair(
+        &self,
+        db_batch: &mut SchemaBatch,
+        least_readable_version: Version,
+    ) -> anyhow::Result<()> {
+        self.event_store.prune_dangling_events(
+            least_readable_version,
+            &self.prune_options.retain_keys,
+            db_batch,
+        )
+    }
 }
 
 impl EventStorePruner {
     pub(in crate::pruner) fn new(event_store: Arc<EventStore>) -> Self {
-        EventStorePruner { event_store }
+        Self::new_with_direction(event_store, PruneDirection::default())
+    }
+
+    /// Creates a pruner evicting versions in `direction`.
+    pub(in crate::pruner) fn new_with_direction(
+        event_store: Arc<EventStore>,
+        direction: PruneDirection,
+    ) -> Self {
+        EventStorePruner {
+            event_store,
+            verify: false,
+            direction,
+            readable_end: AtomicVersion::new(Version::MAX),
+            archive_sink: None,
+            audit_sink: None,
+            pending_audit_records: Mutex::new(vec![]),
+            unarchived_events: Mutex::new(vec![]),
+            prune_options: PruneEventsOptions::default(),
+            isolate_accumulator_failures: false,
+            pending_accumulator_start: Mutex::new(None),
+            pruned_ranges: Mutex::new(vec![]),
+        }
+    }
+
+    /// Hands the events of each range over to `archive_sink` before pruning them, failing the
+    /// round without deleting anything if the sink fails.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn with_archive_sink(
+        mut self,
+        archive_sink: Arc<dyn ArchiveSink + Send + Sync>,
+    ) -> Self {
+        self.archive_sink = Some(archive_sink);
+        self
+    }
+
+    /// Tells `audit_sink` how many events of each key each range deleted, once the deletions are
+    /// committed, i.e. on `on_committed()`. The records of ranges whose deletions are dropped,
+    /// e.g. on dry run, are dropped along with them on `on_discarded()`.
+    pub(in crate::pruner) fn with_audit_sink(
+        mut self,
+        audit_sink: Arc<dyn AuditSink + Send + Sync>,
+    ) -> Self {
+        self.audit_sink = Some(audit_sink);
+        self
+    }
+
+    /// Prunes the events of each of the disjoint ranges of versions `[start, end)` in `ranges`,
+    /// e.g. for recovery, committing them all at once. Retained events are kept as usual.
+    ///
+    /// What's left is no longer a contiguous suffix of versions, so the least readable version is
+    /// left alone and the ranges are recorded apart instead, see `pruned_ranges()`. In
+    /// particular, `DBPruner::is_version_readable()` keeps reporting versions in these ranges as
+    /// readable even though their events are gone, readers have to check
+    /// `is_version_in_pruned_ranges()` as well. The ranges are only kept in memory.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_ranges(
+        &self,
+        ranges: &[(Version, Version)],
+    ) -> anyhow::Result<()> {
+        let mut sorted_ranges = ranges.to_vec();
+        sorted_ranges.sort_unstable();
+        for (start, end) in &sorted_ranges {
+            ensure!(start <= end, "Invalid range to prune [{}, {}).", start, end);
+        }
+        for window in sorted_ranges.windows(2) {
+            ensure!(
+                window[0].1 <= window[1].0,
+                "Ranges to prune [{}, {}) and [{}, {}) overlap.",
+                window[0].0,
+                window[0].1,
+                window[1].0,
+                window[1].1,
+            );
+        }
+        let mut db_batch = SchemaBatch::new();
+        for (start, end) in &sorted_ranges {
+            let deleted_events = self.event_store.prune_events_with_options(
+                *start,
+                *end,
+                &self.prune_options,
+                &mut db_batch,
+            )?;
+            if self.audit_sink.is_some() {
+                self.pending_audit_records.lock().push(Self::audit_record(
+                    *start,
+                    *end,
+                    &deleted_events,
+                ));
+            }
+        }
+        if let Err(e) = self.event_store.write_pruning_batch(db_batch) {
+            self.on_discarded();
+            return Err(e);
+        }
+        self.on_committed();
+
+        let mut pruned_ranges = self.pruned_ranges.lock();
+        pruned_ranges.extend(sorted_ranges.into_iter().filter(|(start, end)| start < end));
+        pruned_ranges.sort_unstable();
+        Ok(())
+    }
+
+    /// Returns the ranges of versions pruned by `prune_ranges()` so far, sorted by their start.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn pruned_ranges(&self) -> Vec<(Version, Version)> {
+        self.pruned_ranges.lock().clone()
+    }
+
+    /// True if `version` lies in one of the ranges pruned by `prune_ranges()`, whose events are
+    /// gone even if `DBPruner::is_version_readable()` says otherwise.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn is_version_in_pruned_ranges(&self, version: Version) -> bool {
+        self.pruned_ranges
+            .lock()
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&version))
+    }
+
+    /// Prunes the events from `least_readable_version` on, up to `target_version` and at most
+    /// `max_versions` versions, commits the deletions and then attests the new boundary with the
+    /// event accumulator root hash of the first version left, as recomputed from the nodes that
+    /// survived, e.g. for external systems to trust what's retained. Since the event store pruner
+    /// doesn't keep track of its progress, the caller tells where it's at. Only applies to the
+    /// ascending direction.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_with_attestation(
+        &self,
+        least_readable_version: Version,
+        target_version: Version,
+        max_versions: u64,
+    ) -> anyhow::Result<(Version, HashValue)> {
+        ensure!(
+            self.direction == PruneDirection::Ascending,
+            "Pruning with attestation only applies to the ascending direction."
+        );
+        let boundary = max(
+            least_readable_version,
+            min(
+                target_version,
+                least_readable_version.saturating_add(max_versions),
+            ),
+        );
+        let mut db_batch = SchemaBatch::new();
+        self.prune(&mut db_batch, least_readable_version, boundary)?;
+        if let Err(e) = self.event_store.write_pruning_batch(db_batch) {
+            self.on_discarded();
+            return Err(e);
+        }
+        self.on_committed();
+        // Read back once committed, so the root reflects the nodes actually left.
+        let root_hash = self.event_store.get_event_accumulator_root_hash(boundary)?;
+        Ok((boundary, root_hash))
+    }
+
+    /// Prunes every event emitted under `event_key`, whatever its version, along with its index
+    /// entries, see `EventStore::prune_all_events_for_key()`, and returns the number of events
+    /// pruned. Unlike the rounds, it doesn't go by version range, so it's meant to be called by
+    /// hand, e.g. once the resource emitting them is destroyed. A retained key can't be pruned.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_key_fully(
+        &self,
+        event_key: &EventKey,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<usize> {
+        ensure!(
+            !self.prune_options.retain_keys.contains(event_key),
+            "Event key {:?} is retained, not pruning it.",
+            event_key
+        );
+        self.event_store
+            .prune_all_events_for_key(event_key, db_batch)
+    }
+
+    /// Prunes only the events by key index entries within the inclusive ranges of sequence
+    /// numbers of each key, see `EventStore::prune_event_key_index()`, leaving the events
+    /// themselves alone. Meant to be called by hand, e.g. after rebuilding the index. A retained
+    /// key can't be pruned.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_key_index(
+        &self,
+        sequence_range_by_event_keys: &HashMap<EventKey, (u64, u64)>,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        if let Some(event_key) = sequence_range_by_event_keys
+            .keys()
+            .find(|event_key| self.prune_options.retain_keys.contains(event_key))
+        {
+            bail!(
+                "Event key {:?} is retained, not pruning its index.",
+                event_key
+            );
+        }
+        self.event_store
+            .prune_event_key_index(sequence_range_by_event_keys, db_batch)
+    }
+
+    /// Tells what pruning the range of versions [start, end) deletes, e.g. for audits, going
+    /// through the same candidates the pruning does without deleting anything.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn preview(
+        &self,
+        start: Version,
+        end: Version,
+    ) -> anyhow::Result<PrunePreview> {
+        let mut preview = PrunePreview::default();
+        for (version, _index, event) in
+            self.event_store
+                .deleted_events(start, end, &self.prune_options)?
+        {
+            preview.add(version, *event.key(), event.sequence_number());
+        }
+        Ok(preview)
+    }
+
+    /// Tallies by key the events deleted in the range of versions [start, end).
+    fn audit_record(
+        start: Version,
+        end: Version,
+        deleted_events: &[(Version, u64, ContractEvent)],
+    ) -> AuditRecord {
+        let mut per_key_counts = HashMap::new();
+        for (_version, _index, event) in deleted_events {
+            *per_key_counts.entry(*event.key()).or_insert(0) += 1;
+        }
+        AuditRecord {
+            version_range: start..end,
+            per_key_counts,
+        }
+    }
+
+    /// Keeps the events emitted under one of `retain_keys` forever, e.g. the configuration change
+    /// events, for auditability. Along with the events themselves, their by key and by version
+    /// index entries are kept, even below the least readable version.
+    ///
+    /// The event accumulator is pruned by version range regardless, so retained events below
+    /// the least readable version can still be read, by version or by key, but no longer come
+    /// with a proof. `verify_post_prune()` is only meaningful from the least readable version on.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn with_retain_keys(mut self, retain_keys: HashSet<EventKey>) -> Self {
+        self.prune_options.retain_keys = retain_keys;
+        self
+    }
+
+    /// Keeps the latest event of each key, i.e. the one with the highest sequence number, even in
+    /// the range pruned, deleting only the older ones, e.g. for keys acting as counters whose
+    /// consumers only need the latest value. Only applies to the ascending direction. See
+    /// `EventStore::deleted_events()` for when the events kept go and what they cost.
+    pub(in crate::pruner) fn with_latest_event_per_key_kept(mut self) -> Self {
+        self.prune_options.keep_latest_per_key = true;
+        self
+    }
+
+    /// Has the events, their indices and the event accumulator pruned in parallel, see
+    /// `PruneEventsOptions::parallel`.
+    pub(in crate::pruner) fn with_parallel_sub_prunes(mut self) -> Self {
+        self.prune_options.parallel = true;
+        self
+    }
+
+    /// Has a failure pruning the event accumulator leave the round to go on with the events and
+    /// their indices, rather than failing it as a whole. The events are deleted, which leaves a
+    /// consistent DB since nothing reads the accumulator of pruned versions, while the accumulator
+    /// left behind is retried, along with the next range, in the next rounds until it succeeds.
+    /// What's pending is only kept in memory, so a restart in the meantime leaves it behind for
+    /// good. Only applies to the ascending direction.
+    pub(in crate::pruner) fn with_accumulator_failure_isolation(mut self) -> Self {
+        self.isolate_accumulator_failures = true;
+        self
+    }
+
+    /// Prunes the events and their indices of the range of versions [start, end) and then,
+    /// apart, the event accumulator from where it was left unpruned, if anywhere before `start`.
+    /// Returns the events deleted.
+    fn prune_isolating_accumulator(
+        &self,
+        db_batch: &mut SchemaBatch,
+        start: Version,
+        end: Version,
+    ) -> anyhow::Result<Vec<(Version, u64, ContractEvent)>> {
+        let mut pending_accumulator_start = self.pending_accumulator_start.lock();
+        let accumulator_start =
+            pending_accumulator_start.map_or(start, |pending| pending.min(start));
+        // The presence of events is still needed from where the accumulator is left.
+        self.event_store
+            .forget_event_presence_before(accumulator_start);
+        let options = PruneEventsOptions {
+            with_accumulator: false,
+            ..self.prune_options.clone()
+        };
+        let deleted_events = self
+            .event_store
+            .prune_events_with_options(start, end, &options, db_batch)?;
+        let mut accumulator_batch = SchemaBatch::new();
+        match self.event_store.prune_lagging_event_accumulator(
+            accumulator_start,
+            end,
+            &mut accumulator_batch,
+        ) {
+            Ok(()) => {
+                db_batch.merge(accumulator_batch);
+                *pending_accumulator_start = None;
+            }
+            Err(e) => {
+                warn!(
+                    error = ?e,
+                    accumulator_start = accumulator_start,
+                    end = end,
+                    "Failed pruning event accumulator, to be retried next round."
+                );
+                *pending_accumulator_start = Some(accumulator_start);
+            }
+        }
+        Ok(deleted_events)
+    }
+
+    /// Creates a pruner which, with `verify` set, checks what's left after pruning in
+    /// `verify_post_prune()`.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn new_with_verification(
+        event_store: Arc<EventStore>,
+        verify: bool,
+    ) -> Self {
+        EventStorePruner {
+            verify,
+            ..Self::new(event_store)
+        }
+    }
+
+    /// Once the deletions of pruning up to `version` are committed, makes sure the events at
+    /// `version` are still readable and provable, failing if anything they need was deleted.
+    /// Does nothing unless the pruner verifies.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn verify_post_prune(&self, version: Version) -> anyhow::Result<()> {
+        if !self.verify {
+            return Ok(());
+        }
+        self.event_store.verify_events_readable(version)
+    }
+
+    /// Returns the greatest version whose events are left by pruning in the descending
+    /// direction, `None` if none is, and `Version::MAX - 1` until anything is evicted.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn greatest_readable_version(&self) -> Option<Version> {
+        self.readable_end.load(Ordering::Relaxed).checked_sub(1)
+    }
+
+    /// Prunes only the events of type `type_tag` in the range of versions [start, end), e.g. high
+    /// volume log events, keeping everything else, retained events included.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_type(
+        &self,
+        type_tag: &TypeTag,
+        start: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<()> {
+        if self.prune_options.retain_keys.is_empty() {
+            return self
+                .event_store
+                .prune_events_by_type(type_tag, start, end, db_batch);
+        }
+        self.event_store.prune_events_matching(
+            start,
+            end,
+            |event| {
+                event.type_tag() == type_tag
+                    && !self.prune_options.retain_keys.contains(event.key())
+            },
+            db_batch,
+        )
+    }
+
+    /// Prunes only the events emitted under one of `keys` in the range of version [start, end),
+    /// events under other keys in the same range are kept, as are retained ones. The event
+    /// accumulator is left intact since the remaining events of each version still need it for
+    /// their proofs. Returns the highest version events are pruned at, `None` if none are, which
+    /// tells how far a sparse range actually had anything to prune.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn prune_keys(
+        &self,
+        keys: &HashSet<EventKey>,
+        start: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> anyhow::Result<Option<Version>> {
+        let mut candidate_events = vec![];
+        for candidate in self.event_store.pruning_candidates(start, end)? {
+            let (version, index, event) = candidate?;
+            if keys.contains(event.key()) && !self.prune_options.retain_keys.contains(event.key()) {
+                db_batch.delete::<EventSchema>(&(version, index))?;
+                candidate_events.push(event);
+            }
+        }
+        // Keys and sequence number ranges come out of a single pass over the candidate events.
+        let sequence_range_by_event_keys =
+            EventStore::sequence_range_by_event_keys(&candidate_events);
+        self.event_store
+            .prune_event_key_index(&sequence_range_by_event_keys, db_batch)?;
+        self.event_store.prune_events_by_version(
+            sequence_range_by_event_keys.into_keys().collect(),
+            start,
+            end,
+            db_batch,
+        )
     }
 }
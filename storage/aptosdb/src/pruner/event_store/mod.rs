@@ -1,6 +1,8 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+pub(crate) mod archive_sink;
+pub(crate) mod audit_sink;
 pub(crate) mod event_store_pruner;
 #[cfg(test)]
 mod test;
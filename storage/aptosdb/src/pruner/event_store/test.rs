@@ -1,35 +1,1588 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{pruner::*, AptosDB, ChangeSet};
+use crate::{
+    pruner::{
+        db_pruner::DBPruner,
+        db_sub_pruner::DBSubPruner,
+        deletion_order::DeletionOrder,
+        event_store::{
+            archive_sink::ArchiveSink,
+            audit_sink::{AuditRecord, AuditSink},
+            event_store_pruner::{EventStorePruner, PruneDirection, PrunePreview},
+        },
+        ledger_store::ledger_store_pruner::{LedgerPruner, STOP_FLAG_CHECK_INTERVAL},
+        prune_strategy::FixedBatchStrategy,
+        transaction_store::test::create_ledger_pruner,
+        *,
+    },
+    schema::{
+        event::EventSchema, event_accumulator::EventAccumulatorSchema,
+        event_by_key::EventByKeySchema, event_by_version::EventByVersionSchema,
+        write_set::WriteSetSchema,
+    },
+    test_helper, AptosDB, ChangeSet, EventStore,
+};
+use aptos_crypto::{
+    hash::{CryptoHash, EventAccumulatorHasher},
+    HashValue,
+};
 use aptos_proptest_helpers::Index;
 use aptos_temppath::TempPath;
 use aptos_types::{
+    account_address::AccountAddress,
     contract_event::ContractEvent,
+    event::EventKey,
+    proof::{accumulator::InMemoryAccumulator, position::Position},
     proptest_types::{AccountInfoUniverse, ContractEventGen},
+    transaction::Transaction,
+    write_set::WriteSet,
 };
+use move_deps::move_core_types::language_storage::TypeTag;
 use proptest::{collection::vec, prelude::*, proptest};
+use schemadb::{schema::Schema, ReadOptions, SchemaBatch};
+use std::collections::{HashMap, HashSet};
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10))]
+
+    #[test]
+    fn test_event_store_pruner(
+        mut universe in any_with::<AccountInfoUniverse>(3),
+        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 0..=2), 0..100),
+    ) {
+        let event_batches = gen_batches
+            .into_iter()
+            .map(|gens| {
+                gens.into_iter()
+                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
+                    .collect()
+            })
+            .collect();
+
+        verify_event_store_pruner(event_batches);
+    }
+
+    #[test]
+    fn test_event_store_pruner_target_below_least_readable_version(
+        mut universe in any_with::<AccountInfoUniverse>(3),
+        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 0..=2), 0..100),
+    ) {
+        let event_batches = gen_batches
+            .into_iter()
+            .map(|gens| {
+                gens.into_iter()
+                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
+                    .collect()
+            })
+            .collect();
+
+        verify_event_store_pruner_target_below_least_readable_version(event_batches);
+    }
+
+    #[test]
+    fn test_event_store_pruner_dry_run(
+        mut universe in any_with::<AccountInfoUniverse>(3),
+        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 0..=2), 0..100),
+    ) {
+        let event_batches = gen_batches
+            .into_iter()
+            .map(|gens| {
+                gens.into_iter()
+                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
+                    .collect()
+            })
+            .collect();
+
+        verify_event_store_pruner_dry_run(event_batches);
+    }
+
+    #[test]
+    fn test_event_store_pruner_initialize_and_repair(
+        mut universe in any_with::<AccountInfoUniverse>(3),
+        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 0..=2), 0..100),
+    ) {
+        let event_batches = gen_batches
+            .into_iter()
+            .map(|gens| {
+                gens.into_iter()
+                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
+                    .collect()
+            })
+            .collect();
+
+        verify_event_store_pruner_initialize_and_repair(event_batches);
+    }
+
+    #[test]
+    fn test_event_store_pruner_keeps_proofs_at_target_version(
+        mut universe in any_with::<AccountInfoUniverse>(3),
+        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 1..=2), 3..100),
+    ) {
+        let event_batches = gen_batches
+            .into_iter()
+            .map(|gens| {
+                gens.into_iter()
+                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
+                    .collect()
+            })
+            .collect();
+
+        verify_event_store_pruner_keeps_proofs_at_target_version(event_batches);
+    }
+}
+
+#[test]
+fn test_event_store_pruner_prune_keys() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let pruner = EventStorePruner::new(Arc::clone(&aptos_db.event_store));
+    let pruned_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let kept_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    let num_versions = 10;
+
+    // Write one event under each key for every version
+    let events: Vec<Vec<ContractEvent>> = (0..num_versions)
+        .map(|seq| {
+            vec![
+                ContractEvent::new(pruned_key, seq, TypeTag::Bool, vec![]),
+                ContractEvent::new(kept_key, seq, TypeTag::Bool, vec![]),
+            ]
+        })
+        .collect();
+    let mut cs = ChangeSet::new();
+    for (version, events_for_version) in events.iter().enumerate() {
+        event_store
+            .put_events(version as u64, events_for_version, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    assert_eq!(
+        pruner
+            .prune_keys(&HashSet::from([pruned_key]), 0, num_versions, &mut db_batch)
+            .unwrap(),
+        Some(num_versions - 1)
+    );
+    aptos_db.db.write_schemas(db_batch).unwrap();
+
+    // Only the events under the kept key are left
+    for version in 0..num_versions {
+        let (pruned_event, kept_event) =
+            (&events[version as usize][0], &events[version as usize][1]);
+        assert_eq!(
+            event_store.get_events_by_version(version).unwrap(),
+            vec![kept_event.clone()]
+        );
+        assert!(event_store
+            .get_txn_ver_by_seq_num(pruned_event.key(), pruned_event.sequence_number())
+            .is_err());
+        assert_eq!(
+            event_store
+                .get_txn_ver_by_seq_num(kept_event.key(), kept_event.sequence_number())
+                .unwrap(),
+            version
+        );
+        assert!(event_store
+            .get_latest_sequence_number(version, &pruned_key)
+            .unwrap()
+            .is_none());
+        assert!(event_store
+            .get_latest_sequence_number(version, &kept_key)
+            .unwrap()
+            .is_some());
+    }
+}
+
+#[test]
+fn test_event_store_pruner_compact_after_prune() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 10;
+    let create_pruner = |compact_after_prune| {
+        LedgerPruner::new_with_dry_run(
+            Arc::clone(&aptos_db.db),
+            Arc::clone(&aptos_db.transaction_store),
+            Arc::clone(&aptos_db.event_store),
+            Arc::clone(&aptos_db.ledger_store),
+            Arc::new(AtomicBool::new(false)), /* stop_flag */
+            0,                                /* prune_window */
+            false,                            /* dry_run */
+            compact_after_prune,
+            Arc::new(FixedBatchStrategy),
+        )
+    };
+
+    let events: Vec<Vec<ContractEvent>> = (0..num_versions)
+        .map(|seq| vec![ContractEvent::new(key, seq, TypeTag::Bool, vec![])])
+        .collect();
+    let mut cs = ChangeSet::new();
+    for (version, events_for_version) in events.iter().enumerate() {
+        event_store
+            .put_events(version as u64, events_for_version, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let pruner = create_pruner(true);
+    pruner.set_target_version(num_versions / 2);
+    let begin = pruner.least_readable_version();
+    let mut db_batch = SchemaBatch::new();
+    let end = pruner.prune(&mut db_batch, num_versions).unwrap().version;
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // The range compacted is exactly the one pruned, and the events left are intact.
+    assert_eq!(
+        pruner.compact_pruned_range(begin, end).unwrap(),
+        Some((0, num_versions / 2))
+    );
+    assert_eq!(pruner.compact_pruned_range(end, end).unwrap(), None);
+    for version in 0..num_versions {
+        assert_eq!(
+            event_store
+                .get_events_by_version(version)
+                .unwrap()
+                .is_empty(),
+            version < num_versions / 2
+        );
+    }
+
+    // Nothing is compacted unless asked.
+    assert_eq!(
+        create_pruner(false)
+            .compact_pruned_range(begin, end)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_event_store_pruner_verify_post_prune() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let pruner = EventStorePruner::new_with_verification(Arc::clone(event_store), true);
+    let unverified_pruner = EventStorePruner::new(Arc::clone(event_store));
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 10;
+
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 5).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    pruner.verify_post_prune(5).unwrap();
+    // Versions pruned as a whole have nothing left to verify.
+    pruner.verify_post_prune(4).unwrap();
+
+    // Over-pruning the events of the boundary version leaves their accumulator behind.
+    let mut db_batch = SchemaBatch::new();
+    event_store.prune_event_schema(5, 6, &mut db_batch).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    assert!(pruner.verify_post_prune(5).is_err());
+    unverified_pruner.verify_post_prune(5).unwrap();
+
+    // Neither are events left without their index.
+    let mut db_batch = SchemaBatch::new();
+    db_batch.delete::<EventByKeySchema>(&(key, 6)).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    assert!(pruner.verify_post_prune(6).is_err());
+    pruner.verify_post_prune(7).unwrap();
+}
+
+#[test]
+fn test_event_store_pruner_directions() {
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 10;
+    for direction in [PruneDirection::Ascending, PruneDirection::Descending] {
+        let tmp_dir = TempPath::new();
+        let aptos_db = AptosDB::new_for_test(&tmp_dir);
+        let event_store = &aptos_db.event_store;
+        let pruner = EventStorePruner::new_with_direction(Arc::clone(event_store), direction);
+        let mut cs = ChangeSet::new();
+        for version in 0..num_versions {
+            let events = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+            event_store.put_events(version, &events, &mut cs).unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+
+        // Each direction evicts its end of the history first.
+        let rounds = match direction {
+            PruneDirection::Ascending => [(0, 3), (3, 6)],
+            PruneDirection::Descending => [(7, 10), (4, 7)],
+        };
+        for (begin, end) in rounds {
+            let mut db_batch = SchemaBatch::new();
+            pruner.prune(&mut db_batch, begin, end).unwrap();
+            aptos_db.db.write_schemas(db_batch).unwrap();
+            pruner.on_committed();
+        }
+        let (readable, greatest_readable_version) = match direction {
+            PruneDirection::Ascending => (6..num_versions, Some(Version::MAX - 1)),
+            PruneDirection::Descending => (0..4, Some(3)),
+        };
+        assert_eq!(
+            pruner.greatest_readable_version(),
+            greatest_readable_version
+        );
+        for version in 0..num_versions {
+            let events = event_store.get_events_by_version(version).unwrap();
+            assert_eq!(events.is_empty(), !readable.contains(&version));
+            assert_eq!(
+                event_store.get_txn_ver_by_seq_num(&key, version).is_ok(),
+                readable.contains(&version)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_event_store_pruner_retain_keys() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let retained_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let other_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    let pruner = EventStorePruner::new(Arc::clone(event_store))
+        .with_retain_keys(HashSet::from([retained_key]));
+    let num_versions = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![
+            ContractEvent::new(other_key, version, TypeTag::Bool, vec![]),
+            ContractEvent::new(retained_key, version, TypeTag::Bool, vec![]),
+        ];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let target_version = 9;
+    for (begin, end) in [(0, 3), (3, 6), (6, target_version)] {
+        let mut db_batch = SchemaBatch::new();
+        prune_and_verify_deletions(&pruner, event_store, &mut db_batch, begin, end);
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+    }
+    // Sweeping what's left below the least readable version keeps them too.
+    let mut db_batch = SchemaBatch::new();
+    pruner.repair(&mut db_batch, target_version).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+
+    for version in 0..num_versions {
+        let events = event_store.get_events_by_version(version).unwrap();
+        let keys: Vec<_> = events.iter().map(|event| *event.key()).collect();
+        if version < target_version {
+            assert_eq!(keys, vec![retained_key]);
+            assert!(event_store
+                .get_txn_ver_by_seq_num(&other_key, version)
+                .is_err());
+        } else {
+            assert_eq!(keys, vec![other_key, retained_key]);
+        }
+        assert_eq!(
+            event_store
+                .get_txn_ver_by_seq_num(&retained_key, version)
+                .unwrap(),
+            version
+        );
+        assert!(aptos_db
+            .db
+            .get::<EventByVersionSchema>(&(retained_key, version, version))
+            .unwrap()
+            .is_some());
+    }
+    // The accumulator is pruned regardless.
+    assert!(event_store
+        .get_event_with_proof_by_version_and_index(0, 1)
+        .is_err());
+}
+
+/// Keeps the events archived in memory, or fails if `fail` is set.
+#[derive(Default)]
+struct MemoryArchiveSink {
+    events: Mutex<Vec<ContractEvent>>,
+    fail: bool,
+}
+
+impl ArchiveSink for MemoryArchiveSink {
+    fn archive_events(&self, events: &[ContractEvent]) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.fail, "Archive unavailable.");
+        self.events.lock().extend_from_slice(events);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_event_store_pruner_archive_sink() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 6;
+    let mut cs = ChangeSet::new();
+    let mut events = vec![];
+    for version in 0..num_versions {
+        let events_for_version = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        event_store
+            .put_events(version, &events_for_version, &mut cs)
+            .unwrap();
+        events.extend(events_for_version);
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // The events are archived, then deleted.
+    let archive_sink = Arc::new(MemoryArchiveSink::default());
+    let pruner =
+        EventStorePruner::new(Arc::clone(event_store)).with_archive_sink(archive_sink.clone());
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 3).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(*archive_sink.events.lock(), events[..3]);
+    for version in 0..3 {
+        assert!(event_store
+            .get_events_by_version(version)
+            .unwrap()
+            .is_empty());
+    }
+
+    // Nothing is deleted if archiving fails.
+    let pruner = EventStorePruner::new(Arc::clone(event_store)).with_archive_sink(Arc::new(
+        MemoryArchiveSink {
+            fail: true,
+            ..Default::default()
+        },
+    ));
+    let mut db_batch = SchemaBatch::new();
+    assert!(pruner.prune(&mut db_batch, 3, num_versions).is_err());
+    assert!(db_batch.is_empty());
+    for version in 3..num_versions {
+        assert_eq!(event_store.get_events_by_version(version).unwrap().len(), 1);
+    }
+}
+
+/// Keeps the audit records in memory.
+#[derive(Default)]
+struct MemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, record: AuditRecord) {
+        self.records.lock().push(record);
+    }
+}
+
+#[test]
+fn test_event_store_pruner_audit_sink() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let other_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 6;
+    let mut cs = ChangeSet::new();
+    let mut inserted = vec![HashMap::new(); num_versions as usize];
+    for version in 0..num_versions {
+        // The other key has events at the even versions only.
+        let mut events = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        if version % 2 == 0 {
+            events.push(ContractEvent::new(
+                other_key,
+                version / 2,
+                TypeTag::Bool,
+                vec![],
+            ));
+        }
+        for event in &events {
+            *inserted[version as usize].entry(*event.key()).or_insert(0) += 1;
+        }
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let expected_counts = |start: Version, end: Version| {
+        let mut counts = HashMap::new();
+        for per_key_counts in &inserted[start as usize..end as usize] {
+            for (key, count) in per_key_counts {
+                *counts.entry(*key).or_insert(0) += count;
+            }
+        }
+        counts
+    };
+
+    let audit_sink = Arc::new(MemoryAuditSink::default());
+    let pruner = EventStorePruner::new(Arc::clone(event_store)).with_audit_sink(audit_sink.clone());
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 3).unwrap();
+    pruner.prune(&mut db_batch, 3, 5).unwrap();
+    // Nothing is told before the deletions are committed.
+    assert!(audit_sink.records.lock().is_empty());
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(
+        *audit_sink.records.lock(),
+        vec![
+            AuditRecord {
+                version_range: 0..3,
+                per_key_counts: expected_counts(0, 3),
+            },
+            AuditRecord {
+                version_range: 3..5,
+                per_key_counts: expected_counts(3, 5),
+            },
+        ]
+    );
+    assert_eq!(expected_counts(0, 3)[&other_key], 2);
+
+    // Told once only.
+    pruner.on_committed();
+    assert_eq!(audit_sink.records.lock().len(), 2);
+
+    // Never told if the deletions are dropped, e.g. on dry run.
+    pruner.prune(&mut SchemaBatch::new(), 5, 6).unwrap();
+    pruner.on_discarded();
+    pruner.on_committed();
+    assert_eq!(audit_sink.records.lock().len(), 2);
+}
+
+#[test]
+fn test_ledger_pruner_audits_only_committed_batches() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 4 * STOP_FLAG_CHECK_INTERVAL;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let audit_sink = Arc::new(MemoryAuditSink::default());
+    let pruner = create_ledger_pruner(&aptos_db)
+        .with_event_store_pruner(Arc::new(
+            EventStorePruner::new(Arc::clone(event_store)).with_audit_sink(audit_sink.clone()),
+        ))
+        .with_commit_threshold(STOP_FLAG_CHECK_INTERVAL as usize / 2);
+    pruner.set_target_version(num_versions);
+    let mut first_batch = SchemaBatch::new();
+    pruner
+        .prune(&mut first_batch, STOP_FLAG_CHECK_INTERVAL)
+        .unwrap();
+    // A round over the commit threshold doesn't tell about the batch handed over before.
+    let mut second_batch = SchemaBatch::new();
+    pruner.prune(&mut second_batch, num_versions).unwrap();
+    assert!(audit_sink.records.lock().is_empty());
+
+    aptos_db.db.write_schemas(first_batch).unwrap();
+    aptos_db.db.write_schemas(second_batch).unwrap();
+    pruner.on_committed();
+    let version_ranges: Vec<_> = audit_sink
+        .records
+        .lock()
+        .iter()
+        .map(|record| record.version_range.clone())
+        .collect();
+    assert_eq!(
+        version_ranges,
+        (0..4)
+            .map(|chunk| chunk * STOP_FLAG_CHECK_INTERVAL..(chunk + 1) * STOP_FLAG_CHECK_INTERVAL)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_event_store_pruner_archive_db() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let archive_tmp_dir = TempPath::new();
+    let archive_db = AptosDB::new_for_test(&archive_tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 6;
+    let mut cs = ChangeSet::new();
+    let mut events = vec![];
+    for version in 0..num_versions {
+        let events_for_version = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        event_store
+            .put_events(version, &events_for_version, &mut cs)
+            .unwrap();
+        events.push(events_for_version);
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let pruner = |dry_run| {
+        LedgerPruner::new_with_dry_run(
+            Arc::clone(&aptos_db.db),
+            Arc::clone(&aptos_db.transaction_store),
+            Arc::clone(event_store),
+            Arc::clone(&aptos_db.ledger_store),
+            Arc::new(AtomicBool::new(false)), /* stop_flag */
+            0,                                /* prune_window */
+            dry_run,
+            false, /* compact_after_prune */
+            Arc::new(FixedBatchStrategy),
+        )
+        .with_archive_db(Arc::clone(&archive_db.db))
+    };
+
+    // A dry run archives nothing.
+    let dry_run_pruner = pruner(true);
+    dry_run_pruner.set_target_version(3);
+    dry_run_pruner.prune(&mut SchemaBatch::new(), 100).unwrap();
+    assert!(archive_db
+        .event_store
+        .get_events_by_version(0)
+        .unwrap()
+        .is_empty());
+
+    let pruner = pruner(false);
+    pruner.set_target_version(3);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    // Archived before the deletion is even committed.
+    for version in 0..3 {
+        assert_eq!(
+            archive_db
+                .event_store
+                .get_events_by_version(version)
+                .unwrap(),
+            events[version as usize]
+        );
+    }
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // Gone from the primary DB, the events and their indices are all in the archive.
+    for version in 0..num_versions {
+        let seq_num = version;
+        assert_eq!(
+            event_store
+                .get_events_by_version(version)
+                .unwrap()
+                .is_empty(),
+            version < 3
+        );
+        assert_eq!(
+            archive_db
+                .db
+                .get::<EventByKeySchema>(&(key, seq_num))
+                .unwrap(),
+            if version < 3 {
+                Some((version, 0))
+            } else {
+                None
+            }
+        );
+        assert_eq!(
+            archive_db
+                .db
+                .get::<EventByVersionSchema>(&(key, version, seq_num))
+                .unwrap(),
+            if version < 3 { Some(0) } else { None }
+        );
+    }
+}
+
+#[test]
+fn test_event_store_pruner_prune_type() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+    let log_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let other_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    let num_versions = 6;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![
+            ContractEvent::new(log_key, version, TypeTag::Bool, vec![]),
+            ContractEvent::new(other_key, version, TypeTag::U64, vec![]),
+        ];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    pruner
+        .prune_type(&TypeTag::Bool, 2, 5, &mut db_batch)
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+
+    for version in 0..num_versions {
+        let is_pruned = (2..5).contains(&version);
+        let types: Vec<_> = event_store
+            .get_events_by_version(version)
+            .unwrap()
+            .iter()
+            .map(|event| event.type_tag().clone())
+            .collect();
+        if is_pruned {
+            assert_eq!(types, vec![TypeTag::U64]);
+        } else {
+            assert_eq!(types, vec![TypeTag::Bool, TypeTag::U64]);
+        }
+        assert_eq!(
+            event_store
+                .get_txn_ver_by_seq_num(&log_key, version)
+                .is_ok(),
+            !is_pruned
+        );
+        assert_eq!(
+            aptos_db
+                .db
+                .get::<EventByVersionSchema>(&(log_key, version, version))
+                .unwrap()
+                .is_some(),
+            !is_pruned
+        );
+        assert_eq!(
+            event_store
+                .get_txn_ver_by_seq_num(&other_key, version)
+                .unwrap(),
+            version
+        );
+    }
+}
+
+#[test]
+fn test_event_store_pruner_prune_key_fully() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let destroyed_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let sibling_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    let retained_key = EventKey::new_from_address(&AccountAddress::random(), 2);
+    let pruner = EventStorePruner::new(Arc::clone(event_store))
+        .with_retain_keys(HashSet::from([retained_key]));
+    let num_versions = 20;
+    let events_per_version = 3;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let mut events: Vec<_> = (0..events_per_version)
+            .map(|i| {
+                let seq_num = version * events_per_version + i;
+                ContractEvent::new(destroyed_key, seq_num, TypeTag::Bool, vec![])
+            })
+            .collect();
+        events.push(ContractEvent::new(
+            sibling_key,
+            version,
+            TypeTag::Bool,
+            vec![],
+        ));
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    assert_eq!(
+        pruner
+            .prune_key_fully(&destroyed_key, &mut db_batch)
+            .unwrap(),
+        (num_versions * events_per_version) as usize
+    );
+    aptos_db.db.write_schemas(db_batch).unwrap();
+
+    for version in 0..num_versions {
+        let keys: Vec<_> = event_store
+            .get_events_by_version(version)
+            .unwrap()
+            .iter()
+            .map(|event| *event.key())
+            .collect();
+        assert_eq!(keys, vec![sibling_key]);
+        for seq_num in version * events_per_version..(version + 1) * events_per_version {
+            assert!(event_store
+                .get_txn_ver_by_seq_num(&destroyed_key, seq_num)
+                .is_err());
+            assert!(aptos_db
+                .db
+                .get::<EventByVersionSchema>(&(destroyed_key, version, seq_num))
+                .unwrap()
+                .is_none());
+        }
+        // The sibling is left as is, proofs included.
+        assert_eq!(
+            event_store
+                .get_txn_ver_by_seq_num(&sibling_key, version)
+                .unwrap(),
+            version
+        );
+        assert!(event_store
+            .get_event_with_proof_by_version_and_index(version, events_per_version)
+            .is_ok());
+    }
+
+    // Nothing is left to prune, and a retained key isn't pruned.
+    let mut db_batch = SchemaBatch::new();
+    assert_eq!(
+        pruner
+            .prune_key_fully(&destroyed_key, &mut db_batch)
+            .unwrap(),
+        0
+    );
+    assert!(pruner
+        .prune_key_fully(&retained_key, &mut db_batch)
+        .is_err());
+    assert!(db_batch.is_empty());
+}
+
+#[test]
+fn test_event_store_pruner_prune_key_index() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let retained_key = EventKey::new_from_address(&AccountAddress::random(), 1);
+    let pruner = EventStorePruner::new(Arc::clone(event_store))
+        .with_retain_keys(HashSet::from([retained_key]));
+    let num_versions = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![
+            ContractEvent::new(event_key, version, TypeTag::Bool, vec![]),
+            ContractEvent::new(retained_key, version, TypeTag::Bool, vec![]),
+        ];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // Only the index entries of sequence numbers [2, 6] go.
+    let mut db_batch = SchemaBatch::new();
+    pruner
+        .prune_key_index(&HashMap::from([(event_key, (2, 6))]), &mut db_batch)
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+
+    for version in 0..num_versions {
+        let indexed = event_store
+            .get_txn_ver_by_seq_num(&event_key, version)
+            .is_ok();
+        assert_eq!(indexed, !(2..=6).contains(&version));
+        // The events themselves are left as is, by version entries and proofs included.
+        assert_eq!(event_store.get_events_by_version(version).unwrap().len(), 2);
+        assert!(aptos_db
+            .db
+            .get::<EventByVersionSchema>(&(event_key, version, version))
+            .unwrap()
+            .is_some());
+        assert!(event_store
+            .get_event_with_proof_by_version_and_index(version, 0)
+            .is_ok());
+    }
+
+    // A retained key's index isn't pruned.
+    let mut db_batch = SchemaBatch::new();
+    assert!(pruner
+        .prune_key_index(&HashMap::from([(retained_key, (0, 9))]), &mut db_batch)
+        .is_err());
+    assert!(db_batch.is_empty());
+}
+
+#[test]
+fn test_event_store_deletion_order() {
+    assert_eq!(DeletionOrder::Ascending.ranges(0, 3500), vec![(0, 3500)]);
+    assert_eq!(
+        DeletionOrder::Descending.ranges(0, 3500),
+        vec![(3000, 3500), (2000, 3000), (1000, 2000), (0, 1000)]
+    );
+    assert_eq!(
+        DeletionOrder::Interleaved.ranges(0, 3500),
+        vec![(0, 1000), (3000, 3500), (1000, 2000), (2000, 3000)]
+    );
+    // Not worth splitting up.
+    assert_eq!(DeletionOrder::Interleaved.ranges(5, 800), vec![(5, 800)]);
+
+    // The same is pruned whatever the order.
+    let num_versions = 2500;
+    let end = 2100;
+    for deletion_order in [
+        DeletionOrder::Ascending,
+        DeletionOrder::Descending,
+        DeletionOrder::Interleaved,
+    ] {
+        let tmp_dir = TempPath::new();
+        let aptos_db = AptosDB::new_for_test(&tmp_dir);
+        let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+        let mut cs = ChangeSet::new();
+        for version in 0..num_versions {
+            let events = vec![ContractEvent::new(
+                event_key,
+                version,
+                TypeTag::Bool,
+                vec![],
+            )];
+            aptos_db
+                .event_store
+                .put_events(version, &events, &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+        let event_store =
+            EventStore::new(Arc::clone(&aptos_db.db)).with_deletion_order(deletion_order);
+        let mut db_batch = SchemaBatch::new();
+        event_store
+            .prune_event_schema(0, end, &mut db_batch)
+            .unwrap();
+        event_store
+            .prune_events_by_version(HashSet::from([event_key]), 0, end, &mut db_batch)
+            .unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+
+        for version in 0..num_versions {
+            let is_pruned = version < end;
+            assert_eq!(
+                event_store
+                    .get_events_by_version(version)
+                    .unwrap()
+                    .is_empty(),
+                is_pruned
+            );
+            assert_eq!(
+                aptos_db
+                    .db
+                    .get::<EventByVersionSchema>(&(event_key, version, version))
+                    .unwrap()
+                    .is_none(),
+                is_pruned
+            );
+        }
+    }
+}
+
+#[test]
+fn test_event_store_pruner_verifies_event_deletions() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 6;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![ContractEvent::new(
+            event_key,
+            version,
+            TypeTag::Bool,
+            vec![],
+        )];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    event_store.verify_event_deletions(&db_batch).unwrap();
+
+    // An index entry deleted without its event.
+    db_batch
+        .delete::<EventByKeySchema>(&(event_key, 5))
+        .unwrap();
+    assert!(event_store.verify_event_deletions(&db_batch).is_err());
+
+    // An event deleted without its by version index entry.
+    let mut db_batch = SchemaBatch::new();
+    db_batch.delete::<EventSchema>(&(4, 0)).unwrap();
+    db_batch
+        .delete::<EventByKeySchema>(&(event_key, 4))
+        .unwrap();
+    assert!(event_store.verify_event_deletions(&db_batch).is_err());
+    db_batch
+        .delete::<EventByVersionSchema>(&(event_key, 4, 4))
+        .unwrap();
+    event_store.verify_event_deletions(&db_batch).unwrap();
+}
+
+#[test]
+fn test_event_store_pruner_preview() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let [every_key, even_key, retained_key] =
+        [(); 3].map(|_| EventKey::new_from_address(&AccountAddress::random(), 0));
+    let pruner = EventStorePruner::new(Arc::clone(event_store))
+        .with_retain_keys(HashSet::from([retained_key]));
+    let mut cs = ChangeSet::new();
+    for version in 0..6 {
+        let mut events = vec![
+            ContractEvent::new(every_key, version, TypeTag::Bool, vec![]),
+            ContractEvent::new(retained_key, version, TypeTag::Bool, vec![]),
+        ];
+        if version % 2 == 0 {
+            events.push(ContractEvent::new(
+                even_key,
+                version / 2,
+                TypeTag::Bool,
+                vec![],
+            ));
+        }
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
 
-proptest! {
-    #![proptest_config(ProptestConfig::with_cases(10))]
+    let preview = pruner.preview(0, 4).unwrap();
+    assert_eq!(
+        preview,
+        PrunePreview {
+            events_by_key: HashMap::from([(every_key, (4, 0, 3)), (even_key, (2, 0, 1))]),
+            version_span: Some((0, 3)),
+        }
+    );
+    assert_eq!(preview.num_events(), 6);
+    // Previewing leaves everything in place.
+    assert_eq!(pruner.preview(0, 4).unwrap(), preview);
 
-    #[test]
-    fn test_event_store_pruner(
-        mut universe in any_with::<AccountInfoUniverse>(3),
-        gen_batches in vec(vec((any::<Index>(), any::<ContractEventGen>()), 0..=2), 0..100),
-    ) {
-        let event_batches = gen_batches
-            .into_iter()
-            .map(|gens| {
-                gens.into_iter()
-                    .map(|(index, gen)| gen.materialize(*index, &mut universe))
-                    .collect()
-            })
+    // The events by key entries actually deleted add up to the preview.
+    let events_by_key = || -> HashSet<(EventKey, u64)> {
+        let mut iter = aptos_db
+            .db
+            .iter::<EventByKeySchema>(ReadOptions::default())
+            .unwrap();
+        iter.seek_to_first();
+        iter.map(|row| row.unwrap().0).collect()
+    };
+    let before = events_by_key();
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    let mut deleted: HashMap<EventKey, (usize, u64, u64)> = HashMap::new();
+    for (event_key, seq_num) in before.difference(&events_by_key()) {
+        let (count, min_seq_num, max_seq_num) =
+            deleted.entry(*event_key).or_insert((0, u64::MAX, 0));
+        *count += 1;
+        *min_seq_num = (*min_seq_num).min(*seq_num);
+        *max_seq_num = (*max_seq_num).max(*seq_num);
+    }
+    assert_eq!(deleted, preview.events_by_key);
+    assert!(pruner.preview(0, 4).unwrap().events_by_key.is_empty());
+}
+
+#[test]
+fn test_event_store_pruner_prune_twice() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        let events = vec![ContractEvent::new(
+            event_key,
+            version,
+            TypeTag::Bool,
+            vec![],
+        )];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    assert!(!db_batch.is_empty());
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // As if the progress wasn't persisted, the same range is pruned again, with nothing left to
+    // delete.
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    assert!(db_batch.is_empty());
+    for version in 0..4 {
+        verify_events_not_in_store(version, event_store);
+    }
+    event_store.verify_events_readable(4).unwrap();
+}
+
+#[test]
+fn test_event_store_pruner_without_event_accumulator() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let events: Vec<Vec<ContractEvent>> = (0..10)
+        .map(|version| {
+            vec![ContractEvent::new(
+                event_key,
+                version,
+                TypeTag::Bool,
+                vec![],
+            )]
+        })
+        .collect();
+    let mut cs = ChangeSet::new();
+    for (version, version_events) in events.iter().enumerate() {
+        event_store
+            .put_events(version as Version, version_events, &mut cs)
+            .unwrap();
+    }
+    // As in a DB from before event accumulators existed, opened afresh.
+    cs.batch
+        .delete_range::<EventAccumulatorSchema>(
+            &(0, Position::from_inorder_index(0)),
+            &(Version::MAX, Position::from_inorder_index(0)),
+        )
+        .unwrap();
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let event_store = &Arc::new(EventStore::new(Arc::clone(&aptos_db.db)));
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    for version in 0..4 {
+        verify_events_not_in_store(version, event_store);
+        verify_event_by_key_not_in_store(&events, version, event_store);
+        verify_event_by_version_not_in_store(&events, version, event_store);
+    }
+    for version in 4..10 {
+        verify_events_in_store(&events, version, event_store);
+        verify_event_by_key_in_store(&events, version, event_store);
+    }
+}
+
+#[test]
+fn test_event_store_pruner_prune_ranges() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let events: Vec<Vec<ContractEvent>> = (0..10)
+        .map(|version| {
+            vec![ContractEvent::new(
+                event_key,
+                version,
+                TypeTag::Bool,
+                vec![],
+            )]
+        })
+        .collect();
+    let mut cs = ChangeSet::new();
+    for (version, version_events) in events.iter().enumerate() {
+        event_store
+            .put_events(version as Version, version_events, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+
+    // Overlapping ranges are refused, leaving everything in place.
+    assert!(pruner.prune_ranges(&[(2, 5), (4, 6)]).is_err());
+    assert!(pruner.prune_ranges(&[(5, 4)]).is_err());
+    for version in 0..10 {
+        verify_events_in_store(&events, version, event_store);
+    }
+
+    pruner.prune_ranges(&[(6, 8), (2, 4)]).unwrap();
+    for version in 0..10 {
+        if (2..4).contains(&version) || (6..8).contains(&version) {
+            verify_events_not_in_store(version, event_store);
+            verify_event_by_key_not_in_store(&events, version, event_store);
+            assert!(pruner.is_version_in_pruned_ranges(version));
+        } else {
+            verify_events_in_store(&events, version, event_store);
+            verify_event_by_key_in_store(&events, version, event_store);
+            assert!(!pruner.is_version_in_pruned_ranges(version));
+        }
+    }
+    assert_eq!(pruner.pruned_ranges(), vec![(2, 4), (6, 8)]);
+}
+
+#[test]
+fn test_event_store_pruner_keeps_latest_event_per_key() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let [counter_key, sparse_key, later_key] =
+        [(); 3].map(|_| EventKey::new_from_address(&AccountAddress::random(), 0));
+    let mut events_by_version = vec![vec![]; 10];
+    for version in 0..6 {
+        events_by_version[version].push(ContractEvent::new(
+            counter_key,
+            version as u64,
+            TypeTag::Bool,
+            vec![],
+        ));
+    }
+    events_by_version[1].push(ContractEvent::new(sparse_key, 0, TypeTag::Bool, vec![]));
+    events_by_version[2].push(ContractEvent::new(sparse_key, 1, TypeTag::Bool, vec![]));
+    // The latest event of this key is past the range pruned.
+    events_by_version[3].push(ContractEvent::new(later_key, 0, TypeTag::Bool, vec![]));
+    events_by_version[8].push(ContractEvent::new(later_key, 1, TypeTag::Bool, vec![]));
+    let mut cs = ChangeSet::new();
+    for (version, events) in events_by_version.iter().enumerate() {
+        event_store
+            .put_events(version as Version, events, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = EventStorePruner::new(Arc::clone(event_store)).with_latest_event_per_key_kept();
+
+    let preview = pruner.preview(0, 6).unwrap();
+    assert_eq!(
+        preview.events_by_key,
+        HashMap::from([
+            (counter_key, (5, 0, 4)),
+            (sparse_key, (1, 0, 0)),
+            (later_key, (1, 0, 0))
+        ])
+    );
+    let mut db_batch = SchemaBatch::new();
+    prune_and_verify_deletions(&pruner, event_store, &mut db_batch, 0, 6);
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    let is_left = |event_key: &EventKey, seq_num: u64| {
+        event_store
+            .get_txn_ver_by_seq_num(event_key, seq_num)
+            .is_ok()
+    };
+    for seq_num in 0..5 {
+        assert!(!is_left(&counter_key, seq_num));
+    }
+    assert!(is_left(&counter_key, 5));
+    assert!(!is_left(&sparse_key, 0));
+    assert!(is_left(&sparse_key, 1));
+    assert!(!is_left(&later_key, 0));
+    assert!(is_left(&later_key, 1));
+    // The events kept are still there by version as well.
+    assert_eq!(
+        event_store.get_events_by_version(5).unwrap(),
+        events_by_version[5]
+    );
+    assert_eq!(
+        event_store.get_events_by_version(2).unwrap(),
+        vec![events_by_version[2][1].clone()]
+    );
+    assert!(event_store.get_events_by_version(3).unwrap().is_empty());
+}
+
+#[test]
+fn test_event_store_pruner_prunes_superseded_latest_events() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let [counter_key, other_key] =
+        [(); 2].map(|_| EventKey::new_from_address(&AccountAddress::random(), 0));
+    let put_events = |version: Version, events: Vec<ContractEvent>| {
+        let mut cs = ChangeSet::new();
+        event_store.put_events(version, &events, &mut cs).unwrap();
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+    };
+    let counter_event =
+        |seq_num: u64| ContractEvent::new(counter_key, seq_num, TypeTag::Bool, vec![]);
+    let other_event = ContractEvent::new(other_key, 0, TypeTag::Bool, vec![]);
+    put_events(1, vec![counter_event(0)]);
+    put_events(2, vec![counter_event(1), other_event.clone()]);
+    put_events(3, vec![counter_event(2)]);
+    let pruner = EventStorePruner::new(Arc::clone(event_store)).with_latest_event_per_key_kept();
+    let is_left = |event_key: &EventKey, seq_num: u64| {
+        event_store
+            .get_txn_ver_by_seq_num(event_key, seq_num)
+            .is_ok()
+    };
+
+    let mut db_batch = SchemaBatch::new();
+    prune_and_verify_deletions(&pruner, event_store, &mut db_batch, 1, 5);
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(
+        (0..3)
+            .map(|seq_num| is_left(&counter_key, seq_num))
+            .collect::<Vec<_>>(),
+        vec![false, false, true]
+    );
+    assert!(is_left(&other_key, 0));
+
+    // Once later events of the counter are emitted, the one kept goes with the range holding
+    // them, while the other key keeps its only event.
+    put_events(6, vec![counter_event(3)]);
+    put_events(7, vec![counter_event(4)]);
+    assert_eq!(
+        pruner.preview(5, 7).unwrap().events_by_key,
+        HashMap::from([(counter_key, (2, 2, 3))])
+    );
+    let mut db_batch = SchemaBatch::new();
+    prune_and_verify_deletions(&pruner, event_store, &mut db_batch, 5, 7);
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(
+        (0..5)
+            .map(|seq_num| is_left(&counter_key, seq_num))
+            .collect::<Vec<_>>(),
+        vec![false, false, false, false, true]
+    );
+    assert!(event_store.get_events_by_version(3).unwrap().is_empty());
+    assert_eq!(
+        event_store.get_events_by_version(2).unwrap(),
+        vec![other_event]
+    );
+    assert!(is_left(&other_key, 0));
+}
+
+#[test]
+fn test_event_store_pruner_normal_prune_after_keeping_latest() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let [counter_key, other_key] =
+        [(); 2].map(|_| EventKey::new_from_address(&AccountAddress::random(), 0));
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        let mut events = vec![ContractEvent::new(
+            counter_key,
+            version,
+            TypeTag::Bool,
+            vec![],
+        )];
+        // The latest event of this key, at version 4, is kept.
+        if version < 5 {
+            events.push(ContractEvent::new(
+                other_key,
+                version,
+                TypeTag::Bool,
+                vec![],
+            ));
+        }
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let selective_pruner =
+        EventStorePruner::new(Arc::clone(event_store)).with_latest_event_per_key_kept();
+    // The first versions are left for the pruning that follows to have events to delete.
+    let mut db_batch = SchemaBatch::new();
+    selective_pruner.prune(&mut db_batch, 2, 8).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    selective_pruner.on_committed();
+    assert_eq!(event_store.get_events_by_version(4).unwrap().len(), 1);
+
+    // Ending at a version pruned selectively doesn't fail on its accumulator being gone.
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 4, 9).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    for version in 0..9 {
+        assert!(event_store
+            .get_events_by_version(version)
+            .unwrap()
+            .is_empty());
+    }
+    event_store.verify_events_readable(9).unwrap();
+}
+
+#[test]
+fn test_event_store_pruner_prune_with_attestation() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let events: Vec<Vec<ContractEvent>> = (0..10)
+        .map(|version| {
+            (0..3)
+                .map(|i| ContractEvent::new(event_key, 3 * version + i, TypeTag::Bool, vec![]))
+                .collect()
+        })
+        .collect();
+    let mut cs = ChangeSet::new();
+    for (version, version_events) in events.iter().enumerate() {
+        event_store
+            .put_events(version as Version, version_events, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+    let expected_root_hash = |version: Version| {
+        let event_hashes: Vec<HashValue> = events[version as usize]
+            .iter()
+            .map(CryptoHash::hash)
             .collect();
+        InMemoryAccumulator::<EventAccumulatorHasher>::from_leaves(&event_hashes).root_hash()
+    };
 
-        verify_event_store_pruner(event_batches);
+    // The boundary is held back by `max_versions`, and then by the target.
+    let (boundary, root_hash) = pruner.prune_with_attestation(0, 6, 4).unwrap();
+    assert_eq!(boundary, 4);
+    assert_eq!(root_hash, expected_root_hash(4));
+    verify_events_not_in_store(3, event_store);
+
+    let (boundary, root_hash) = pruner.prune_with_attestation(4, 6, 4).unwrap();
+    assert_eq!(boundary, 6);
+    assert_eq!(root_hash, expected_root_hash(6));
+    verify_events_not_in_store(5, event_store);
+    verify_events_in_store(&events, 6, event_store);
+
+    let descending_pruner =
+        EventStorePruner::new_with_direction(Arc::clone(event_store), PruneDirection::Descending);
+    assert!(descending_pruner.prune_with_attestation(6, 8, 1).is_err());
+}
+
+// The failure comes from the check of the accumulator left, which only debug builds run.
+#[cfg(debug_assertions)]
+#[test]
+fn test_event_store_pruner_accumulator_failure_isolation() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let num_versions = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events = vec![
+            ContractEvent::new(event_key, 2 * version, TypeTag::Bool, vec![]),
+            ContractEvent::new(event_key, 2 * version + 1, TypeTag::Bool, vec![]),
+        ];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let accumulator_nodes = |version: Version| -> Vec<_> {
+        let mut iter = aptos_db
+            .db
+            .iter::<EventAccumulatorSchema>(ReadOptions::default())
+            .unwrap();
+        iter.seek(&(version, Position::from_inorder_index(0)))
+            .unwrap();
+        iter.map(Result::unwrap)
+            .take_while(|((node_version, _), _)| *node_version == version)
+            .collect()
+    };
+
+    // The accumulator step of pruning up to version 4 fails once the accumulator of version 4,
+    // which it checks is left intact, is gone.
+    let removed_nodes = accumulator_nodes(4);
+    let mut db_batch = SchemaBatch::new();
+    for (key, _hash) in &removed_nodes {
+        db_batch.delete::<EventAccumulatorSchema>(key).unwrap();
+    }
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    let pruner = EventStorePruner::new(Arc::clone(event_store));
+    assert!(pruner.prune(&mut SchemaBatch::new(), 0, 4).is_err());
+
+    // The events are pruned regardless, while the accumulator is left behind.
+    let pruner =
+        EventStorePruner::new(Arc::clone(event_store)).with_accumulator_failure_isolation();
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 0, 4).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    for version in 0..4 {
+        verify_events_not_in_store(version, event_store);
+        assert!(!accumulator_nodes(version).is_empty());
+    }
+
+    // Once the failure is gone, the next round catches up with the accumulator left behind.
+    let mut db_batch = SchemaBatch::new();
+    for (key, hash) in &removed_nodes {
+        db_batch.put::<EventAccumulatorSchema>(key, hash).unwrap();
+    }
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 4, 8).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    for version in 0..8 {
+        verify_events_not_in_store(version, event_store);
+        assert!(accumulator_nodes(version).is_empty());
+    }
+    event_store.verify_events_readable(8).unwrap();
+}
+
+#[test]
+fn test_event_store_pruner_readable_version_range() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // On a fresh DB both ends are at version 0.
+    assert_eq!(pruner.readable_version_range(), (0, 0));
+
+    pruner.set_target_version(10);
+    assert_eq!(pruner.readable_version_range(), (0, 10));
+    pruner.record_progress(4);
+    assert_eq!(pruner.readable_version_range(), (4, 10));
+}
+
+#[test]
+fn test_pruning_candidates_leave_block_cache_alone() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    test_helper::put_events(&aptos_db, 100, 10);
+    test_helper::flush_all(&aptos_db);
+
+    let cache_usage = test_helper::event_block_cache_usage(&aptos_db);
+    assert_eq!(
+        test_helper::scan_pruning_candidates(&aptos_db, 100, false /* fill_cache */),
+        1000
+    );
+    assert_eq!(test_helper::event_block_cache_usage(&aptos_db), cache_usage);
+
+    assert_eq!(
+        test_helper::scan_pruning_candidates(&aptos_db, 100, true /* fill_cache */),
+        1000
+    );
+    assert!(test_helper::event_block_cache_usage(&aptos_db) > cache_usage);
+}
+
+#[test]
+fn test_ledger_pruner_estimate_next_batch_size() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let pruner = create_ledger_pruner(&aptos_db);
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        let events: Vec<_> = (2 * version..2 * version + 2)
+            .map(|seq_num| ContractEvent::new(key, seq_num, TypeTag::Bool, vec![]))
+            .collect();
+        event_store.put_events(version, &events, &mut cs).unwrap();
     }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
 
+    // Nothing is pending before a target is set.
+    assert_eq!(pruner.estimate_next_batch_size(100).unwrap(), Some(0));
+
+    pruner.set_target_version(6);
+    assert_eq!(pruner.estimate_next_batch_size(100).unwrap(), Some(12));
+    assert_eq!(pruner.estimate_next_batch_size(2).unwrap(), Some(4));
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 2).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(pruner.estimate_next_batch_size(100).unwrap(), Some(8));
+}
+
+/// Prunes the range of versions [begin, end) into `db_batch`, then makes sure the events deleted
+/// are exactly the ones whose index entries are, which the pruner itself leaves unchecked.
+fn prune_and_verify_deletions(
+    pruner: &EventStorePruner,
+    event_store: &EventStore,
+    db_batch: &mut SchemaBatch,
+    begin: Version,
+    end: Version,
+) {
+    pruner.prune(db_batch, begin, end).unwrap();
+    event_store.verify_event_deletions(db_batch).unwrap();
 }
 
 fn verify_event_store_pruner(events: Vec<Vec<ContractEvent>>) {
@@ -40,18 +1593,19 @@ fn verify_event_store_pruner(events: Vec<Vec<ContractEvent>>) {
     let num_versions = events.len();
     let pruner = Pruner::new(
         Arc::clone(&aptos_db.db),
-        StoragePrunerConfig {
-            state_store_prune_window: Some(0),
-            ledger_prune_window: Some(0),
-            pruning_batch_size: 1,
-        },
+        StoragePrunerConfig::new(Some(0), Some(0), 1),
         Arc::clone(&aptos_db.transaction_store),
         Arc::clone(&aptos_db.ledger_store),
         Arc::clone(&aptos_db.event_store),
+        None, /* archive_db */
     );
 
-    // Write events to DB
+    // Write events to DB, along with the transactions they are emitted by.
     for (version, events_for_version) in events.iter().enumerate() {
+        aptos_db
+            .transaction_store
+            .put_transaction(version as u64, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
         event_store
             .put_events(version as u64, events_for_version, &mut cs)
             .unwrap();
@@ -81,6 +1635,218 @@ fn verify_event_store_pruner(events: Vec<Vec<ContractEvent>>) {
     }
 }
 
+fn verify_event_store_pruner_target_below_least_readable_version(events: Vec<Vec<ContractEvent>>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let mut cs = ChangeSet::new();
+    let num_versions = events.len();
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // Write events to DB
+    for (version, events_for_version) in events.iter().enumerate() {
+        event_store
+            .put_events(version as u64, events_for_version, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // A target below the least readable version must be a no-op rather than a full scan.
+    pruner.record_progress(num_versions as u64);
+    pruner.set_target_version(0);
+    let mut db_batch = SchemaBatch::new();
+    assert_eq!(
+        pruner.prune(&mut db_batch, 1).unwrap().version,
+        num_versions as u64
+    );
+    // Same for the event store itself when handed an inverted range.
+    event_store
+        .prune_events(num_versions as u64, 0, &mut db_batch)
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    for j in 0..num_versions {
+        verify_events_in_store(&events, j as u64, event_store);
+        verify_event_by_key_in_store(&events, j as u64, event_store);
+        verify_event_by_version_in_store(&events, j as u64, event_store);
+    }
+}
+
+fn verify_event_store_pruner_dry_run(events: Vec<Vec<ContractEvent>>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let mut cs = ChangeSet::new();
+    let num_versions = events.len();
+    let pruner = LedgerPruner::new_with_dry_run(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(&aptos_db.transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        0,                                /* prune_window */
+        true,                             /* dry_run */
+        false,                            /* compact_after_prune */
+        Arc::new(FixedBatchStrategy),
+    );
+
+    // Write events and write sets to DB
+    for (version, events_for_version) in events.iter().enumerate() {
+        event_store
+            .put_events(version as u64, events_for_version, &mut cs)
+            .unwrap();
+        aptos_db
+            .transaction_store
+            .put_write_set(version as u64, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let db_content_before = dump_ledger_schemas(&aptos_db.db);
+
+    pruner.set_target_version(num_versions as u64);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner
+        .prune(&mut db_batch, num_versions as u64 /* max_versions */)
+        .unwrap();
+    assert!(db_batch.is_empty());
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // The whole range would have been pruned, but neither the DB nor the progress has changed.
+    assert_eq!(progress.version, num_versions as u64);
+    assert_eq!(progress.keys_pruned > 0, num_versions > 0);
+    assert_eq!(pruner.least_readable_version(), 0);
+    assert_eq!(dump_ledger_schemas(&aptos_db.db), db_content_before);
+}
+
+fn verify_event_store_pruner_initialize_and_repair(events: Vec<Vec<ContractEvent>>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let mut cs = ChangeSet::new();
+    let num_versions = events.len();
+    let torn_version = num_versions / 2;
+
+    // Write events and transactions to DB
+    for (version, events_for_version) in events.iter().enumerate() {
+        event_store
+            .put_events(version as u64, events_for_version, &mut cs)
+            .unwrap();
+        aptos_db
+            .transaction_store
+            .put_transaction(version as u64, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // Simulate a torn pruning round which removed the transactions and events before
+    // `torn_version` but not the event indices.
+    let mut db_batch = SchemaBatch::new();
+    aptos_db
+        .transaction_store
+        .prune_transaction_schema(0, torn_version as u64, &mut db_batch)
+        .unwrap();
+    event_store
+        .prune_event_schema(0, torn_version as u64, &mut db_batch)
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    for j in 0..torn_version {
+        verify_event_by_key_in_store(&events, j as u64, event_store);
+    }
+
+    let pruner = create_ledger_pruner(&aptos_db);
+    assert_eq!(pruner.initialize_and_repair().unwrap(), torn_version as u64);
+
+    // The dangling indices are gone while everything after `torn_version` is intact.
+    for j in 0..torn_version {
+        verify_events_not_in_store(j as u64, event_store);
+        verify_event_by_key_not_in_store(&events, j as u64, event_store);
+        verify_event_by_version_not_in_store(&events, j as u64, event_store);
+    }
+    for j in torn_version..num_versions {
+        verify_events_in_store(&events, j as u64, event_store);
+        verify_event_by_key_in_store(&events, j as u64, event_store);
+        verify_event_by_version_in_store(&events, j as u64, event_store);
+    }
+}
+
+fn verify_event_store_pruner_keeps_proofs_at_target_version(events: Vec<Vec<ContractEvent>>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let event_store = &aptos_db.event_store;
+    let mut cs = ChangeSet::new();
+    let num_versions = events.len();
+    let target_version = (num_versions / 2) as u64;
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // Write events to DB, every version has at least one event
+    let root_hashes: Vec<HashValue> = events
+        .iter()
+        .enumerate()
+        .map(|(version, events_for_version)| {
+            event_store
+                .put_events(version as u64, events_for_version, &mut cs)
+                .unwrap()
+        })
+        .collect();
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(target_version);
+    let mut db_batch = SchemaBatch::new();
+    pruner
+        .prune(&mut db_batch, num_versions as u64 /* max_versions */)
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // The events right above the prune boundary can still be proven
+    for (index, event) in events[target_version as usize].iter().enumerate() {
+        let (event_from_db, proof) = event_store
+            .get_event_with_proof_by_version_and_index(target_version, index as u64)
+            .unwrap();
+        assert_eq!(&event_from_db, event);
+        proof
+            .verify(
+                root_hashes[target_version as usize],
+                event.hash(),
+                index as u64,
+            )
+            .unwrap();
+    }
+
+    // Pruning refuses to go on once the accumulator above the next boundary is damaged.
+    let next_target_version = target_version + 1;
+    let mut db_batch = SchemaBatch::new();
+    db_batch
+        .put::<EventAccumulatorSchema>(
+            &(next_target_version, Position::from_leaf_index(0)),
+            &HashValue::zero(),
+        )
+        .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    assert!(event_store
+        .prune_events(target_version, next_target_version, &mut SchemaBatch::new())
+        .is_err());
+}
+
+/// Formats every row of the schemas touched by the ledger pruner, for byte-for-byte comparison.
+fn dump_ledger_schemas(db: &DB) -> Vec<String> {
+    let mut rows = vec![];
+    rows.extend(dump_schema::<EventSchema>(db));
+    rows.extend(dump_schema::<EventAccumulatorSchema>(db));
+    rows.extend(dump_schema::<EventByKeySchema>(db));
+    rows.extend(dump_schema::<EventByVersionSchema>(db));
+    rows.extend(dump_schema::<WriteSetSchema>(db));
+    rows
+}
+
+fn dump_schema<S: Schema>(db: &DB) -> Vec<String> {
+    let mut iter = db.iter::<S>(ReadOptions::default()).unwrap();
+    iter.seek_to_first();
+    iter.map(|row| format!("{:?}", row.unwrap())).collect()
+}
+
 fn verify_event_by_key_not_in_store(
     events: &[Vec<ContractEvent>],
     version: Version,
@@ -0,0 +1,262 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+use crate::{
+    metrics::{
+        PRUNER_BATCH_CLAMPED_TOTAL, PRUNER_BATCH_DURATION_SECONDS, PRUNER_KEYS_DELETED_TOTAL,
+        PRUNER_LEAST_READABLE_VERSION,
+    },
+    pruner::{
+        db_pruner::{DBPruner, PruneProgress, PruneProgressCallback, PruneStatus, PrunerError},
+        pruner_kind::PrunerKind,
+    },
+    schema::{
+        db_metadata::{DbMetadataKey, DbMetadataSchema, DbMetadataValue},
+        ledger_info::LedgerInfoSchema,
+    },
+    LedgerStore,
+};
+use aptos_infallible::{duration_since_epoch, Mutex};
+use aptos_types::transaction::{AtomicVersion, Version};
+use schemadb::{ReadOptions, SchemaBatch, DB, DEFAULT_CF_NAME};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Prunes the ledger infos of versions below the target.
+///
+/// The epoch-ending ledger infos are never pruned since they make up the proof chain of validator
+/// set changes clients sync through, and neither is the latest one, which the DB serves as the
+/// latest committed state. Anything else left of an old epoch is pruned.
+///
+/// Since the ledger infos are keyed by epoch, an epoch only keeps the last one written, which is
+/// the epoch-ending one for every epoch but the latest. What's left to prune is the odd epoch
+/// whose last ledger info doesn't end it, so the worker doesn't run this pruner.
+pub struct LedgerInfoPruner {
+    db: Arc<DB>,
+    ledger_store: Arc<LedgerStore>,
+    /// Keeps track of the target version that the pruner needs to achieve.
+    target_version: AtomicVersion,
+    least_readable_version: AtomicVersion,
+    /// Rounds do nothing while unset, see `set_enabled()`.
+    enabled: AtomicBool,
+    /// The `max_versions` and the target of the last round which did work.
+    last_batch: Mutex<Option<(u64, Version)>>,
+    /// How the last round which did work went.
+    last_status: Mutex<Option<PruneStatus>>,
+    /// When the least readable version last changed, since the UNIX epoch.
+    progress_updated_at: Mutex<Option<Duration>>,
+    /// The version the last round pruned up to, recorded as the least readable version only once
+    /// the caller commits the deletions, see `on_committed()`.
+    pending_progress: Mutex<Option<Version>>,
+    /// Held throughout `prune()`, so overlapping calls run one after the other.
+    prune_lock: Mutex<()>,
+    /// Number of versions right before the target version that are kept from pruning.
+    prune_window: Version,
+    /// Told about each successful round.
+    progress_callback: PruneProgressCallback,
+}
+
+impl DBPruner for LedgerInfoPruner {
+    fn kind(&self) -> PrunerKind {
+        PrunerKind::LedgerInfo
+    }
+
+    fn prune(
+        &self,
+        db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        let _prune_guard = self.prune_lock.lock();
+        if !self.is_pruning_pending() {
+            return Ok(PruneProgress {
+                version: self.least_readable_version(),
+                keys_pruned: 0,
+            });
+        }
+        let _timer = PRUNER_BATCH_DURATION_SECONDS
+            .with_label_values(&[self.kind().metric_label()])
+            .start_timer();
+        let started_at = Instant::now();
+        let result = self.prune_inner(db_batch, max_versions);
+        *self.last_status.lock() = Some(PruneStatus::new(&result, started_at.elapsed()));
+        result
+    }
+
+    fn initialize_least_readable_version(&self) -> anyhow::Result<Version> {
+        // Not pruned since the progress started being persisted, in which case seeking from the
+        // first epoch is cheap since there is one ledger info per epoch.
+        Ok(self
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerInfoPrunerProgress)?
+            .map_or(0, |value| value.expect_version()))
+    }
+
+    fn least_readable_version(&self) -> Version {
+        self.least_readable_version.load(Ordering::Acquire)
+    }
+
+    fn set_target_version(&self, target_version: Version) {
+        self.target_version.store(target_version, Ordering::Relaxed)
+    }
+
+    fn target_version(&self) -> Version {
+        self.target_version.load(Ordering::Relaxed)
+    }
+
+    fn last_batch(&self) -> Option<(u64, Version)> {
+        *self.last_batch.lock()
+    }
+
+    fn last_status(&self) -> Option<PruneStatus> {
+        self.last_status.lock().clone()
+    }
+
+    fn progress_updated_at(&self) -> Option<Duration> {
+        *self.progress_updated_at.lock()
+    }
+
+    fn on_committed(&self) {
+        if let Some(pruned_version) = self.pending_progress.lock().take() {
+            self.record_progress(pruned_version);
+        }
+    }
+
+    fn on_discarded(&self) {
+        self.pending_progress.lock().take();
+    }
+
+    fn flush_progress(&self) -> anyhow::Result<()> {
+        // Not in the middle of a round.
+        let _prune_guard = self.prune_lock.lock();
+        let mut db_batch = SchemaBatch::new();
+        db_batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerInfoPrunerProgress,
+            &DbMetadataValue::Version(self.least_readable_version()),
+        )?;
+        self.db.write_schemas(db_batch)
+    }
+
+    fn prune_window(&self) -> Version {
+        self.prune_window
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn commit(&self, db_batch: SchemaBatch) -> anyhow::Result<()> {
+        self.db.write_schemas(db_batch)
+    }
+
+    fn round_start_version(&self) -> Version {
+        self.pending_progress
+            .lock()
+            .unwrap_or_else(|| self.least_readable_version())
+    }
+
+    fn record_progress(&self, least_readable_version: Version) -> Version {
+        let previous_version = self
+            .least_readable_version
+            .swap(least_readable_version, Ordering::Release);
+        if least_readable_version != previous_version {
+            *self.progress_updated_at.lock() = Some(duration_since_epoch());
+        }
+        PRUNER_LEAST_READABLE_VERSION
+            .with_label_values(&[PrunerKind::LedgerInfo.metric_label()])
+            .set(least_readable_version as i64);
+        least_readable_version.saturating_sub(previous_version)
+    }
+}
+
+impl LedgerInfoPruner {
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn new(
+        db: Arc<DB>,
+        ledger_store: Arc<LedgerStore>,
+        prune_window: Version,
+    ) -> Self {
+        let pruner = LedgerInfoPruner {
+            db,
+            ledger_store,
+            target_version: AtomicVersion::new(0),
+            least_readable_version: AtomicVersion::new(0),
+            enabled: AtomicBool::new(true),
+            last_batch: Mutex::new(None),
+            last_status: Mutex::new(None),
+            progress_updated_at: Mutex::new(None),
+            pending_progress: Mutex::new(None),
+            prune_lock: Mutex::new(()),
+            prune_window,
+            progress_callback: Box::new(|_, _| {}),
+        };
+        pruner.initialize();
+        pruner
+    }
+
+    /// Sets the callback told about the version reached and the keys deleted by each successful
+    /// round, which runs on the pruning thread.
+    #[allow(dead_code)]
+    pub fn with_progress_callback(mut self, progress_callback: PruneProgressCallback) -> Self {
+        self.progress_callback = progress_callback;
+        self
+    }
+
+    fn prune_inner(
+        &self,
+        db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        let round_start_version = self.round_start_version();
+        let current_target_version = self.current_batch_target(max_versions);
+        if current_target_version < self.prunable_target_version() {
+            PRUNER_BATCH_CLAMPED_TOTAL
+                .with_label_values(&[self.kind().metric_label()])
+                .inc();
+        }
+        *self.last_batch.lock() = Some((max_versions, current_target_version));
+
+        // Ledger infos are keyed by epoch, and the ones of the epochs before that of the round's
+        // start version are all below it, i.e. already taken care of.
+        let mut iter = self.db.iter::<LedgerInfoSchema>(ReadOptions::default())?;
+        iter.seek(&self.ledger_store.get_epoch(round_start_version)?)?;
+        let mut iter = iter.peekable();
+        let mut keys_pruned = 0;
+        while let Some(item) = iter.next() {
+            let (epoch, ledger_info_with_sigs) = item?;
+            let ledger_info = ledger_info_with_sigs.ledger_info();
+            if ledger_info.version() >= current_target_version {
+                break;
+            }
+            let is_latest = iter.peek().is_none();
+            if !ledger_info.ends_epoch() && !is_latest {
+                db_batch.delete::<LedgerInfoSchema>(&epoch)?;
+                keys_pruned += 1;
+            }
+        }
+
+        // Persisted along with the deletions, for the pruner to resume from after restart.
+        db_batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerInfoPrunerProgress,
+            &DbMetadataValue::Version(current_target_version),
+        )?;
+        *self.pending_progress.lock() = Some(current_target_version);
+        if keys_pruned > 0 {
+            PRUNER_KEYS_DELETED_TOTAL
+                .with_label_values(&[PrunerKind::LedgerInfo.metric_label(), DEFAULT_CF_NAME])
+                .inc_by(keys_pruned as u64);
+        }
+        (self.progress_callback)(current_target_version, keys_pruned);
+        Ok(PruneProgress {
+            version: current_target_version,
+            keys_pruned,
+        })
+    }
+}
@@ -1,82 +1,278 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 use crate::{
-    metrics::PRUNER_LEAST_READABLE_VERSION,
+    metrics::{
+        PRUNER_BATCH_CLAMPED_TOTAL, PRUNER_BATCH_DURATION_SECONDS,
+        PRUNER_INTERMEDIATE_COMMITS_TOTAL, PRUNER_KEYS_DELETED_TOTAL,
+        PRUNER_LEAST_READABLE_VERSION, PRUNER_UNDECODABLE_KEYS_TOTAL,
+    },
     pruner::{
-        db_pruner::DBPruner,
+        checkpoint_registry::CheckpointRegistry,
+        db_pruner::{
+            warn_if_slow_batch, DBPruner, LatestVersionCallback, PruneProgress,
+            PruneProgressCallback, PruneStatus, PrunerError,
+        },
         db_sub_pruner::DBSubPruner,
         event_store::event_store_pruner::EventStorePruner,
-        ledger_store::ledger_counter_pruner::LedgerCounterPruner,
+        ledger_store::{
+            ledger_counter_pruner::LedgerCounterPruner,
+            transaction_accumulator_pruner::TransactionAccumulatorPruner,
+        },
+        prune_read_options::PruneReadOptions,
+        prune_strategy::{FixedBatchStrategy, PruneStrategy},
+        pruner_kind::PrunerKind,
+        rate_limiter::RateLimiter,
+        read_floor::ReadFloorRegistry,
         transaction_store::{
             transaction_store_pruner::TransactionStorePruner, write_set_pruner::WriteSetPruner,
         },
+        write_retries::WriteRetries,
+    },
+    schema::{
+        db_metadata::{DbMetadataKey, DbMetadataSchema, DbMetadataValue},
+        event::EventSchema,
+        write_set::WriteSetSchema,
+        TRANSACTION_CF_NAME,
     },
     transaction::TransactionSchema,
     EventStore, LedgerStore, TransactionStore,
 };
+use anyhow::ensure;
+use aptos_infallible::{duration_since_epoch, Mutex};
+use aptos_logger::{debug, warn};
 use aptos_types::transaction::{AtomicVersion, Version};
-use schemadb::{ReadOptions, SchemaBatch, DB};
-use std::sync::{atomic::Ordering, Arc};
+use schemadb::{schema::Schema, ReadOptions, SchemaBatch, SchemaIterator, DB};
+use std::{
+    cmp::{max, min},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{field, info_span, Span};
 
-pub const LEDGER_PRUNER_NAME: &str = "ledger pruner";
+pub const LEDGER_PRUNER_NAME: &str = PrunerKind::Ledger.name();
+
+/// Number of versions pruned between two checks of the stop flag.
+pub(in crate::pruner) const STOP_FLAG_CHECK_INTERVAL: Version = 100;
 
 pub struct LedgerPruner {
     db: Arc<DB>,
+    /// Read by `estimate_next_batch_size()`, the pruning itself goes through the sub-pruners.
+    event_store: Arc<EventStore>,
     /// Keeps track of the target version that the pruner needs to achieve.
     target_version: AtomicVersion,
     least_readable_version: AtomicVersion,
+    /// Rounds do nothing while unset, see `set_enabled()`.
+    enabled: AtomicBool,
+    /// The `max_versions` and the target of the last round which did work.
+    last_batch: Mutex<Option<(u64, Version)>>,
+    /// How the last round which did work went.
+    last_status: Mutex<Option<PruneStatus>>,
+    /// When the least readable version last changed, since the UNIX epoch.
+    progress_updated_at: Mutex<Option<Duration>>,
+    /// The version the last round pruned up to, recorded as the least readable version only once
+    /// the caller commits the deletions, see `on_committed()`.
+    pending_progress: Mutex<Option<Version>>,
+    /// Held throughout `prune()`, so overlapping calls run one after the other.
+    prune_lock: Mutex<()>,
     transaction_store_pruner: Arc<dyn DBSubPruner + Send + Sync>,
     event_store_pruner: Arc<dyn DBSubPruner + Send + Sync>,
     write_set_pruner: Arc<dyn DBSubPruner + Send + Sync>,
     ledger_counter_pruner: Arc<dyn DBSubPruner + Send + Sync>,
+    /// If set, prunes the transaction accumulator along with the rest, see
+    /// `with_transaction_accumulator_pruning()`.
+    transaction_accumulator_pruner: Option<Arc<dyn DBSubPruner + Send + Sync>>,
+    /// Once set, a pruning round in flight stops at the next chunk boundary.
+    stop_flag: Arc<AtomicBool>,
+    /// Number of versions right before the target version that are kept from pruning.
+    prune_window: Version,
+    /// In dry run mode the pruner computes everything it would delete but neither hands the
+    /// deletions over to the caller nor records progress.
+    dry_run: bool,
+    /// If set, the pruned range of the event schema is compacted after each round.
+    compact_after_prune: bool,
+    /// Decides how far each round goes.
+    prune_strategy: Arc<dyn PruneStrategy + Send + Sync>,
+    /// How long the last round took, for the prune strategy to adapt to.
+    last_batch_duration: Mutex<Duration>,
+    /// Told about each successful round, except on dry run.
+    progress_callback: PruneProgressCallback,
+    /// If set, caps the bytes each round hands over to the caller to write per second.
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// If set, a round commits its deletions itself whenever at least that many are collected.
+    commit_threshold: Option<usize>,
+    /// If set, retries the writes the pruner makes itself on transient failures.
+    write_retries: Option<WriteRetries>,
+    /// If set, the events and write sets pruned are moved there, see `with_archive_db()`.
+    archive_db: Option<Arc<DB>>,
+    /// If set, rounds taking longer are warned about.
+    slow_batch_threshold: Option<Duration>,
+    /// If set, holds the versions in-flight reads need back from pruning.
+    read_floors: Option<Arc<ReadFloorRegistry>>,
+    /// Where the transaction accumulator is pruned from, see
+    /// `with_transaction_accumulator_pruning()`, and which tells where the epochs end, for epoch
+    /// aligned pruning.
+    ledger_store: Arc<LedgerStore>,
+    /// Where the write sets are pruned from, see `with_write_set_checkpoints()`.
+    transaction_store: Arc<TransactionStore>,
+    /// If set, only whole epochs are pruned, see `set_epoch_aligned()`.
+    epoch_aligned: AtomicBool,
+    /// If set, the transactions of genesis are never pruned, see `with_genesis_kept()`.
+    never_prune_genesis: bool,
+    /// If set, the target is clamped to the version it returns, see `with_latest_version()`.
+    latest_version: Option<LatestVersionCallback>,
+    /// Set while the rounds are clamped to the latest version in the DB, so it's warned about
+    /// once rather than every round.
+    clamped_to_latest_in_db: AtomicBool,
+    /// Rounds within that long of the last commit do nothing, see `with_min_prune_interval()`.
+    min_prune_interval: Duration,
+    /// When the deletions of the rounds were last committed, see `on_committed()`.
+    last_committed_at: Mutex<Option<Instant>>,
+    /// The span of the last round handed over, left open until its deletions are committed or
+    /// dropped, see `round_span()`.
+    round_span: Mutex<Option<Span>>,
 }
 
 impl DBPruner for LedgerPruner {
-    fn name(&self) -> &'static str {
-        LEDGER_PRUNER_NAME
+    fn kind(&self) -> PrunerKind {
+        PrunerKind::Ledger
     }
 
-    fn prune(&self, db_batch: &mut SchemaBatch, max_versions: u64) -> anyhow::Result<Version> {
+    fn prune(
+        &self,
+        db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        let _prune_guard = self.prune_lock.lock();
+        // Nothing to do until the target advances, which is checked before anything else since
+        // the pruner is frequently polled while idle.
         if !self.is_pruning_pending() {
-            return Ok(self.least_readable_version());
+            return Ok(PruneProgress {
+                version: self.least_readable_version(),
+                keys_pruned: 0,
+            });
         }
-        let least_readable_version = self.least_readable_version();
-        // Current target version might be less than the target version to ensure we don't prune
-        // more than max_version in one go.
-        let current_target_version = self.get_currrent_batch_target(max_versions);
+        let started_at = Instant::now();
+        let result = self.prune_round(db_batch, max_versions);
+        *self.last_status.lock() = Some(PruneStatus::new(&result, started_at.elapsed()));
+        if self.dry_run {
+            // The deletions went to a scratch batch, dropped already.
+            self.on_discarded();
+        }
+        result
+    }
 
-        self.transaction_store_pruner.prune(
-            db_batch,
-            least_readable_version,
-            current_target_version,
-        )?;
-        self.write_set_pruner
-            .prune(db_batch, least_readable_version, current_target_version)?;
-        self.ledger_counter_pruner.prune(
-            db_batch,
-            least_readable_version,
-            current_target_version,
-        )?;
+    fn is_stop_requested(&self) -> bool {
+        self.stop_flag.load(Ordering::Relaxed)
+    }
 
-        self.event_store_pruner
-            .prune(db_batch, least_readable_version, current_target_version)?;
+    fn compact_pruned_range(
+        &self,
+        begin: Version,
+        end: Version,
+    ) -> anyhow::Result<Option<(Version, Version)>> {
+        if !self.compact_after_prune || self.dry_run || begin >= end {
+            return Ok(None);
+        }
+        self.db
+            .compact_range_for_schema::<EventSchema>(&(begin, 0), &(end, 0))?;
+        Ok(Some((begin, end)))
+    }
 
-        self.record_progress(current_target_version);
-        Ok(current_target_version)
+    fn estimate_next_batch_size(&self, max_versions: Version) -> anyhow::Result<Option<usize>> {
+        if !self.is_pruning_pending() {
+            return Ok(Some(0));
+        }
+        // Only the events take a varying number of keys per version, the other schemas a handful
+        // each, so they are what's counted.
+        let count = self.event_store.count_events_in_version_range(
+            self.round_start_version(),
+            self.current_batch_target(max_versions),
+        )?;
+        Ok(Some(count))
     }
 
     fn initialize_least_readable_version(&self) -> anyhow::Result<Version> {
-        let mut iter = self.db.iter::<TransactionSchema>(ReadOptions::default())?;
+        let first_version = self.oldest_live_version()?;
+        self.reconcile_least_readable_version(first_version)
+    }
+
+    fn never_prune_genesis(&self) -> bool {
+        self.never_prune_genesis
+    }
+
+    fn oldest_live_version(&self) -> anyhow::Result<Option<Version>> {
+        // The seek goes through the tombstones of whatever was pruned, which is no use caching.
+        let mut iter = self
+            .db
+            .iter::<TransactionSchema>(PruneReadOptions::default().read_options())?;
         iter.seek_to_first();
-        let version = iter.next().transpose()?.map_or(0, |(version, _)| version);
-        Ok(version)
+        self.next_transaction_version(&mut iter)
+    }
+
+    fn initialize_least_readable_version_with_hint(
+        &self,
+        hint: Version,
+    ) -> anyhow::Result<Version> {
+        // Two point lookups tell whether the hint is the first transaction left, without going
+        // through the tombstones of the pruned ones.
+        let is_first_version = self.db.get::<TransactionSchema>(&hint)?.is_some()
+            && (hint == 0 || self.db.get::<TransactionSchema>(&(hint - 1))?.is_none());
+        if !is_first_version {
+            warn!(
+                hint = hint,
+                "{} hint is not the first transaction, seeking it instead.",
+                self.name()
+            );
+            return self.initialize_least_readable_version();
+        }
+        self.reconcile_least_readable_version(Some(hint))
+    }
+
+    fn initialize_and_repair(&self) -> anyhow::Result<Version> {
+        let least_readable_version = self.initialize_least_readable_version()?;
+        let mut db_batch = SchemaBatch::new();
+        self.event_store_pruner
+            .repair(&mut db_batch, least_readable_version)?;
+        if let Some(transaction_accumulator_pruner) = &self.transaction_accumulator_pruner {
+            transaction_accumulator_pruner.repair(&mut db_batch, least_readable_version)?;
+        }
+        if !db_batch.is_empty() {
+            warn!(
+                least_readable_version = least_readable_version,
+                num_write_ops = db_batch.len(),
+                "{} repairing data left over by a previous pruning round.",
+                self.name()
+            );
+            self.write_schemas(&db_batch)?;
+        }
+        Ok(least_readable_version)
     }
 
     fn least_readable_version(&self) -> Version {
-        self.least_readable_version.load(Ordering::Relaxed)
+        self.least_readable_version.load(Ordering::Acquire)
     }
 
     fn set_target_version(&self, target_version: Version) {
+        let latest_version = self
+            .latest_version
+            .as_ref()
+            .map(|latest_version| latest_version().unwrap_or(0));
+        let target_version = match latest_version {
+            Some(latest_version) if target_version > latest_version => {
+                warn!(
+                    target_version = target_version,
+                    latest_version = latest_version,
+                    "{} target is past the latest version committed, clamping it.",
+                    self.name()
+                );
+                latest_version
+            }
+            _ => target_version,
+        };
         self.target_version.store(target_version, Ordering::Relaxed)
     }
 
@@ -84,12 +280,142 @@ impl DBPruner for LedgerPruner {
         self.target_version.load(Ordering::Relaxed)
     }
 
-    fn record_progress(&self, least_readable_version: Version) {
-        self.least_readable_version
-            .store(least_readable_version, Ordering::Relaxed);
+    fn last_batch(&self) -> Option<(u64, Version)> {
+        *self.last_batch.lock()
+    }
+
+    fn last_status(&self) -> Option<PruneStatus> {
+        self.last_status.lock().clone()
+    }
+
+    fn progress_updated_at(&self) -> Option<Duration> {
+        *self.progress_updated_at.lock()
+    }
+
+    fn on_committed(&self) {
+        self.round_span.lock().take();
+        self.on_sub_pruners_committed();
+        // Only now are the deletions there for readers gated on the least readable version to
+        // rely on.
+        if let Some(pruned_version) = self.pending_progress.lock().take() {
+            self.record_progress(pruned_version);
+            *self.last_committed_at.lock() = Some(Instant::now());
+        }
+    }
+
+    fn on_discarded(&self) {
+        self.round_span.lock().take();
+        self.pending_progress.lock().take();
+        self.transaction_store_pruner.on_discarded();
+        self.write_set_pruner.on_discarded();
+        self.ledger_counter_pruner.on_discarded();
+        if let Some(transaction_accumulator_pruner) = &self.transaction_accumulator_pruner {
+            transaction_accumulator_pruner.on_discarded();
+        }
+        self.event_store_pruner.on_discarded();
+    }
+
+    fn flush_progress(&self) -> anyhow::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        // Not in the middle of a round.
+        let _prune_guard = self.prune_lock.lock();
+        let mut db_batch = SchemaBatch::new();
+        db_batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerPrunerProgress,
+            &DbMetadataValue::Version(self.least_readable_version()),
+        )?;
+        self.write_schemas(&db_batch)
+    }
+
+    fn warmup(&self, max_versions: Version) -> anyhow::Result<usize> {
+        let begin = self.least_readable_version();
+        let end = begin.saturating_add(max_versions);
+        // The schemas the rounds read, the rest they only delete ranges of.
+        let num_transactions =
+            self.warm_up_schema::<TransactionSchema>(&begin, end, |version| *version)?;
+        let num_write_sets =
+            self.warm_up_schema::<WriteSetSchema>(&begin, end, |version| *version)?;
+        let num_events =
+            self.warm_up_schema::<EventSchema>(&(begin, 0), end, |(version, _index)| *version)?;
+        Ok(num_transactions + num_write_sets + num_events)
+    }
+
+    fn prune_window(&self) -> Version {
+        self.prune_window
+    }
+
+    fn read_floor(&self) -> Option<Version> {
+        self.read_floors
+            .as_ref()
+            .and_then(|read_floors| read_floors.min_floor())
+    }
+
+    fn align_target(&self, target: Version) -> Version {
+        if !self.epoch_aligned.load(Ordering::Relaxed) {
+            return target;
+        }
+        // Nothing is pruned unless a whole epoch fits, leaving a least readable version already
+        // in the middle of an epoch as is.
+        let least_readable_version = self.least_readable_version();
+        match self.ledger_store.get_epoch_ending_version_before(target) {
+            // Pruning up to right after the end of an epoch leaves none partially readable.
+            Ok(epoch_ending_version) => epoch_ending_version
+                .map_or(least_readable_version, |epoch_ending_version| {
+                    max(epoch_ending_version + 1, least_readable_version)
+                }),
+            Err(e) => {
+                warn!(
+                    error = ?e,
+                    target = target,
+                    "Failed looking up the epoch ending before the target, not pruning.",
+                );
+                least_readable_version
+            }
+        }
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn commit(&self, db_batch: SchemaBatch) -> anyhow::Result<()> {
+        self.write_schemas(&db_batch)
+    }
+
+    fn round_span(&self) -> Span {
+        self.round_span.lock().clone().unwrap_or_else(Span::none)
+    }
+
+    fn is_pruning_pending(&self) -> bool {
+        // A throttled pruner has nothing to do for now, for callers polling it not to spin.
+        self.is_enabled()
+            && !self.is_throttled()
+            && self.prunable_target_version() > self.round_start_version()
+    }
+
+    fn round_start_version(&self) -> Version {
+        self.pending_progress
+            .lock()
+            .unwrap_or_else(|| self.least_readable_version())
+    }
+
+    fn record_progress(&self, least_readable_version: Version) -> Version {
+        let previous_version = self
+            .least_readable_version
+            .swap(least_readable_version, Ordering::Release);
+        if least_readable_version != previous_version {
+            *self.progress_updated_at.lock() = Some(duration_since_epoch());
+        }
         PRUNER_LEAST_READABLE_VERSION
-            .with_label_values(&["ledger_pruner"])
+            .with_label_values(&[PrunerKind::Ledger.metric_label()])
             .set(least_readable_version as i64);
+        least_readable_version.saturating_sub(previous_version)
     }
 }
 
@@ -99,19 +425,624 @@ impl LedgerPruner {
         transaction_store: Arc<TransactionStore>,
         event_store: Arc<EventStore>,
         ledger_store: Arc<LedgerStore>,
+        stop_flag: Arc<AtomicBool>,
+        prune_window: Version,
+    ) -> Self {
+        Self::new_with_dry_run(
+            db,
+            transaction_store,
+            event_store,
+            ledger_store,
+            stop_flag,
+            prune_window,
+            false, /* dry_run */
+            false, /* compact_after_prune */
+            Arc::new(FixedBatchStrategy),
+        )
+    }
+
+    /// Creates a pruner which, in dry run mode, reports the version and the number of keys each
+    /// round would prune while leaving the DB untouched. Since a dry run pruner never makes
+    /// progress, it is meant to be driven by hand rather than by the pruner worker.
+    ///
+    /// With `compact_after_prune`, `compact_pruned_range()` compacts the event schema over the
+    /// versions pruned. `prune_strategy` decides how far each round goes.
+    pub(in crate::pruner) fn new_with_dry_run(
+        db: Arc<DB>,
+        transaction_store: Arc<TransactionStore>,
+        event_store: Arc<EventStore>,
+        ledger_store: Arc<LedgerStore>,
+        stop_flag: Arc<AtomicBool>,
+        prune_window: Version,
+        dry_run: bool,
+        compact_after_prune: bool,
+        prune_strategy: Arc<dyn PruneStrategy + Send + Sync>,
     ) -> Self {
         let pruner = LedgerPruner {
             db,
             target_version: AtomicVersion::new(0),
             least_readable_version: AtomicVersion::new(0),
-            ledger_counter_pruner: Arc::new(LedgerCounterPruner::new(ledger_store)),
+            enabled: AtomicBool::new(true),
+            last_batch: Mutex::new(None),
+            last_status: Mutex::new(None),
+            progress_updated_at: Mutex::new(None),
+            pending_progress: Mutex::new(None),
+            prune_lock: Mutex::new(()),
+            ledger_counter_pruner: Arc::new(LedgerCounterPruner::new(Arc::clone(&ledger_store))),
+            transaction_accumulator_pruner: None,
             transaction_store_pruner: Arc::new(TransactionStorePruner::new(
                 transaction_store.clone(),
             )),
-            event_store_pruner: Arc::new(EventStorePruner::new(event_store)),
-            write_set_pruner: Arc::new(WriteSetPruner::new(transaction_store)),
+            event_store_pruner: Arc::new(EventStorePruner::new(Arc::clone(&event_store))),
+            event_store,
+            write_set_pruner: Arc::new(WriteSetPruner::new(Arc::clone(&transaction_store))),
+            transaction_store,
+            stop_flag,
+            prune_window,
+            dry_run,
+            compact_after_prune,
+            prune_strategy,
+            last_batch_duration: Mutex::new(Duration::ZERO),
+            progress_callback: Box::new(|_, _| {}),
+            rate_limiter: None,
+            commit_threshold: None,
+            write_retries: None,
+            archive_db: None,
+            slow_batch_threshold: None,
+            read_floors: None,
+            ledger_store,
+            epoch_aligned: AtomicBool::new(false),
+            never_prune_genesis: false,
+            latest_version: None,
+            clamped_to_latest_in_db: AtomicBool::new(false),
+            min_prune_interval: Duration::ZERO,
+            last_committed_at: Mutex::new(None),
+            round_span: Mutex::new(None),
         };
         pruner.initialize();
         pruner
     }
+
+    /// Tells the least readable version from the progress persisted and the first transaction
+    /// left, `None` if there is none.
+    fn reconcile_least_readable_version(
+        &self,
+        first_version: Option<Version>,
+    ) -> anyhow::Result<Version> {
+        let persisted_version = self
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)?
+            .map(|value| value.expect_version());
+        // Without persisted progress, i.e. not pruned since the progress started being persisted,
+        // the first transaction left tells it. Otherwise the first transaction still wins if it's
+        // past the persisted progress, which is the case of a DB restored from a snapshot pruned
+        // further.
+        Ok(max(persisted_version, first_version).unwrap_or(0))
+    }
+
+    /// Returns the version of the latest transaction in the DB, `None` if there is none.
+    fn latest_version(&self) -> anyhow::Result<Option<Version>> {
+        let mut iter = self
+            .db
+            .rev_iter::<TransactionSchema>(ReadOptions::default())?;
+        iter.seek_to_last();
+        self.next_transaction_version(&mut iter)
+    }
+
+    /// Returns the version of the next transaction `iter` reads, skipping with a warning the
+    /// entries failing to decode, e.g. written in a legacy layout, rather than failing the
+    /// initialization of the pruner.
+    fn next_transaction_version(
+        &self,
+        iter: &mut SchemaIterator<TransactionSchema>,
+    ) -> anyhow::Result<Option<Version>> {
+        iter.next_skipping_undecodable(|raw_key, error| {
+            warn!(
+                raw_key = ?raw_key,
+                error = ?error,
+                "{} skipping undecodable transaction.",
+                self.name()
+            );
+            PRUNER_UNDECODABLE_KEYS_TOTAL
+                .with_label_values(&[PrunerKind::Ledger.metric_label(), TRANSACTION_CF_NAME])
+                .inc();
+        })
+        .transpose()
+        .map(|row| row.map(|(version, _transaction)| version))
+    }
+
+    /// Reads the keys of `S` from `first_key` on, up to the first one `version_of` tells is of
+    /// `end` or later, and returns the number read. Unlike the rounds' reads, these fill the block
+    /// cache, which is their point.
+    fn warm_up_schema<S: Schema>(
+        &self,
+        first_key: &S::Key,
+        end: Version,
+        version_of: fn(&S::Key) -> Version,
+    ) -> anyhow::Result<usize> {
+        let mut iter = self.db.iter::<S>(ReadOptions::default())?;
+        iter.seek(first_key)?;
+        let mut num_keys = 0;
+        while let Some(key) = iter.next_key().transpose()? {
+            if version_of(&key) >= end {
+                break;
+            }
+            num_keys += 1;
+        }
+        Ok(num_keys)
+    }
+
+    /// Sets the callback told about the version reached and the keys deleted by each successful
+    /// round, which runs on the pruning thread.
+    #[allow(dead_code)]
+    pub fn with_progress_callback(mut self, progress_callback: PruneProgressCallback) -> Self {
+        self.progress_callback = progress_callback;
+        self
+    }
+
+    /// Caps the bytes each round hands over to the caller to write by `rate_limiter`, sleeping
+    /// on the pruning thread until the round's deletions fit in.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Has rounds taking longer than `slow_batch_threshold` warned about, along with the range
+    /// pruned and the number of keys deleted. Commits along the way and the rate limit are
+    /// accounted for, while the commit of what's handed over at the end is up to the caller.
+    pub fn with_slow_batch_threshold(mut self, slow_batch_threshold: Duration) -> Self {
+        self.slow_batch_threshold = Some(slow_batch_threshold);
+        self
+    }
+
+    /// Has rounds commit their deletions themselves once at least `commit_threshold` are
+    /// collected, which is checked every `STOP_FLAG_CHECK_INTERVAL` versions, rather than handing
+    /// all of them over at the end. A round over a large range then doesn't build up a huge batch
+    /// in memory.
+    pub fn with_commit_threshold(mut self, commit_threshold: usize) -> Self {
+        self.commit_threshold = Some(commit_threshold);
+        self
+    }
+
+    /// Moves the events and write sets pruned into `archive_db`, e.g. a DB on cheaper storage,
+    /// which needs the column families of the write sets, the events and their by key and by
+    /// version indices. What a batch deletes is committed to it right before the batch is handed
+    /// over, or committed in the middle of the round, so nothing is deleted without being
+    /// archived. A round failing before then archives nothing, while a batch failing to commit
+    /// leaves its data in both DBs until the next round archives it again. Nothing is archived
+    /// on dry run.
+    pub fn with_archive_db(mut self, archive_db: Arc<DB>) -> Self {
+        self.archive_db = Some(archive_db);
+        self
+    }
+
+    /// Has the writes the pruner makes itself, i.e. the commits in the middle of a round and the
+    /// repairs, retried up to `max_retries` times on transient failures, backing off
+    /// exponentially from `base_backoff`.
+    pub fn with_write_retries(mut self, max_retries: u32, base_backoff: Duration) -> Self {
+        self.write_retries = Some(WriteRetries::new(max_retries, base_backoff));
+        self
+    }
+
+    /// Keeps the write sets from the oldest checkpoint version registered in `checkpoints` on,
+    /// for restores to replay them, while the rest of the ledger is pruned as usual.
+    #[allow(dead_code)]
+    pub fn with_write_set_checkpoints(mut self, checkpoints: Arc<CheckpointRegistry>) -> Self {
+        self.write_set_pruner = Arc::new(
+            WriteSetPruner::new(Arc::clone(&self.transaction_store)).with_checkpoints(checkpoints),
+        );
+        self
+    }
+
+    /// Has rounds called within `min_prune_interval` of the last commit of the deletions of a
+    /// round do nothing, returning the least readable version, so that a caller spinning on
+    /// `prune()` doesn't hammer the DB with tiny batches. The pruner has no pruning pending in the
+    /// meantime, see `is_pruning_pending()`. Zero, the default, doesn't throttle.
+    pub fn with_min_prune_interval(mut self, min_prune_interval: Duration) -> Self {
+        self.min_prune_interval = min_prune_interval;
+        self
+    }
+
+    /// True if the deletions of a round were committed within `min_prune_interval`.
+    fn is_throttled(&self) -> bool {
+        self.last_committed_at.lock().map_or(false, |committed_at| {
+            committed_at.elapsed() < self.min_prune_interval
+        })
+    }
+
+    /// Keeps the transaction of genesis, along with its events, write set and the rest of its
+    /// ledger data, forever. The rounds start past it, as if it were pruned already, once there is
+    /// a transaction past it, so a DB holding only genesis is left alone whatever the target.
+    /// Genesis is then below the least readable version, like pruned data, while it can still
+    /// be read by version.
+    pub fn with_genesis_kept(mut self) -> Self {
+        self.never_prune_genesis = true;
+        self
+    }
+
+    /// Clamps the targets set past the latest version committed, as told by `latest_version`, to
+    /// it, with a warning, so that a buggy caller doesn't have the rounds go over versions that
+    /// don't exist yet. With nothing committed, the target is 0. The clamping happens as the
+    /// target is set, it's not revisited as more versions are committed.
+    #[allow(dead_code)]
+    pub fn with_latest_version(mut self, latest_version: LatestVersionCallback) -> Self {
+        self.latest_version = Some(latest_version);
+        self
+    }
+
+    /// Keeps the rounds from pruning past the lowest version registered in `read_floors`.
+    #[allow(dead_code)]
+    pub fn with_read_floors(mut self, read_floors: Arc<ReadFloorRegistry>) -> Self {
+        self.read_floors = Some(read_floors);
+        self
+    }
+
+    /// Has the pruner round its target down to right after the end of an epoch, so that every
+    /// epoch is either pruned whole or left whole, e.g. for nodes serving epoch change proofs.
+    /// The rounds of at most `max_versions` catching up with the target still stop mid-epoch,
+    /// but the pruner never settles there. Taking effect from the next round on, it's checked
+    /// against the epoch index each time the target is, which costs a seek.
+    pub fn set_epoch_aligned(&self, epoch_aligned: bool) {
+        self.epoch_aligned.store(epoch_aligned, Ordering::Relaxed)
+    }
+
+    /// Moves the least readable version to `to`, along with the progress persisted, for the next
+    /// rounds to prune from there on again, e.g. after partial data was restored into a range
+    /// already pruned. `to` must be the version of a transaction in the DB and not be past the
+    /// target. Meant for recovering from incidents only, so the pruner must be disabled, see
+    /// `set_enabled()`, which keeps the pruning rounds from running over the reset.
+    #[allow(dead_code)]
+    pub fn reset_progress(&self, to: Version) -> anyhow::Result<()> {
+        ensure!(
+            !self.is_enabled(),
+            "{} must be disabled to reset its progress.",
+            self.name()
+        );
+        let _prune_guard = self.prune_lock.lock();
+        ensure!(
+            to <= self.target_version(),
+            "Can't reset {} progress to {}, past the target version {}.",
+            self.name(),
+            to,
+            self.target_version(),
+        );
+        ensure!(
+            self.db.get::<TransactionSchema>(&to)?.is_some(),
+            "Can't reset {} progress to {}, no transaction is left there.",
+            self.name(),
+            to,
+        );
+        self.db.put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerPrunerProgress,
+            &DbMetadataValue::Version(to),
+        )?;
+        warn!(
+            previous_version = self.least_readable_version(),
+            least_readable_version = to,
+            "{} progress reset.",
+            self.name()
+        );
+        // A round handed over and yet to be committed is overridden.
+        self.pending_progress.lock().take();
+        self.record_progress(to);
+        Ok(())
+    }
+
+    /// Prunes all the way to the target in as few rounds as the prune strategy allows, regardless
+    /// of any batch size, committing the deletions itself, and returns the least readable version
+    /// reached. The deletions are collected in memory, unless a commit threshold is set, see
+    /// `with_commit_threshold()`, for them to be committed along the way. The progress recorded
+    /// goes ahead of the deletions until they are committed, so this is meant for offline
+    /// maintenance, with the node stopped and no reads in flight.
+    #[allow(dead_code)]
+    pub fn prune_unbounded(&self) -> anyhow::Result<Version> {
+        let mut db_batch = SchemaBatch::new();
+        let result = self.prune_to_target(&mut db_batch, Version::MAX);
+        // Rounds before a failing one made progress, which is committed regardless.
+        self.write_schemas(&db_batch)?;
+        self.on_committed();
+        result
+    }
+
+    fn write_schemas(&self, db_batch: &SchemaBatch) -> anyhow::Result<()> {
+        if db_batch.is_empty() {
+            return Ok(());
+        }
+        match &self.write_retries {
+            Some(write_retries) => write_retries.run(|| self.db.write_schemas_ref(db_batch)),
+            None => self.db.write_schemas_ref(db_batch),
+        }
+    }
+
+    /// True if there is a transaction, a write set or an event past genesis.
+    fn has_data_past_genesis(&self) -> anyhow::Result<bool> {
+        let read_options = || PruneReadOptions::default().read_options();
+        let mut transactions = self.db.iter::<TransactionSchema>(read_options())?;
+        transactions.seek(&1)?;
+        let mut write_sets = self.db.iter::<WriteSetSchema>(read_options())?;
+        write_sets.seek(&1)?;
+        let mut events = self.db.iter::<EventSchema>(read_options())?;
+        events.seek(&(1, 0))?;
+        Ok(transactions.next().transpose()?.is_some()
+            || write_sets.next().transpose()?.is_some()
+            || events.next().transpose()?.is_some())
+    }
+
+    /// Runs a round with pruning pending, on behalf of `prune()`.
+    fn prune_round(
+        &self,
+        db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        let _timer = PRUNER_BATCH_DURATION_SECONDS
+            .with_label_values(&[self.kind().metric_label()])
+            .start_timer();
+        // Genesis is skipped rather than deleted, the least readable version staying at it until
+        // the round's deletions are committed.
+        let skips_genesis = self.never_prune_genesis
+            && self.round_start_version() == 0
+            && self.has_data_past_genesis()?;
+        let least_readable_version = if skips_genesis {
+            1
+        } else {
+            self.round_start_version()
+        };
+        // Covers the whole round, commits in the middle of it included, and is kept open for the
+        // caller to enter while committing the rest. The fields are recorded as they become
+        // known.
+        let span = info_span!(
+            LEDGER_PRUNER_NAME,
+            start_version = least_readable_version,
+            target_version = field::Empty,
+            keys_deleted = field::Empty,
+        );
+        let _entered = span.enter();
+        // Current target version might be less than the target version to ensure we don't prune
+        // more than max_version in one go, or if the prune strategy decides so.
+        let mut batch_target_version = if skips_genesis {
+            self.batch_target_from(least_readable_version, max_versions)
+        } else {
+            self.current_batch_target(max_versions)
+        };
+        if batch_target_version < self.prunable_target_version() {
+            PRUNER_BATCH_CLAMPED_TOTAL
+                .with_label_values(&[self.kind().metric_label()])
+                .inc();
+        }
+        // A DB rolled back below the target, e.g. restored from an older snapshot, has its latest
+        // version kept, like any version at the target. The target itself is left as is, for the
+        // round to catch up with it once the DB does.
+        match self.latest_version()? {
+            Some(latest_version) if batch_target_version > latest_version => {
+                if !self.clamped_to_latest_in_db.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        target_version = batch_target_version,
+                        latest_version = latest_version,
+                        "{} target is past the latest version in the DB, clamping to it.",
+                        self.name()
+                    );
+                }
+                batch_target_version = max(latest_version, least_readable_version);
+            }
+            _ => self.clamped_to_latest_in_db.store(false, Ordering::Relaxed),
+        }
+        let current_target_version = min(
+            self.prune_strategy.next_target(
+                least_readable_version,
+                batch_target_version,
+                *self.last_batch_duration.lock(),
+            ),
+            batch_target_version,
+        );
+        span.record("target_version", &current_target_version);
+        *self.last_batch.lock() = Some((max_versions, current_target_version));
+        // The deletions are collected apart from what the caller put in `db_batch`, for a commit
+        // in the middle of the round to write only its own. On dry run, they are dropped
+        // afterwards.
+        let mut round_batch = SchemaBatch::new();
+        let mut keys_committed = 0;
+        // Where the deletions not yet handed over start.
+        let mut batch_start_version = least_readable_version;
+
+        let started_at = Instant::now();
+
+        // The range is pruned in chunks, checking for a stop request in between. Every sub pruner
+        // covers each chunk, so stopping early still leaves all the stores pruned up to the same
+        // version and the next round resumes from there.
+        let mut pruned_version = least_readable_version;
+        while pruned_version < current_target_version {
+            let chunk_target_version = min(
+                pruned_version + STOP_FLAG_CHECK_INTERVAL,
+                current_target_version,
+            );
+            self.prune_sub_stores(&mut round_batch, pruned_version, chunk_target_version)?;
+            pruned_version = chunk_target_version;
+            if self.stop_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            // A batch grown past the threshold in the middle of the round is committed right
+            // away along with the progress so far, bounding the memory it takes. Not while the
+            // batch of an earlier round is handed over and yet to be committed though, since the
+            // progress and what the sub pruners are told committed would then cover that batch
+            // too, so this round's deletions go along with it.
+            let is_over_threshold = self.commit_threshold.map_or(false, |commit_threshold| {
+                round_batch.len() >= commit_threshold
+            });
+            let has_uncommitted_batch = self.pending_progress.lock().is_some();
+            if !self.dry_run
+                && is_over_threshold
+                && !has_uncommitted_batch
+                && pruned_version < current_target_version
+            {
+                keys_committed += round_batch.len();
+                self.archive(batch_start_version, pruned_version)?;
+                batch_start_version = pruned_version;
+                self.finish_batch(&mut round_batch, pruned_version)?;
+                self.write_schemas(&mem::take(&mut round_batch))?;
+                // Only this round's own deletions are committed.
+                self.on_sub_pruners_committed();
+                self.record_progress(pruned_version);
+                PRUNER_INTERMEDIATE_COMMITS_TOTAL
+                    .with_label_values(&[PrunerKind::Ledger.metric_label()])
+                    .inc();
+            }
+        }
+
+        *self.last_batch_duration.lock() = started_at.elapsed();
+        let progress = PruneProgress {
+            version: pruned_version,
+            keys_pruned: keys_committed + round_batch.len(),
+        };
+
+        if !self.dry_run {
+            self.archive(batch_start_version, pruned_version)?;
+            self.finish_batch(&mut round_batch, pruned_version)?;
+            db_batch.merge(round_batch);
+            *self.pending_progress.lock() = Some(pruned_version);
+            *self.round_span.lock() = Some(span.clone());
+        }
+        span.record("keys_deleted", &progress.keys_pruned);
+        warn_if_slow_batch(
+            self.name(),
+            self.slow_batch_threshold,
+            started_at.elapsed(),
+            least_readable_version,
+            pruned_version,
+            progress.keys_pruned,
+        );
+        if pruned_version < current_target_version {
+            return Err(PrunerError::Interrupted(progress));
+        }
+        if !self.dry_run {
+            (self.progress_callback)(pruned_version, progress.keys_pruned);
+        }
+        Ok(progress)
+    }
+
+    /// Commits what the sub pruners delete in the range of versions [least_readable_version,
+    /// target_version) to the archive DB, if any.
+    fn archive(
+        &self,
+        least_readable_version: Version,
+        target_version: Version,
+    ) -> anyhow::Result<()> {
+        let archive_db = match &self.archive_db {
+            Some(archive_db) => archive_db,
+            None => return Ok(()),
+        };
+        let mut archive_batch = SchemaBatch::new();
+        for sub_pruner in [&self.write_set_pruner, &self.event_store_pruner] {
+            sub_pruner.archive(&mut archive_batch, least_readable_version, target_version)?;
+        }
+        if archive_batch.is_empty() {
+            return Ok(());
+        }
+        match &self.write_retries {
+            Some(write_retries) => {
+                write_retries.run(|| archive_db.write_schemas_ref(&archive_batch))
+            }
+            None => archive_db.write_schemas(archive_batch),
+        }
+    }
+
+    /// Counts the deletions in `db_batch`, then adds the progress to the batch, logs the bytes
+    /// it's estimated to write for them and holds it back until it fits in the rate limit.
+    fn finish_batch(
+        &self,
+        db_batch: &mut SchemaBatch,
+        pruned_version: Version,
+    ) -> anyhow::Result<()> {
+        // Deletions are broken down by column family, e.g. to tell the events from their indices
+        // and accumulator.
+        for (cf_name, num_write_ops) in db_batch.len_by_column_family() {
+            PRUNER_KEYS_DELETED_TOTAL
+                .with_label_values(&[PrunerKind::Ledger.metric_label(), cf_name])
+                .inc_by(num_write_ops as u64);
+        }
+        // Persisted along with the deletions, for the pruner to resume from after restart.
+        db_batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerPrunerProgress,
+            &DbMetadataValue::Version(pruned_version),
+        )?;
+        let size_in_bytes = db_batch.size_in_bytes();
+        debug!(
+            pruned_version = pruned_version,
+            size_in_bytes = size_in_bytes,
+            "{} batch ready to be written.",
+            self.name()
+        );
+        // Hold the deletions back until they fit in the rate limit, before they are written.
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.consume(size_in_bytes);
+        }
+        Ok(())
+    }
+
+    fn on_sub_pruners_committed(&self) {
+        self.transaction_store_pruner.on_committed();
+        self.write_set_pruner.on_committed();
+        self.ledger_counter_pruner.on_committed();
+        if let Some(transaction_accumulator_pruner) = &self.transaction_accumulator_pruner {
+            transaction_accumulator_pruner.on_committed();
+        }
+        self.event_store_pruner.on_committed();
+    }
+
+    /// Prunes the transaction accumulator nodes of the versions pruned along with the rest, keeping
+    /// the ones the proofs of the versions left readable need. The consistency proofs from pruned
+    /// versions can no longer be served then. The deletions are counted under the
+    /// "transaction_accumulator" sub component, and `initialize_and_repair()` also gets rid of the
+    /// nodes left behind before the accumulator was pruned.
+    pub fn with_transaction_accumulator_pruning(mut self) -> Self {
+        self.transaction_accumulator_pruner = Some(Arc::new(TransactionAccumulatorPruner::new(
+            Arc::clone(&self.ledger_store),
+        )));
+        self
+    }
+
+    /// Replaces the event store sub-pruner, e.g. by one set up to keep the latest event of each
+    /// key, or in tests by one failing on purpose.
+    pub(in crate::pruner) fn with_event_store_pruner(
+        mut self,
+        event_store_pruner: Arc<dyn DBSubPruner + Send + Sync>,
+    ) -> Self {
+        self.event_store_pruner = event_store_pruner;
+        self
+    }
+
+    fn prune_sub_stores(
+        &self,
+        db_batch: &mut SchemaBatch,
+        least_readable_version: Version,
+        target_version: Version,
+    ) -> anyhow::Result<()> {
+        // The transactions, write sets and events of a version make up one ledger entry, so the
+        // deletions of the range are only handed over once every sub pruner is done with it. A
+        // sub pruner failing midway then never leaves, e.g., a transaction whose events are gone
+        // once the batch is committed.
+        let mut range_batch = SchemaBatch::new();
+        self.transaction_store_pruner.prune(
+            &mut range_batch,
+            least_readable_version,
+            target_version,
+        )?;
+        self.write_set_pruner
+            .prune(&mut range_batch, least_readable_version, target_version)?;
+        self.ledger_counter_pruner.prune(
+            &mut range_batch,
+            least_readable_version,
+            target_version,
+        )?;
+        if let Some(transaction_accumulator_pruner) = &self.transaction_accumulator_pruner {
+            transaction_accumulator_pruner.prune(
+                &mut range_batch,
+                least_readable_version,
+                target_version,
+            )?;
+        }
+        self.event_store_pruner
+            .prune(&mut range_batch, least_readable_version, target_version)?;
+        db_batch.merge(range_batch);
+        Ok(())
+    }
 }
@@ -2,4 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub(crate) mod ledger_counter_pruner;
+pub(crate) mod ledger_info_pruner;
 pub(crate) mod ledger_store_pruner;
+#[cfg(test)]
+mod test;
+pub(crate) mod transaction_accumulator_pruner;
@@ -0,0 +1,442 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    metrics::{PRUNER_BATCH_CLAMPED_TOTAL, PRUNER_UNDECODABLE_KEYS_TOTAL},
+    pruner::{
+        db_pruner::DBPruner, ledger_store::ledger_info_pruner::LedgerInfoPruner,
+        pruner_kind::PrunerKind, transaction_store::test::create_ledger_pruner,
+        write_retries::WriteRetries,
+    },
+    schema::{
+        db_metadata::{DbMetadataKey, DbMetadataSchema, DbMetadataValue},
+        ledger_info::LedgerInfoSchema,
+        transaction_accumulator::TransactionAccumulatorSchema,
+        TRANSACTION_CF_NAME,
+    },
+    AptosDB, ChangeSet,
+};
+use anyhow::{format_err, Result};
+use aptos_crypto::{hash::CryptoHash, HashValue};
+use aptos_temppath::TempPath;
+use aptos_types::{
+    block_info::BlockInfo,
+    epoch_state::EpochState,
+    ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
+    proof::position::{FrozenSubTreeIterator, Position},
+    transaction::{ExecutionStatus, Transaction, TransactionInfo, Version},
+};
+use byteorder::{BigEndian, ReadBytesExt};
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+    Options, SchemaBatch, DB, DEFAULT_CF_NAME,
+};
+use std::{collections::BTreeMap, path::Path, sync::Arc, time::Duration};
+
+// Shares the column family of the transactions, with keys shorter than a version.
+define_schema!(LegacyTransactionSchema, u32, Vec<u8>, TRANSACTION_CF_NAME);
+
+impl KeyCodec<LegacyTransactionSchema> for u32 {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+
+    fn decode_key(mut data: &[u8]) -> Result<Self> {
+        Ok(data.read_u32::<BigEndian>()?)
+    }
+}
+
+impl ValueCodec<LegacyTransactionSchema> for Vec<u8> {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(self.clone())
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+fn ledger_info(epoch: u64, version: Version, ends_epoch: bool) -> LedgerInfoWithSignatures {
+    LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                epoch,
+                version, /* round */
+                HashValue::zero(),
+                HashValue::zero(),
+                version,
+                0, /* timestamp_usecs */
+                ends_epoch.then(EpochState::empty),
+            ),
+            HashValue::zero(),
+        ),
+        BTreeMap::new(),
+    )
+}
+
+/// Writes `db_batch` with `write_retries` through a DB failing the first `num_failures` writes
+/// with what `failure` returns, returning the result along with the number of writes attempted.
+fn write_with_failures(
+    db: &DB,
+    db_batch: &SchemaBatch,
+    write_retries: &WriteRetries,
+    num_failures: u32,
+    failure: impl Fn() -> anyhow::Error,
+) -> (anyhow::Result<()>, u32) {
+    let mut attempts = 0;
+    let result = write_retries.run(|| {
+        attempts += 1;
+        if attempts <= num_failures {
+            Err(failure())
+        } else {
+            db.write_schemas_ref(db_batch)
+        }
+    });
+    (result, attempts)
+}
+
+/// Returns a RocksDB I/O error, as opening the DB at `path`, which is already open, fails on its
+/// lock with.
+fn io_error(path: &Path) -> anyhow::Error {
+    DB::open(path, "test", vec![DEFAULT_CF_NAME], &Options::default())
+        .err()
+        .expect("Opening a DB already open fails.")
+}
+
+fn stored_epochs(aptos_db: &AptosDB, epochs: impl Iterator<Item = u64>) -> Vec<u64> {
+    epochs
+        .filter(|epoch| {
+            aptos_db
+                .db
+                .get::<LedgerInfoSchema>(epoch)
+                .unwrap()
+                .is_some()
+        })
+        .collect()
+}
+
+#[test]
+fn test_ledger_info_pruner() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let ledger_store = &aptos_db.ledger_store;
+
+    // Epochs 0 and 2 end at versions 5 and 15, while the ledger info of epoch 1 was left behind
+    // without ending it, and epoch 3 is the latest.
+    let mut cs = ChangeSet::new();
+    for (epoch, version, ends_epoch) in
+        [(0, 5, true), (1, 10, false), (2, 15, true), (3, 20, false)]
+    {
+        ledger_store
+            .put_ledger_info(&ledger_info(epoch, version, ends_epoch), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let pruner = LedgerInfoPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(ledger_store),
+        0, /* prune_window */
+    );
+
+    // The left behind ledger info is still readable up to its version.
+    pruner.set_target_version(10);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, 100).unwrap();
+    assert_eq!(progress.version, 10);
+    assert_eq!(progress.keys_pruned, 0);
+    assert_eq!(pruner.last_status().unwrap().error, None);
+    assert_eq!(pruner.record_last_error_age(), None);
+    // The progress is recorded only once the round's deletions are committed.
+    assert_eq!(pruner.least_readable_version(), 0);
+
+    pruner.set_target_version(11);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(progress.keys_pruned, 1);
+    assert_eq!(pruner.least_readable_version(), 11);
+    assert_eq!(stored_epochs(&aptos_db, 0..4), vec![0, 2, 3]);
+
+    // Past all the versions, the epoch-ending ledger infos and the latest one are kept.
+    pruner.set_target_version(100);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(progress.version, 100);
+    assert_eq!(progress.keys_pruned, 0);
+    assert_eq!(stored_epochs(&aptos_db, 0..4), vec![0, 2, 3]);
+
+    // A pruner created after restart resumes from the progress committed.
+    let pruner = LedgerInfoPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(ledger_store),
+        0, /* prune_window */
+    );
+    assert_eq!(pruner.least_readable_version(), 100);
+}
+
+#[test]
+fn test_record_progress_returns_advance() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let pruner = LedgerInfoPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(&aptos_db.ledger_store),
+        0, /* prune_window */
+    );
+
+    for (least_readable_version, advance) in [(5, 5), (5, 0), (12, 7), (13, 1), (3, 0), (10, 7)] {
+        assert_eq!(pruner.record_progress(least_readable_version), advance);
+        assert_eq!(pruner.least_readable_version(), least_readable_version);
+    }
+}
+
+#[test]
+fn test_ledger_info_pruner_counts_clamped_batches() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let pruner = LedgerInfoPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(&aptos_db.ledger_store),
+        0, /* prune_window */
+    );
+    let clamped = || {
+        PRUNER_BATCH_CLAMPED_TOTAL
+            .with_label_values(&[PrunerKind::LedgerInfo.metric_label()])
+            .get()
+    };
+
+    // Every round falling short of the target is counted, the one reaching it isn't.
+    pruner.set_target_version(100);
+    for round in 1..=10 {
+        let clamped_before = clamped();
+        let progress = pruner.prune(&mut SchemaBatch::new(), 10).unwrap();
+        assert_eq!(progress.version, round * 10);
+        let expected = if round < 10 { 1 } else { 0 };
+        assert_eq!(clamped() - clamped_before, expected);
+    }
+}
+
+#[test]
+fn test_write_retries() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let write_retries = WriteRetries::new(3, Duration::from_millis(1));
+    let mut db_batch = SchemaBatch::new();
+    db_batch
+        .put::<DbMetadataSchema>(
+            &DbMetadataKey::LedgerPrunerProgress,
+            &DbMetadataValue::Version(7),
+        )
+        .unwrap();
+    let db_path = tmp_dir.path().join("aptosdb");
+    let io_error = || io_error(&db_path);
+
+    // More failures than retries give up, without anything written.
+    let (result, attempts) =
+        write_with_failures(&aptos_db.db, &db_batch, &write_retries, 4, io_error);
+    assert!(result.is_err());
+    assert_eq!(attempts, 4);
+    assert!(aptos_db
+        .db
+        .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+        .unwrap()
+        .is_none());
+
+    // Errors that aren't transient are not retried.
+    let (result, attempts) =
+        write_with_failures(&aptos_db.db, &db_batch, &write_retries, 1, || {
+            format_err!("Corruption: block checksum mismatch")
+        });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+
+    let (result, attempts) =
+        write_with_failures(&aptos_db.db, &db_batch, &write_retries, 3, io_error);
+    result.unwrap();
+    assert_eq!(attempts, 4);
+    assert_eq!(
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version()),
+        Some(7)
+    );
+}
+
+#[test]
+fn test_ledger_pruner_skips_undecodable_transactions() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let mut cs = ChangeSet::new();
+    for version in 3..6 {
+        aptos_db
+            .transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    // Sorted before and after the transactions respectively.
+    for legacy_key in [0, 7] {
+        cs.batch
+            .put::<LegacyTransactionSchema>(&legacy_key, &vec![0xff])
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let undecodable_keys = || {
+        PRUNER_UNDECODABLE_KEYS_TOTAL
+            .with_label_values(&[PrunerKind::Ledger.metric_label(), TRANSACTION_CF_NAME])
+            .get()
+    };
+    let undecodable_keys_before = undecodable_keys();
+
+    // Initialized on creation, looking up the first transaction.
+    let pruner = create_ledger_pruner(&aptos_db);
+    assert_eq!(pruner.least_readable_version(), 3);
+    assert_eq!(undecodable_keys(), undecodable_keys_before + 1);
+
+    // The round looks up the latest transaction, found past the legacy key as well.
+    pruner.set_target_version(10);
+    let progress = pruner.prune(&mut SchemaBatch::new(), 100).unwrap();
+    assert_eq!(progress.version, 6);
+    assert_eq!(pruner.target_version(), 10);
+    assert_eq!(undecodable_keys(), undecodable_keys_before + 2);
+}
+
+#[test]
+fn test_ledger_pruner_epoch_aligned() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let num_txns = 15;
+    // Epochs 0 and 1 end at versions 4 and 9, while epoch 2 goes on.
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        aptos_db
+            .transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    for (epoch, version, ends_epoch) in [(0, 4, true), (1, 9, true), (2, 14, false)] {
+        aptos_db
+            .ledger_store
+            .put_ledger_info(&ledger_info(epoch, version, ends_epoch), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let pruner = create_ledger_pruner(&aptos_db);
+    pruner.set_epoch_aligned(true);
+    let prune = || {
+        let mut db_batch = SchemaBatch::new();
+        let progress = pruner.prune(&mut db_batch, num_txns).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+        progress.version
+    };
+
+    // No whole epoch fits under the target, so nothing is pruned.
+    pruner.set_target_version(4);
+    assert!(!pruner.is_pruning_pending());
+    assert_eq!(prune(), 0);
+
+    // Mid-epoch targets are rounded down to right after the end of the last whole epoch.
+    pruner.set_target_version(7);
+    assert_eq!(prune(), 5);
+    pruner.set_target_version(10);
+    assert_eq!(prune(), 10);
+    pruner.set_target_version(14);
+    assert!(!pruner.is_pruning_pending());
+    assert_eq!(prune(), 10);
+
+    pruner.set_epoch_aligned(false);
+    assert_eq!(prune(), 14);
+}
+
+#[test]
+fn test_transaction_accumulator_pruner() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let ledger_store = &aptos_db.ledger_store;
+    let num_txns = 13;
+    let txn_infos: Vec<_> = (0..num_txns)
+        .map(|_| {
+            TransactionInfo::new(
+                HashValue::random(),
+                HashValue::random(),
+                HashValue::random(),
+                None,
+                0,
+                ExecutionStatus::Success,
+            )
+        })
+        .collect();
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        aptos_db
+            .transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    ledger_store
+        .put_transaction_infos(0, &txn_infos, &mut cs)
+        .unwrap();
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let ledger_version = num_txns - 1;
+    let root_hash = ledger_store.get_root_hash(ledger_version).unwrap();
+
+    let pruner = create_ledger_pruner(&aptos_db).with_transaction_accumulator_pruning();
+    for target_version in [6, 12] {
+        pruner.set_target_version(target_version);
+        let mut db_batch = SchemaBatch::new();
+        pruner.prune(&mut db_batch, num_txns).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+
+        // Everything from the boundary on is still provable against the same root.
+        assert_eq!(
+            ledger_store.get_root_hash(ledger_version).unwrap(),
+            root_hash
+        );
+        for version in target_version..num_txns {
+            ledger_store
+                .get_transaction_proof(version, ledger_version)
+                .unwrap()
+                .verify(root_hash, txn_infos[version as usize].hash(), version)
+                .unwrap();
+        }
+        let txn_hashes: Vec<_> = txn_infos[target_version as usize..]
+            .iter()
+            .map(CryptoHash::hash)
+            .collect();
+        ledger_store
+            .get_transaction_range_proof(
+                Some(target_version),
+                num_txns - target_version,
+                ledger_version,
+            )
+            .unwrap()
+            .verify(root_hash, Some(target_version), &txn_hashes)
+            .unwrap();
+
+        // Below the boundary only the frozen subtree roots are left.
+        assert!(ledger_store
+            .get_transaction_proof(target_version - 1, ledger_version)
+            .is_err());
+        for position in FrozenSubTreeIterator::new(target_version) {
+            assert!(aptos_db
+                .db
+                .get::<TransactionAccumulatorSchema>(&position)
+                .unwrap()
+                .is_some());
+        }
+        assert!(aptos_db
+            .db
+            .get::<TransactionAccumulatorSchema>(&Position::from_leaf_index(target_version - 1))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+use crate::{pruner::db_sub_pruner::DBSubPruner, LedgerStore};
+use aptos_types::transaction::Version;
+use schemadb::SchemaBatch;
+use std::sync::Arc;
+
+pub struct TransactionAccumulatorPruner {
+    ledger_store: Arc<LedgerStore>,
+}
+
+impl DBSubPruner for TransactionAccumulatorPruner {
+    fn prune(
+        &self,
+        db_batch: &mut SchemaBatch,
+        least_readable_version: u64,
+        target_version: u64,
+    ) -> anyhow::Result<()> {
+        self.ledger_store.prune_transaction_accumulator(
+            least_readable_version,
+            target_version,
+            db_batch,
+        )
+    }
+
+    fn repair(
+        &self,
+        db_batch: &mut SchemaBatch,
+        least_readable_version: Version,
+    ) -> anyhow::Result<()> {
+        // Also catches the nodes left behind by the rounds before the accumulator was pruned.
+        self.ledger_store
+            .prune_transaction_accumulator(0, least_readable_version, db_batch)
+    }
+}
+
+impl TransactionAccumulatorPruner {
+    pub fn new(ledger_store: Arc<LedgerStore>) -> Self {
+        TransactionAccumulatorPruner { ledger_store }
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! A `DBPruner` test double needing no DB, for testing what orchestrates pruners, e.g.
+//! `PrunerManager`, without setting up RocksDB. It advances by a fixed step per round and keeps
+//! track of the calls it gets. Other crates get it with the `testing` feature:
+//!
+//! ```ignore
+//! let pruner = Arc::new(MockPruner::new(2 /* step */));
+//! pruner.set_target_version(5);
+//! pruner.prune(&mut SchemaBatch::new(), 10).unwrap();
+//! assert_eq!(pruner.least_readable_version(), 2);
+//! assert_eq!(pruner.target_versions_set(), vec![5]);
+//! assert_eq!(pruner.progress_recorded(), vec![2]);
+//! ```
+
+use crate::pruner::{
+    db_pruner::{DBPruner, PruneProgress, PrunerError},
+    pruner_kind::PrunerKind,
+};
+use anyhow::anyhow;
+use aptos_infallible::{duration_since_epoch, Mutex};
+use aptos_types::transaction::{AtomicVersion, Version};
+use schemadb::SchemaBatch;
+use std::{
+    cmp::min,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+pub const MOCK_PRUNER_NAME: &str = PrunerKind::Mock.name();
+
+/// Prunes nothing but pretends to, each round advancing by up to `step` versions within what it's
+/// allowed to, and deleting one key per version.
+pub struct MockPruner {
+    step: Version,
+    target_version: AtomicVersion,
+    least_readable_version: AtomicVersion,
+    enabled: AtomicBool,
+    num_prune_calls: AtomicUsize,
+    /// Rounds fail on IO while set, see `set_failing_on_io()`.
+    failing_on_io: AtomicBool,
+    /// Every version passed to `set_target_version()`, in order.
+    target_versions_set: Mutex<Vec<Version>>,
+    /// Every version passed to `record_progress()`, in order.
+    progress_recorded: Mutex<Vec<Version>>,
+    /// When the least readable version last changed, since the UNIX epoch.
+    progress_updated_at: Mutex<Option<Duration>>,
+}
+
+impl Default for MockPruner {
+    /// Prunes as far as allowed in each round.
+    fn default() -> Self {
+        Self::new(Version::MAX)
+    }
+}
+
+impl MockPruner {
+    pub fn new(step: Version) -> Self {
+        Self {
+            step,
+            target_version: AtomicVersion::new(0),
+            least_readable_version: AtomicVersion::new(0),
+            enabled: AtomicBool::new(true),
+            num_prune_calls: AtomicUsize::new(0),
+            failing_on_io: AtomicBool::new(false),
+            target_versions_set: Mutex::new(vec![]),
+            progress_recorded: Mutex::new(vec![]),
+            progress_updated_at: Mutex::new(None),
+        }
+    }
+
+    /// Returns the number of times `prune()` was called, those with nothing to do included.
+    pub fn num_prune_calls(&self) -> usize {
+        self.num_prune_calls.load(Ordering::Relaxed)
+    }
+
+    /// Has the rounds fail with `PrunerError::Io` without advancing while `failing` is set.
+    pub fn set_failing_on_io(&self, failing: bool) {
+        self.failing_on_io.store(failing, Ordering::Relaxed)
+    }
+
+    pub fn target_versions_set(&self) -> Vec<Version> {
+        self.target_versions_set.lock().clone()
+    }
+
+    pub fn progress_recorded(&self) -> Vec<Version> {
+        self.progress_recorded.lock().clone()
+    }
+}
+
+impl DBPruner for MockPruner {
+    fn kind(&self) -> PrunerKind {
+        PrunerKind::Mock
+    }
+
+    fn prune(
+        &self,
+        _db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        self.num_prune_calls.fetch_add(1, Ordering::Relaxed);
+        if self.failing_on_io.load(Ordering::Relaxed) {
+            return Err(PrunerError::Io(anyhow!("Injected IO failure.")));
+        }
+        let least_readable_version = self.least_readable_version();
+        let version = min(
+            self.current_batch_target(max_versions),
+            least_readable_version.saturating_add(self.step),
+        );
+        let keys_pruned = (version - least_readable_version) as usize;
+        self.record_progress(version);
+        Ok(PruneProgress {
+            version,
+            keys_pruned,
+        })
+    }
+
+    fn initialize_least_readable_version(&self) -> anyhow::Result<Version> {
+        Ok(0)
+    }
+
+    fn least_readable_version(&self) -> Version {
+        self.least_readable_version.load(Ordering::Relaxed)
+    }
+
+    fn set_target_version(&self, target_version: Version) {
+        self.target_versions_set.lock().push(target_version);
+        self.target_version.store(target_version, Ordering::Relaxed)
+    }
+
+    fn target_version(&self) -> Version {
+        self.target_version.load(Ordering::Relaxed)
+    }
+
+    fn prune_window(&self) -> Version {
+        0
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn commit(&self, _db_batch: SchemaBatch) -> anyhow::Result<()> {
+        // Nothing is deleted.
+        Ok(())
+    }
+
+    fn record_progress(&self, least_readable_version: Version) -> Version {
+        self.progress_recorded.lock().push(least_readable_version);
+        let previous_version = self
+            .least_readable_version
+            .swap(least_readable_version, Ordering::Relaxed);
+        if least_readable_version != previous_version {
+            *self.progress_updated_at.lock() = Some(duration_since_epoch());
+        }
+        least_readable_version.saturating_sub(previous_version)
+    }
+
+    fn progress_updated_at(&self) -> Option<Duration> {
+        *self.progress_updated_at.lock()
+    }
+}
@@ -4,25 +4,44 @@
 //! This module provides `Pruner` which manages a thread pruning old data in the background and is
 //! meant to be triggered by other threads as they commit new data to the DB.
 
+pub(crate) mod checkpoint_provider;
+pub(crate) mod checkpoint_registry;
+pub(crate) mod clock;
 mod db_pruner;
 pub(crate) mod db_sub_pruner;
+pub(crate) mod deletion_order;
 pub(crate) mod event_store;
 mod ledger_store;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock_pruner;
+pub(crate) mod prune_read_options;
+pub(crate) mod prune_schedule;
+pub(crate) mod prune_strategy;
+pub(crate) mod pruner_kind;
+pub(crate) mod pruner_manager;
+pub(crate) mod rate_limiter;
+pub(crate) mod read_floor;
 pub(crate) mod state_store;
+pub(crate) mod state_sync_reservation;
 pub(crate) mod transaction_store;
 pub mod utils;
 pub(crate) mod worker;
+pub(crate) mod write_retries;
+
+#[cfg(any(test, feature = "testing"))]
+pub use db_pruner::DBPruner;
 
 use crate::metrics::{PRUNER_BATCH_SIZE, PRUNER_WINDOW};
 
 use aptos_config::config::StoragePrunerConfig;
 use aptos_infallible::Mutex;
 
-use crate::{pruner::PrunerIndex::LedgerPrunerIndex, EventStore, LedgerStore, TransactionStore};
+use crate::{EventStore, LedgerStore, TransactionStore};
 use aptos_types::transaction::Version;
 use schemadb::DB;
 use std::{
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{channel, Sender},
         Arc,
     },
@@ -60,14 +79,23 @@ pub(crate) struct Pruner {
     pruning_batch_size: usize,
     /// latest version
     latest_version: Arc<Mutex<Version>>,
+    /// Set upon destruction so that a long pruning round in the worker thread stops early.
+    stop_flag: Arc<AtomicBool>,
+    /// While set, the worker thread runs no pruning round, see `pause()`.
+    paused: Arc<AtomicBool>,
 }
 
+/// The position of each pruner among the ones the worker runs, see `utils::create_db_pruners()`.
 pub enum PrunerIndex {
-    #[allow(dead_code)]
     StateStorePrunerIndex,
     LedgerPrunerIndex,
 }
 
+impl PrunerIndex {
+    /// The number of pruners the worker runs.
+    pub const NUM_PRUNERS: usize = PrunerIndex::LedgerPrunerIndex as usize + 1;
+}
+
 impl Pruner {
     /// Creates a worker thread that waits on a channel for pruning commands.
     pub fn new(
@@ -76,10 +104,11 @@ impl Pruner {
         transaction_store: Arc<TransactionStore>,
         ledger_store: Arc<LedgerStore>,
         event_store: Arc<EventStore>,
+        archive_db: Option<Arc<DB>>,
     ) -> Self {
         let (command_sender, command_receiver) = channel();
 
-        let least_readable_version = Arc::new(Mutex::new(vec![0, 0, 0, 0, 0]));
+        let least_readable_version = Arc::new(Mutex::new(vec![0; PrunerIndex::NUM_PRUNERS]));
         let worker_progress_clone = Arc::clone(&least_readable_version);
 
         PRUNER_WINDOW
@@ -92,6 +121,8 @@ impl Pruner {
 
         PRUNER_BATCH_SIZE.set(storage_pruner_config.pruning_batch_size as i64);
 
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
         let worker = Worker::new(
             db,
             transaction_store,
@@ -99,7 +130,10 @@ impl Pruner {
             event_store,
             command_receiver,
             least_readable_version,
-            storage_pruner_config.pruning_batch_size as u64,
+            storage_pruner_config,
+            Arc::clone(&stop_flag),
+            Arc::clone(&paused),
+            archive_db,
         );
         let worker_thread = std::thread::Builder::new()
             .name("aptosdb_pruner".into())
@@ -119,6 +153,8 @@ impl Pruner {
             last_version_sent_to_pruners: Arc::new(Mutex::new(0)),
             pruning_batch_size: storage_pruner_config.pruning_batch_size,
             latest_version: Arc::new(Mutex::new(0)),
+            stop_flag,
+            paused,
         }
     }
 
@@ -131,7 +167,13 @@ impl Pruner {
     }
 
     pub fn get_least_readable_ledger_version(&self) -> Version {
-        self.least_readable_version.lock()[LedgerPrunerIndex as usize]
+        self.least_readable_version.lock()[PrunerIndex::LedgerPrunerIndex as usize]
+    }
+
+    /// Returns the least readable version across all the pruners, which reads spanning the
+    /// stores are gated by, see `utils::combined_least_readable_version()`.
+    pub fn get_combined_least_readable_version(&self) -> Version {
+        utils::combined_least_readable_version(&self.least_readable_version.lock())
     }
     /// Sends pruning command to the worker thread when necessary.
     pub fn maybe_wake_pruner(&self, latest_version: Version) {
@@ -144,19 +186,38 @@ impl Pruner {
         }
     }
 
+    /// Pauses all the pruners at once, e.g. to freeze what's readable while debugging. A round in
+    /// flight runs to its end, the worker thread running none after it until `resume()`, while
+    /// the targets are still updated.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes the pruning paused by `pause()`, from where it was up to the latest targets.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        // The worker thread waits for a command while paused.
+        self.wake_pruner(*self.latest_version.lock());
+    }
+
+    /// True if the pruning is paused by `pause()`.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
     fn wake_pruner(&self, latest_version: Version) {
         let least_readable_state_store_version =
             latest_version.saturating_sub(self.state_store_prune_window);
         let least_readable_ledger_version = latest_version.saturating_sub(self.ledger_prune_window);
 
+        let mut target_db_versions = vec![0; PrunerIndex::NUM_PRUNERS];
+        target_db_versions[PrunerIndex::StateStorePrunerIndex as usize] =
+            least_readable_state_store_version;
+        target_db_versions[PrunerIndex::LedgerPrunerIndex as usize] = least_readable_ledger_version;
+
         self.command_sender
             .lock()
-            .send(Command::Prune {
-                target_db_versions: vec![
-                    least_readable_state_store_version,
-                    least_readable_ledger_version,
-                ],
-            })
+            .send(Command::Prune { target_db_versions })
             .expect("Receiver should not destruct prematurely.");
     }
 
@@ -203,6 +264,7 @@ impl Pruner {
 
 impl Drop for Pruner {
     fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
         self.command_sender
             .lock()
             .send(Command::Quit)
@@ -0,0 +1,24 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use schemadb::ReadOptions;
+
+/// How the iterators going through data about to be pruned read the DB. Unlike regular reads,
+/// they visit every block once right before its keys are deleted, so by default they leave the
+/// block cache alone rather than evicting the blocks regular reads keep coming back to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PruneReadOptions {
+    /// If set, the blocks read are added to the block cache.
+    pub fill_cache: bool,
+    /// If set, seeks go by the total order of the keys regardless of any prefix extractor.
+    pub total_order_seek: bool,
+}
+
+impl PruneReadOptions {
+    pub fn read_options(&self) -> ReadOptions {
+        let mut read_options = ReadOptions::default();
+        read_options.fill_cache(self.fill_cache);
+        read_options.set_total_order_seek(self.total_order_seek);
+        read_options
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::pruner::clock::{Clock, SystemClock};
+use anyhow::ensure;
+use std::{sync::Arc, time::Duration};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A daily range of time [start, end), as the times of day in UTC it starts and ends at. A
+/// window ending before it starts spans midnight.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PruneWindow {
+    start: Duration,
+    end: Duration,
+}
+
+impl PruneWindow {
+    pub fn new(start: Duration, end: Duration) -> anyhow::Result<Self> {
+        ensure!(
+            start < DAY && end < DAY,
+            "Prune window {:?}..{:?} is not within a day.",
+            start,
+            end,
+        );
+        ensure!(
+            start != end,
+            "Prune window {:?}..{:?} is empty.",
+            start,
+            end
+        );
+        Ok(Self { start, end })
+    }
+
+    fn contains(&self, time_of_day: Duration) -> bool {
+        if self.start < self.end {
+            self.start <= time_of_day && time_of_day < self.end
+        } else {
+            self.start <= time_of_day || time_of_day < self.end
+        }
+    }
+
+    /// Returns the time from `time_of_day` until the window next opens, zero if it's open.
+    fn time_until_open(&self, time_of_day: Duration) -> Duration {
+        if self.contains(time_of_day) {
+            Duration::ZERO
+        } else if self.start > time_of_day {
+            self.start - time_of_day
+        } else {
+            self.start + DAY - time_of_day
+        }
+    }
+}
+
+/// The windows of the day pruning is allowed in, e.g. to keep it to low traffic hours.
+pub struct PruneSchedule {
+    windows: Vec<PruneWindow>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl PruneSchedule {
+    /// Allows pruning within any of `windows`, of which there must be at least one, as told by
+    /// the wall clock.
+    pub fn new(windows: Vec<PruneWindow>) -> anyhow::Result<Self> {
+        ensure!(!windows.is_empty(), "A prune schedule needs a window.");
+        Ok(Self {
+            windows,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Allows pruning daily from `start_hour` to `end_hour` in UTC, e.g. as configured.
+    pub fn from_utc_hours(start_hour: u8, end_hour: u8) -> anyhow::Result<Self> {
+        Self::new(vec![PruneWindow::new(
+            HOUR * start_hour as u32,
+            HOUR * end_hour as u32,
+        )?])
+    }
+
+    /// Has the schedule tell the time by `clock` rather than the wall clock.
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the time until any of the windows next opens, zero if one is open.
+    pub fn time_until_open(&self) -> Duration {
+        let time_of_day = Duration::from_secs(self.clock.now().as_secs() % DAY.as_secs());
+        self.windows
+            .iter()
+            .map(|window| window.time_until_open(time_of_day))
+            .min()
+            .expect("A prune schedule has a window.")
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_logger::warn;
+use aptos_types::transaction::Version;
+use schemadb::DB;
+use std::{
+    cmp::{max, min},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Decides how far each pruning round goes, within what the caller of `prune()` allows, e.g. to
+/// prune less when the rounds take too long.
+pub trait PruneStrategy {
+    /// Returns the version the next round prunes up to, given the least readable version, the
+    /// furthest version the round is allowed to reach, and how long the last round took. A
+    /// version beyond `target` is capped at it.
+    fn next_target(
+        &self,
+        least_readable: Version,
+        target: Version,
+        last_batch_duration: Duration,
+    ) -> Version;
+}
+
+/// Always prunes as far as allowed, which is the fixed number of versions the caller asks for.
+pub struct FixedBatchStrategy;
+
+impl PruneStrategy for FixedBatchStrategy {
+    fn next_target(
+        &self,
+        _least_readable: Version,
+        target: Version,
+        _last_batch_duration: Duration,
+    ) -> Version {
+        target
+    }
+}
+
+/// Prunes a pseudo-random number of versions each round, at least one and at most what's allowed,
+/// drawn from a seed rather than from how long the rounds take. Given the same seed and the same
+/// rounds asked for, it always cuts them the same way, for property tests of the batching.
+#[cfg(test)]
+pub struct SeededBatchStrategy {
+    /// The state of the generator, advanced on every draw.
+    state: AtomicU64,
+}
+
+#[cfg(test)]
+impl SeededBatchStrategy {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Draws the next number, by SplitMix64.
+    fn next_random(&self) -> u64 {
+        const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut z = self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+            .wrapping_add(GOLDEN_GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+impl PruneStrategy for SeededBatchStrategy {
+    fn next_target(
+        &self,
+        least_readable: Version,
+        target: Version,
+        _last_batch_duration: Duration,
+    ) -> Version {
+        // Nothing is drawn with nothing allowed.
+        if target <= least_readable {
+            return least_readable;
+        }
+        least_readable + 1 + self.next_random() % (target - least_readable)
+    }
+}
+
+/// Tells how much disk space the DB takes, for `DiskWatermarkStrategy` to go by.
+pub trait DataSizeEstimator {
+    fn estimate_live_data_size(&self) -> anyhow::Result<u64>;
+}
+
+impl DataSizeEstimator for DB {
+    fn estimate_live_data_size(&self) -> anyhow::Result<u64> {
+        DB::estimate_live_data_size(self)
+    }
+}
+
+/// Prunes by disk usage rather than by version: nothing until the live data size reaches the high
+/// watermark, then a fixed number of versions per round until it drops below the low watermark.
+/// Whatever the size, versions from the retained version floor on are never pruned.
+///
+/// The size only shrinks as compaction reclaims the space of what's deleted, so pruning may go on
+/// for a few rounds past the point where the low watermark would have been reached.
+pub struct DiskWatermarkStrategy {
+    size_estimator: Arc<dyn DataSizeEstimator + Send + Sync>,
+    low_watermark_bytes: u64,
+    high_watermark_bytes: u64,
+    versions_per_round: Version,
+    retained_version_floor: Version,
+    /// Set from the high watermark being reached until the size drops below the low one.
+    triggered: AtomicBool,
+}
+
+impl DiskWatermarkStrategy {
+    #[allow(dead_code)]
+    pub fn new(
+        size_estimator: Arc<dyn DataSizeEstimator + Send + Sync>,
+        low_watermark_bytes: u64,
+        high_watermark_bytes: u64,
+        versions_per_round: Version,
+        retained_version_floor: Version,
+    ) -> Self {
+        assert!(
+            low_watermark_bytes <= high_watermark_bytes,
+            "Low watermark must not be above the high watermark."
+        );
+        Self {
+            size_estimator,
+            low_watermark_bytes,
+            high_watermark_bytes,
+            versions_per_round,
+            retained_version_floor,
+            triggered: AtomicBool::new(false),
+        }
+    }
+}
+
+impl PruneStrategy for DiskWatermarkStrategy {
+    fn next_target(
+        &self,
+        least_readable: Version,
+        target: Version,
+        _last_batch_duration: Duration,
+    ) -> Version {
+        let size = match self.size_estimator.estimate_live_data_size() {
+            Ok(size) => size,
+            Err(e) => {
+                warn!(error = ?e, "Failed estimating DB size, not pruning.");
+                return least_readable;
+            }
+        };
+        if size >= self.high_watermark_bytes {
+            self.triggered.store(true, Ordering::Relaxed);
+        } else if size < self.low_watermark_bytes {
+            self.triggered.store(false, Ordering::Relaxed);
+        }
+        if !self.triggered.load(Ordering::Relaxed) {
+            return least_readable;
+        }
+        let next_target = min(
+            least_readable.saturating_add(self.versions_per_round),
+            min(self.retained_version_floor, target),
+        );
+        max(next_target, least_readable)
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+/// The kinds of pruners, each telling both its name and the label of the metrics about it, so
+/// that the two can't go out of sync.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum PrunerKind {
+    Ledger,
+    LedgerInfo,
+    StateStore,
+    #[cfg(any(test, feature = "testing"))]
+    Mock,
+}
+
+impl PrunerKind {
+    /// Every kind of pruner, for tests to check the names and labels of.
+    #[cfg(test)]
+    pub const ALL: [PrunerKind; 3] = [
+        PrunerKind::Ledger,
+        PrunerKind::LedgerInfo,
+        PrunerKind::StateStore,
+    ];
+
+    /// Returns the human readable name, e.g. for logs, as returned by `DBPruner::name()`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            PrunerKind::Ledger => "ledger pruner",
+            PrunerKind::LedgerInfo => "ledger info pruner",
+            PrunerKind::StateStore => "state store pruner",
+            #[cfg(any(test, feature = "testing"))]
+            PrunerKind::Mock => "mock pruner",
+        }
+    }
+
+    /// Returns the `pruner_name` label of the metrics about the pruner.
+    pub const fn metric_label(self) -> &'static str {
+        match self {
+            PrunerKind::Ledger => "ledger_pruner",
+            PrunerKind::LedgerInfo => "ledger_info",
+            PrunerKind::StateStore => "state_store",
+            #[cfg(any(test, feature = "testing"))]
+            PrunerKind::Mock => "mock",
+        }
+    }
+}
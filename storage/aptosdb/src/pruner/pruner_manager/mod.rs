@@ -0,0 +1,257 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::pruner::{
+    checkpoint_provider::CheckpointProvider,
+    db_pruner::{DBPruner, ProgressSnapshot, PrunerConfig, PrunerError, PrunerHealth},
+    utils,
+};
+use aptos_logger::warn;
+use aptos_types::transaction::{AtomicVersion, Version};
+use schemadb::SchemaBatch;
+use std::sync::{atomic::Ordering, Arc};
+
+/// Invoked with the new target version by `set_target_version_coalesced()` when the target
+/// advances by a whole step, e.g. to wake the pruning loop up. It runs on the thread setting the
+/// target, so it must be quick.
+pub type TargetSignal = Box<dyn Fn(Version) + Send + Sync>;
+
+/// Drives a set of DB pruners together, sharing a budget of versions to prune per round among
+/// them so that no pruner starves the others.
+pub struct PrunerManager {
+    db_pruners: Vec<Arc<dyn DBPruner + Send + Sync>>,
+    /// Number of versions all the pruners together prune at most in one round.
+    max_versions_per_round: Version,
+    /// Index of the pruner that is first in line in the next round, rotated every round.
+    next_pruner_index: usize,
+    /// If set, the step the target has to advance by for the signal to be invoked, see
+    /// `with_target_signal()`.
+    target_signal: Option<(Version, TargetSignal)>,
+    /// The target version the signal was last invoked with.
+    last_signaled_target: AtomicVersion,
+    /// If set, the target is derived from its checkpoints, keeping the given number of them, see
+    /// `with_checkpoint_target()`.
+    checkpoint_target: Option<(Arc<dyn CheckpointProvider + Send + Sync>, usize)>,
+}
+
+impl PrunerManager {
+    pub fn new(
+        db_pruners: Vec<Arc<dyn DBPruner + Send + Sync>>,
+        max_versions_per_round: Version,
+    ) -> Self {
+        Self {
+            db_pruners,
+            max_versions_per_round,
+            next_pruner_index: 0,
+            target_signal: None,
+            last_signaled_target: AtomicVersion::new(0),
+            checkpoint_target: None,
+        }
+    }
+
+    /// Has `set_target_version_coalesced()` invoke `signal` only once the target advanced by at
+    /// least `step` versions since it was last invoked, rather than on every call, so that a
+    /// target set on every committed version doesn't wake the pruning loop up as often.
+    #[allow(dead_code)]
+    pub fn with_target_signal(mut self, step: Version, signal: TargetSignal) -> Self {
+        self.target_signal = Some((step, signal));
+        self
+    }
+
+    /// Has each round first set the target version to that of the checkpoint
+    /// `checkpoints_retained` checkpoints behind the latest one in `checkpoint_provider`, so that
+    /// the state of that many checkpoints before the latest one stays readable, e.g. one for
+    /// restores to fall back on. Until there are that many, the target is left as it was, i.e.
+    /// nothing is pruned on a fresh node. The target follows the checkpoints, so it's meant to
+    /// be the only way the target is set.
+    #[allow(dead_code)]
+    pub fn with_checkpoint_target(
+        mut self,
+        checkpoint_provider: Arc<dyn CheckpointProvider + Send + Sync>,
+        checkpoints_retained: usize,
+    ) -> Self {
+        self.checkpoint_target = Some((checkpoint_provider, checkpoints_retained));
+        self
+    }
+
+    /// Runs one pruning round. The budget is split evenly among the pruners with pending work,
+    /// the remainder going to the ones first in line. Since the line is rotated every round, each
+    /// pruner gets its turn even if the budget is smaller than the number of pruners. Disabled
+    /// pruners have no pending work and are skipped.
+    ///
+    /// A pruner failing on IO is left to be retried in the next round, while any other error
+    /// aborts the round. An interrupted pruner ends the round early.
+    ///
+    /// With a checkpoint provider, the target is first derived from the latest checkpoints.
+    pub fn prune(&mut self, db_batch: &mut SchemaBatch) -> anyhow::Result<()> {
+        self.prune_round(db_batch, true /* retry_on_io_error */)
+    }
+
+    /// Same as `prune()`, except that a pruner failing on IO aborts the round too, for one-off
+    /// rounds with no next round to retry in.
+    pub fn prune_without_retries(&mut self, db_batch: &mut SchemaBatch) -> anyhow::Result<()> {
+        self.prune_round(db_batch, false /* retry_on_io_error */)
+    }
+
+    /// Tells all the pruners the deletions of the rounds so far are committed, see
+    /// `DBPruner::on_committed()`.
+    pub fn on_committed(&self) {
+        for db_pruner in &self.db_pruners {
+            db_pruner.on_committed();
+        }
+    }
+
+    /// Tells all the pruners the deletions of the rounds since the last commit are dropped, see
+    /// `DBPruner::on_discarded()`.
+    pub fn on_discarded(&self) {
+        for db_pruner in &self.db_pruners {
+            db_pruner.on_discarded();
+        }
+    }
+
+    fn prune_round(
+        &mut self,
+        db_batch: &mut SchemaBatch,
+        retry_on_io_error: bool,
+    ) -> anyhow::Result<()> {
+        self.set_target_from_checkpoints()?;
+        let num_pruners = self.db_pruners.len();
+        if num_pruners == 0 {
+            return Ok(());
+        }
+        let first_pruner_index = self.next_pruner_index;
+        self.next_pruner_index = (first_pruner_index + 1) % num_pruners;
+
+        let pending_pruners: Vec<_> = (0..num_pruners)
+            .map(|i| &self.db_pruners[(first_pruner_index + i) % num_pruners])
+            .filter(|db_pruner| db_pruner.is_pruning_pending())
+            .collect();
+        if pending_pruners.is_empty() {
+            return Ok(());
+        }
+        let num_pending_pruners = pending_pruners.len() as Version;
+        let share = self.max_versions_per_round / num_pending_pruners;
+        let remainder = self.max_versions_per_round % num_pending_pruners;
+        for (i, db_pruner) in pending_pruners.into_iter().enumerate() {
+            let max_versions = share + if (i as Version) < remainder { 1 } else { 0 };
+            if max_versions > 0 {
+                match db_pruner.prune(db_batch, max_versions) {
+                    Ok(_) => (),
+                    Err(PrunerError::Interrupted(_)) => break,
+                    Err(PrunerError::Io(e)) if retry_on_io_error => {
+                        warn!(
+                            error = ?e,
+                            "{} failed on IO, to be retried next round.",
+                            db_pruner.name(),
+                        );
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the same target version for all the pruners.
+    pub fn set_target_version(&self, target_version: Version) {
+        for db_pruner in &self.db_pruners {
+            db_pruner.set_target_version(target_version);
+        }
+    }
+
+    /// Sets the target version of all the pruners to that of the checkpoint the checkpoint
+    /// provider, if any, has `checkpoints_retained` checkpoints behind the latest one, and
+    /// returns it, `None` if there is no provider or not that many checkpoints yet.
+    pub fn set_target_from_checkpoints(&self) -> anyhow::Result<Option<Version>> {
+        let (checkpoint_provider, checkpoints_retained) = match &self.checkpoint_target {
+            Some(checkpoint_target) => checkpoint_target,
+            None => return Ok(None),
+        };
+        let target_version = checkpoint_provider
+            .latest_checkpoints(checkpoints_retained + 1)?
+            .get(*checkpoints_retained)
+            .copied();
+        if let Some(target_version) = target_version {
+            self.set_target_version(target_version);
+        }
+        Ok(target_version)
+    }
+
+    /// Same as `set_target_version()`, plus invoking the target signal if the target advanced by
+    /// a whole step since it was last invoked. The target of the pruners is always updated, so a
+    /// round run for any other reason prunes up to the latest one.
+    #[allow(dead_code)]
+    pub fn set_target_version_coalesced(&self, target_version: Version) {
+        self.set_target_version(target_version);
+        if let Some((step, signal)) = &self.target_signal {
+            let last_signaled_target = self.last_signaled_target.load(Ordering::Relaxed);
+            // Of concurrent callers advancing the target past the step, only one signals.
+            if target_version >= last_signaled_target.saturating_add(*step)
+                && self
+                    .last_signaled_target
+                    .compare_exchange(
+                        last_signaled_target,
+                        target_version,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                signal(target_version);
+            }
+        }
+    }
+
+    /// Returns the least readable version across all the pruners, below which some of the data
+    /// might have been pruned.
+    #[allow(dead_code)]
+    pub fn least_readable_version(&self) -> Version {
+        let least_readable_versions: Vec<_> = self
+            .db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.least_readable_version())
+            .collect();
+        utils::combined_least_readable_version(&least_readable_versions)
+    }
+
+    /// True if every one of the pruners left `version` readable, so the data of all the stores
+    /// is still there for it.
+    #[allow(dead_code)]
+    pub fn is_version_readable_everywhere(&self, version: Version) -> bool {
+        self.db_pruners
+            .iter()
+            .all(|db_pruner| db_pruner.is_version_readable(version))
+    }
+
+    /// Returns the live configuration of each of the pruners, in the order they were given.
+    #[allow(dead_code)]
+    pub fn configs(&self) -> Vec<PrunerConfig> {
+        self.db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.config())
+            .collect()
+    }
+
+    /// Returns how each of the pruners is doing, in the order they were given, e.g. for a status
+    /// endpoint to report.
+    #[allow(dead_code)]
+    pub fn health(&self) -> Vec<PrunerHealth> {
+        self.db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.health())
+            .collect()
+    }
+
+    /// Returns a snapshot of the progress of each of the pruners, in the order they were given,
+    /// e.g. for a controller to collect across nodes.
+    #[allow(dead_code)]
+    pub fn snapshot_all(&self) -> Vec<ProgressSnapshot> {
+        self.db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.snapshot())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test;
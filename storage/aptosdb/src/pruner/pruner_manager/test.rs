@@ -0,0 +1,493 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::pruner::{
+    checkpoint_provider::CheckpointProvider,
+    clock::{Clock, FrozenClock},
+    db_pruner::{DBPruner, ProgressSnapshot, PrunerConfig, PrunerHealth},
+    mock_pruner::{MockPruner, MOCK_PRUNER_NAME},
+    prune_schedule::{PruneSchedule, PruneWindow},
+    pruner_kind::PrunerKind,
+    pruner_manager::PrunerManager,
+    utils::{combined_least_readable_version, StarvationTracker},
+};
+use aptos_infallible::{duration_since_epoch, Mutex};
+use aptos_types::transaction::Version;
+use schemadb::SchemaBatch;
+use std::{cmp::min, collections::HashSet, sync::Arc, time::Duration};
+
+fn create_pruner_manager(
+    max_versions_per_round: Version,
+) -> (PrunerManager, Arc<MockPruner>, Arc<MockPruner>) {
+    let first = Arc::new(MockPruner::default());
+    let second = Arc::new(MockPruner::default());
+    let pruner_manager = PrunerManager::new(
+        vec![
+            Arc::clone(&first) as Arc<dyn DBPruner + Send + Sync>,
+            Arc::clone(&second) as Arc<dyn DBPruner + Send + Sync>,
+        ],
+        max_versions_per_round,
+    );
+    (pruner_manager, first, second)
+}
+
+#[test]
+fn test_pruner_manager_round_robin() {
+    // With a budget smaller than the number of pruners, they take turns.
+    let (mut pruner_manager, first, second) = create_pruner_manager(1);
+    pruner_manager.set_target_version(10);
+    for round in 1..=10 {
+        pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+        assert_eq!(first.least_readable_version(), (round + 1) / 2);
+        assert_eq!(second.least_readable_version(), round / 2);
+    }
+
+    // The remainder of the budget goes to each pruner in turn.
+    let (mut pruner_manager, first, second) = create_pruner_manager(3);
+    pruner_manager.set_target_version(100);
+    for round in 1..=10 {
+        pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+        assert_eq!(first.least_readable_version(), round * 3 / 2 + round % 2);
+        assert_eq!(second.least_readable_version(), round * 3 / 2);
+    }
+
+    // A pruner done with its work leaves the whole budget to the others.
+    let (mut pruner_manager, first, second) = create_pruner_manager(4);
+    pruner_manager.set_target_version(100);
+    first.set_target_version(2);
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    assert_eq!(first.least_readable_version(), 2);
+    assert_eq!(second.least_readable_version(), 6);
+}
+
+#[test]
+fn test_pruner_manager_skips_disabled_pruners() {
+    let (mut pruner_manager, first, second) = create_pruner_manager(4);
+    pruner_manager.set_target_version(100);
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    assert_eq!(first.least_readable_version(), 2);
+    assert_eq!(second.least_readable_version(), 2);
+
+    // A disabled pruner stays where it is, leaving the whole budget to the others.
+    first.set_enabled(false);
+    assert!(!first.is_pruning_pending());
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    assert_eq!(first.least_readable_version(), 2);
+    assert_eq!(second.least_readable_version(), 10);
+
+    // Once enabled again, it resumes from there.
+    first.set_enabled(true);
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    assert_eq!(first.least_readable_version(), 4);
+    assert_eq!(second.least_readable_version(), 12);
+}
+
+#[test]
+fn test_pruner_manager_io_failures() {
+    let (mut pruner_manager, first, second) = create_pruner_manager(4);
+    pruner_manager.set_target_version(100);
+    first.set_failing_on_io(true);
+
+    // A pruner failing on IO is left to the next round, not holding the others back.
+    pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    assert_eq!(first.least_readable_version(), 0);
+    assert_eq!(second.least_readable_version(), 2);
+
+    // Unless there is no next round to retry in.
+    assert!(pruner_manager
+        .prune_without_retries(&mut SchemaBatch::new())
+        .is_err());
+    assert_eq!(first.least_readable_version(), 0);
+
+    first.set_failing_on_io(false);
+    pruner_manager
+        .prune_without_retries(&mut SchemaBatch::new())
+        .unwrap();
+    assert_eq!(first.least_readable_version(), 2);
+}
+
+#[test]
+fn test_pruner_manager_least_readable_version() {
+    let (pruner_manager, first, second) = create_pruner_manager(1);
+    assert_eq!(pruner_manager.least_readable_version(), 0);
+
+    first.record_progress(10);
+    second.record_progress(5);
+    assert_eq!(pruner_manager.least_readable_version(), 5);
+
+    second.record_progress(20);
+    assert_eq!(
+        pruner_manager.least_readable_version(),
+        min(
+            first.least_readable_version(),
+            second.least_readable_version()
+        )
+    );
+    assert_eq!(pruner_manager.least_readable_version(), 10);
+
+    assert_eq!(PrunerManager::new(vec![], 1).least_readable_version(), 0);
+}
+
+#[test]
+fn test_combined_least_readable_version() {
+    let db_pruners: Vec<_> = (0..3).map(|_| MockPruner::default()).collect();
+    let least_readable_versions = || {
+        db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.least_readable_version())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        combined_least_readable_version(&least_readable_versions()),
+        0
+    );
+
+    // The pruners advancing at different rates, the slowest one sets the floor.
+    for (db_pruner, least_readable_version) in db_pruners.iter().zip([30, 10, 20]) {
+        db_pruner.record_progress(least_readable_version);
+    }
+    assert_eq!(
+        combined_least_readable_version(&least_readable_versions()),
+        10
+    );
+    db_pruners[1].record_progress(40);
+    assert_eq!(
+        combined_least_readable_version(&least_readable_versions()),
+        20
+    );
+    assert_eq!(
+        combined_least_readable_version(&least_readable_versions()[..1]),
+        30
+    );
+
+    assert_eq!(combined_least_readable_version(&[]), 0);
+}
+
+#[test]
+fn test_pruner_manager_is_version_readable_everywhere() {
+    let (pruner_manager, first, second) = create_pruner_manager(1);
+    first.record_progress(10);
+    second.record_progress(5);
+
+    // Above, at and below the floor of each pruner.
+    assert!(first.is_version_readable(11));
+    assert!(first.is_version_readable(10));
+    assert!(!first.is_version_readable(9));
+    assert!(second.is_version_readable(5));
+    assert!(!second.is_version_readable(4));
+
+    // Only versions the furthest pruner left are readable everywhere.
+    assert!(pruner_manager.is_version_readable_everywhere(11));
+    assert!(pruner_manager.is_version_readable_everywhere(10));
+    assert!(!pruner_manager.is_version_readable_everywhere(9));
+    assert!(!pruner_manager.is_version_readable_everywhere(4));
+
+    assert!(PrunerManager::new(vec![], 1).is_version_readable_everywhere(0));
+}
+
+#[test]
+fn test_pruner_manager_configs() {
+    let (pruner_manager, first, _second) = create_pruner_manager(1);
+    pruner_manager.set_target_version(10);
+    first.set_target_version(20);
+    let config = |target_version| PrunerConfig {
+        name: MOCK_PRUNER_NAME,
+        prune_window: 0,
+        target_version,
+        max_versions: None,
+        batch_target: None,
+    };
+    assert_eq!(pruner_manager.configs(), vec![config(20), config(10)]);
+}
+
+#[test]
+fn test_pruner_manager_snapshot_all() {
+    let (pruner_manager, first, _second) = create_pruner_manager(1);
+    pruner_manager.set_target_version(10);
+    first.set_target_version(20);
+    let progress = || -> Vec<_> {
+        pruner_manager
+            .snapshot_all()
+            .iter()
+            .map(|snapshot| {
+                (
+                    snapshot.kind,
+                    snapshot.least_readable,
+                    snapshot.target,
+                    snapshot.updated_at.is_some(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        progress(),
+        vec![
+            (PrunerKind::Mock, 0, 20, false),
+            (PrunerKind::Mock, 0, 10, false)
+        ]
+    );
+
+    // The snapshots follow the pruners as they go.
+    first.record_progress(4);
+    assert_eq!(
+        progress(),
+        vec![
+            (PrunerKind::Mock, 4, 20, true),
+            (PrunerKind::Mock, 0, 10, false)
+        ]
+    );
+}
+
+#[test]
+fn test_pruner_manager_health() {
+    let (pruner_manager, first, second) = create_pruner_manager(1);
+    pruner_manager.set_target_version(10);
+    first.record_progress(4);
+    second.set_enabled(false);
+    let health = |least_readable_version, enabled| PrunerHealth {
+        name: MOCK_PRUNER_NAME,
+        least_readable_version,
+        target_version: 10,
+        remaining_versions: 10 - least_readable_version,
+        enabled,
+        last_batch_duration: None,
+        last_error: None,
+    };
+    assert_eq!(
+        pruner_manager.health(),
+        vec![health(4, true), health(0, false)]
+    );
+}
+
+#[test]
+fn test_progress_snapshot() {
+    let pruner = MockPruner::default();
+    pruner.set_target_version(10);
+    let snapshot = pruner.snapshot();
+    assert_eq!(snapshot.kind, PrunerKind::Mock);
+    assert_eq!((snapshot.least_readable, snapshot.target), (0, 10));
+    // No progress yet.
+    assert_eq!(snapshot.updated_at, None);
+
+    let recorded_after = duration_since_epoch();
+    pruner.record_progress(4);
+    let snapshot = pruner.snapshot();
+    assert_eq!((snapshot.least_readable, snapshot.target), (4, 10));
+    let updated_at = snapshot.updated_at.unwrap();
+    assert!(updated_at >= recorded_after);
+
+    // Recording the same version again isn't progress.
+    pruner.record_progress(4);
+    assert_eq!(pruner.snapshot().updated_at, Some(updated_at));
+}
+
+#[test]
+fn test_progress_snapshot_serde_round_trip() {
+    let snapshot = ProgressSnapshot {
+        kind: PrunerKind::Mock,
+        least_readable: 5,
+        target: 10,
+        updated_at: Some(Duration::from_millis(1_234_567)),
+    };
+    let bytes = bcs::to_bytes(&snapshot).unwrap();
+    assert_eq!(
+        bcs::from_bytes::<ProgressSnapshot>(&bytes).unwrap(),
+        snapshot
+    );
+}
+
+#[test]
+fn test_starvation_tracker() {
+    let mut starvation_tracker = StarvationTracker::new(vec![0, 0], 3);
+
+    // The versions left grow every round.
+    for round in 1..=5 {
+        assert_eq!(
+            starvation_tracker.track(&[round * 3, round * 3]),
+            vec![round >= 3; 2]
+        );
+    }
+
+    // One pruner catching up is no longer starving, while the other falls behind further.
+    assert_eq!(starvation_tracker.track(&[10, 15]), vec![false, false]);
+    for (remaining_versions, starving) in [(20, false), (25, false), (30, true)] {
+        assert_eq!(
+            starvation_tracker.track(&[10, remaining_versions]),
+            vec![false, starving]
+        );
+    }
+}
+
+#[test]
+fn test_pruner_manager_with_stepping_pruners() {
+    // A pruner slower than its share of the budget leaves the rest of it unused.
+    let slow = Arc::new(MockPruner::new(1));
+    let fast = Arc::new(MockPruner::default());
+    let mut pruner_manager = PrunerManager::new(
+        vec![
+            Arc::clone(&slow) as Arc<dyn DBPruner + Send + Sync>,
+            Arc::clone(&fast) as Arc<dyn DBPruner + Send + Sync>,
+        ],
+        4,
+    );
+    pruner_manager.set_target_version(10);
+    for _ in 0..3 {
+        pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+    }
+    assert_eq!(slow.least_readable_version(), 3);
+    assert_eq!(fast.least_readable_version(), 6);
+    assert_eq!(slow.progress_recorded(), vec![1, 2, 3]);
+    assert_eq!(slow.target_versions_set(), vec![10]);
+    assert_eq!(slow.num_prune_calls(), 3);
+}
+
+#[test]
+fn test_pruner_kinds_have_unique_labels() {
+    // Fails to compile once a kind is added, until it's added to `PrunerKind::ALL` as well.
+    let position = |kind: PrunerKind| match kind {
+        PrunerKind::Ledger => 0,
+        PrunerKind::LedgerInfo => 1,
+        PrunerKind::StateStore => 2,
+        // Only there for tests, so left out of `PrunerKind::ALL`.
+        PrunerKind::Mock => usize::MAX,
+    };
+    for (i, kind) in PrunerKind::ALL.iter().enumerate() {
+        assert_eq!(position(*kind), i);
+    }
+
+    let names: HashSet<_> = PrunerKind::ALL.iter().map(|kind| kind.name()).collect();
+    let labels: HashSet<_> = PrunerKind::ALL
+        .iter()
+        .map(|kind| kind.metric_label())
+        .collect();
+    assert_eq!(names.len(), PrunerKind::ALL.len());
+    assert_eq!(labels.len(), PrunerKind::ALL.len());
+}
+
+#[test]
+fn test_pruner_manager_coalesces_target_signals() {
+    let first = Arc::new(MockPruner::default());
+    let signaled = Arc::new(Mutex::new(vec![]));
+    let signaled_clone = Arc::clone(&signaled);
+    let pruner_manager = PrunerManager::new(
+        vec![Arc::clone(&first) as Arc<dyn DBPruner + Send + Sync>],
+        100, /* max_versions_per_round */
+    )
+    .with_target_signal(
+        10,
+        Box::new(move |target_version| signaled_clone.lock().push(target_version)),
+    );
+
+    for target_version in 1..=35 {
+        pruner_manager.set_target_version_coalesced(target_version);
+        // The target is set regardless of the signal.
+        assert_eq!(first.target_version(), target_version);
+    }
+    assert_eq!(*signaled.lock(), vec![10, 20, 30]);
+
+    // A jump past several steps signals once, the next step counting from there.
+    pruner_manager.set_target_version_coalesced(57);
+    pruner_manager.set_target_version_coalesced(66);
+    pruner_manager.set_target_version_coalesced(67);
+    assert_eq!(*signaled.lock(), vec![10, 20, 30, 57, 67]);
+}
+
+fn hours(hours: u64) -> Duration {
+    Duration::from_secs(hours * 60 * 60)
+}
+
+#[test]
+fn test_prune_schedule_windows() {
+    let clock = Arc::new(FrozenClock::default());
+    // From 10pm to 2am, and from 1pm to 2pm.
+    let schedule = PruneSchedule::new(vec![
+        PruneWindow::new(hours(22), hours(2)).unwrap(),
+        PruneWindow::new(hours(13), hours(14)).unwrap(),
+    ])
+    .unwrap()
+    .with_clock(Arc::clone(&clock) as Arc<dyn Clock + Send + Sync>);
+    let day = hours(24);
+    for (now, time_until_open) in [
+        (hours(0), Duration::ZERO),
+        (hours(1), Duration::ZERO),
+        (hours(2), hours(11)),
+        (hours(12), hours(1)),
+        (hours(13), Duration::ZERO),
+        (hours(14), hours(8)),
+        (hours(23), Duration::ZERO),
+        // Only the time of the day matters.
+        (day * 3 + hours(12), hours(1)),
+    ] {
+        *clock.now.lock() = now;
+        assert_eq!(schedule.time_until_open(), time_until_open);
+    }
+
+    assert!(PruneSchedule::new(vec![]).is_err());
+    assert!(PruneWindow::new(hours(1), hours(1)).is_err());
+    assert!(PruneWindow::new(hours(1), day).is_err());
+    assert!(PruneSchedule::from_utc_hours(22, 2).is_ok());
+    assert!(PruneSchedule::from_utc_hours(2, 24).is_err());
+}
+
+/// Holds the checkpoints committed so far, in version order.
+#[derive(Default)]
+struct MockCheckpointProvider {
+    checkpoints: Mutex<Vec<Version>>,
+}
+
+impl CheckpointProvider for MockCheckpointProvider {
+    fn latest_checkpoints(&self, count: usize) -> anyhow::Result<Vec<Version>> {
+        Ok(self
+            .checkpoints
+            .lock()
+            .iter()
+            .rev()
+            .take(count)
+            .copied()
+            .collect())
+    }
+}
+
+#[test]
+fn test_pruner_manager_with_checkpoint_target() {
+    let checkpoint_provider = Arc::new(MockCheckpointProvider::default());
+    let pruner = Arc::new(MockPruner::default());
+    let mut pruner_manager = PrunerManager::new(
+        vec![Arc::clone(&pruner) as Arc<dyn DBPruner + Send + Sync>],
+        100,
+    )
+    .with_checkpoint_target(
+        Arc::clone(&checkpoint_provider) as Arc<dyn CheckpointProvider + Send + Sync>,
+        2, /* checkpoints_retained */
+    );
+
+    // Until there are more checkpoints than retained, nothing is pruned.
+    for checkpoint in [10, 20] {
+        checkpoint_provider.checkpoints.lock().push(checkpoint);
+        pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+        assert_eq!(pruner.target_version(), 0);
+        assert_eq!(pruner.least_readable_version(), 0);
+    }
+
+    // Then the target follows the checkpoints, two behind the latest.
+    for (checkpoint, target_version) in [(30, 10), (40, 20), (55, 30)] {
+        checkpoint_provider.checkpoints.lock().push(checkpoint);
+        pruner_manager.prune(&mut SchemaBatch::new()).unwrap();
+        assert_eq!(pruner.target_version(), target_version);
+        assert_eq!(pruner.least_readable_version(), target_version);
+    }
+    assert_eq!(
+        pruner_manager.set_target_from_checkpoints().unwrap(),
+        Some(30)
+    );
+
+    // Without a provider, the target is left alone.
+    let pruner_manager = PrunerManager::new(
+        vec![Arc::clone(&pruner) as Arc<dyn DBPruner + Send + Sync>],
+        100,
+    );
+    assert_eq!(pruner_manager.set_target_from_checkpoints().unwrap(), None);
+    assert_eq!(pruner.target_version(), 30);
+}
@@ -0,0 +1,58 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::pruner::clock::Clock;
+use aptos_infallible::Mutex;
+use std::{sync::Arc, time::Duration};
+
+/// Caps the number of bytes the pruner writes per second, for it not to starve the main write path
+/// of disk IO. It is a token bucket holding up to a second's worth of bytes, which starts full.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    bucket: Mutex<TokenBucket>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+struct TokenBucket {
+    /// Bytes that can be written right away, negative while paying off a write larger than what
+    /// the bucket held.
+    tokens: f64,
+    /// When the tokens were last refilled, as told by the clock.
+    refilled_at: Duration,
+}
+
+impl RateLimiter {
+    /// Lets through `bytes_per_sec` bytes per second, as told by `clock`.
+    pub fn new(bytes_per_sec: u64, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        assert!(bytes_per_sec > 0, "Rate limit must be positive.");
+        Self {
+            bytes_per_sec,
+            bucket: Mutex::new(TokenBucket {
+                tokens: bytes_per_sec as f64,
+                refilled_at: clock.now(),
+            }),
+            clock,
+        }
+    }
+
+    /// Takes `bytes` out of the bucket, sleeping until as many have accumulated if the bucket
+    /// doesn't hold enough.
+    pub fn consume(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock();
+            let now = self.clock.now();
+            let refilled = now.saturating_sub(bucket.refilled_at).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refilled).min(rate) - bytes as f64;
+            bucket.refilled_at = now;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if wait > Duration::ZERO {
+            self.clock.sleep(wait);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_infallible::Mutex;
+use aptos_types::transaction::Version;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Keeps track of the versions in-flight reads need, e.g. a long running state proof export, for
+/// the pruners given the registry not to prune past the lowest of them while they run.
+#[derive(Default)]
+pub struct ReadFloorRegistry {
+    /// Number of guards registered at each version.
+    floors: Mutex<BTreeMap<Version, usize>>,
+}
+
+impl ReadFloorRegistry {
+    /// Keeps `version` from being pruned until the returned guard is dropped. Data already pruned
+    /// by then isn't brought back, so readers are to register before checking what's readable.
+    pub fn register(self: &Arc<Self>, version: Version) -> ReadFloorGuard {
+        *self.floors.lock().entry(version).or_insert(0) += 1;
+        ReadFloorGuard {
+            registry: Arc::clone(self),
+            version,
+        }
+    }
+
+    /// Returns the lowest version a guard is held at, `None` if none is.
+    pub fn min_floor(&self) -> Option<Version> {
+        self.floors.lock().keys().next().copied()
+    }
+
+    fn release(&self, version: Version) {
+        let mut floors = self.floors.lock();
+        if let Some(count) = floors.get_mut(&version) {
+            *count -= 1;
+            if *count == 0 {
+                floors.remove(&version);
+            }
+        }
+    }
+}
+
+/// Holds a read floor registered in a `ReadFloorRegistry`, releasing it when dropped.
+pub struct ReadFloorGuard {
+    registry: Arc<ReadFloorRegistry>,
+    version: Version,
+}
+
+impl ReadFloorGuard {
+    pub fn version(&self) -> Version {
+        self.version
+    }
+}
+
+impl Drop for ReadFloorGuard {
+    fn drop(&mut self) {
+        self.registry.release(self.version);
+    }
+}
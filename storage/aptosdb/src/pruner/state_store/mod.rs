@@ -2,24 +2,49 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    jellyfish_merkle_node::JellyfishMerkleNodeSchema, metrics::PRUNER_LEAST_READABLE_VERSION,
-    pruner::db_pruner::DBPruner, stale_node_index::StaleNodeIndexSchema, OTHER_TIMERS_SECONDS,
+    jellyfish_merkle_node::JellyfishMerkleNodeSchema,
+    metrics::{
+        PRUNER_BATCH_DURATION_SECONDS, PRUNER_KEYS_DELETED_TOTAL, PRUNER_LEAST_READABLE_VERSION,
+    },
+    pruner::{
+        db_pruner::{
+            warn_if_slow_batch, DBPruner, PruneProgress, PruneProgressCallback, PruneStatus,
+            PrunerError,
+        },
+        prune_read_options::PruneReadOptions,
+        prune_strategy::PruneStrategy,
+        pruner_kind::PrunerKind,
+        rate_limiter::RateLimiter,
+        read_floor::ReadFloorRegistry,
+        write_retries::WriteRetries,
+    },
+    schema::{
+        db_metadata::{DbMetadataKey, DbMetadataSchema, DbMetadataValue},
+        JELLYFISH_MERKLE_NODE_CF_NAME,
+    },
+    stale_node_index::StaleNodeIndexSchema,
+    OTHER_TIMERS_SECONDS,
 };
-use aptos_infallible::Mutex;
+use aptos_infallible::{duration_since_epoch, Mutex};
 use aptos_jellyfish_merkle::StaleNodeIndex;
-use aptos_logger::{error, warn};
+use aptos_logger::{debug, error, warn};
 use aptos_types::transaction::{AtomicVersion, Version};
-use schemadb::{ReadOptions, SchemaBatch, SchemaIterator, DB};
+use schemadb::{SchemaBatch, SchemaIterator, DB};
 use std::{
+    cmp::min,
     iter::Peekable,
-    sync::{atomic::Ordering, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
+use tracing::{field, info_span};
 
 #[cfg(test)]
 mod test;
 
-pub const STATE_STORE_PRUNER_NAME: &str = "state store pruner";
+pub const STATE_STORE_PRUNER_NAME: &str = PrunerKind::StateStore.name();
 
 pub struct StateStorePruner {
     db: Arc<DB>,
@@ -28,27 +53,123 @@ pub struct StateStorePruner {
     /// Keeps track of the target version that the pruner needs to achieve.
     target_version: AtomicVersion,
     least_readable_version: AtomicVersion,
+    /// Rounds do nothing while unset, see `set_enabled()`.
+    enabled: AtomicBool,
+    /// The `max_versions` and the target of the last round which did work.
+    last_batch: Mutex<Option<(u64, Version)>>,
+    /// How the last round which did work went.
+    last_status: Mutex<Option<PruneStatus>>,
+    /// When the least readable version last changed, since the UNIX epoch.
+    progress_updated_at: Mutex<Option<Duration>>,
+    /// Held throughout `prune()`, so overlapping calls run one after the other.
+    prune_lock: Mutex<()>,
+    /// Number of versions right before the target version that are kept from pruning.
+    prune_window: Version,
+    /// Decides how far each round goes.
+    prune_strategy: Arc<dyn PruneStrategy + Send + Sync>,
+    /// How long the last round took, for the prune strategy to adapt to.
+    last_batch_duration: Mutex<Duration>,
+    /// Told about each successful round.
+    progress_callback: PruneProgressCallback,
+    /// If set, caps the bytes written per second.
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// If set, retries the writes on transient failures.
+    write_retries: Option<WriteRetries>,
+    /// If set, rounds taking longer are warned about.
+    slow_batch_threshold: Option<Duration>,
+    /// If set, holds the versions in-flight reads need back from pruning.
+    read_floors: Option<Arc<ReadFloorRegistry>>,
+    /// If set, the nodes of the genesis state are never pruned, see `with_genesis_kept()`.
+    never_prune_genesis: bool,
 }
 
 impl DBPruner for StateStorePruner {
-    fn name(&self) -> &'static str {
-        STATE_STORE_PRUNER_NAME
+    fn kind(&self) -> PrunerKind {
+        PrunerKind::StateStore
     }
 
-    fn prune(&self, _db_batch: &mut SchemaBatch, max_versions: u64) -> anyhow::Result<Version> {
+    fn prune(
+        &self,
+        _db_batch: &mut SchemaBatch,
+        max_versions: u64,
+    ) -> Result<PruneProgress, PrunerError> {
+        let _prune_guard = self.prune_lock.lock();
+        // Nothing to do until the target advances, which is checked before anything else since
+        // the pruner is frequently polled while idle.
         if !self.is_pruning_pending() {
-            return Ok(self.least_readable_version());
+            return Ok(PruneProgress {
+                version: self.least_readable_version(),
+                keys_pruned: 0,
+            });
         }
-        let least_readable_version = self.least_readable_version.load(Ordering::Relaxed);
-        let target_version = self.target_version();
-        return match prune_state_store(
+        let _timer = PRUNER_BATCH_DURATION_SECONDS
+            .with_label_values(&[self.kind().metric_label()])
+            .start_timer();
+        let least_readable_version = self.least_readable_version.load(Ordering::Acquire);
+        // Any version past genesis leaves nodes of genesis stale. They are skipped rather than
+        // deleted, the least readable version staying at genesis until the round is written.
+        let skips_genesis = self.never_prune_genesis
+            && least_readable_version == 0
+            && self.oldest_live_version()?.is_some();
+        // Covers the whole round, the write included. The fields are recorded as they become
+        // known.
+        let span = info_span!(
+            STATE_STORE_PRUNER_NAME,
+            start_version = least_readable_version,
+            target_version = field::Empty,
+            keys_deleted = field::Empty,
+        );
+        let _entered = span.enter();
+        // Rounds are bounded by the number of versions with stale nodes rather than by the range
+        // of versions, see `prune_state_store()`.
+        let batch_target_version = if skips_genesis {
+            self.batch_target_from(1, Version::MAX)
+        } else {
+            self.current_batch_target(Version::MAX)
+        };
+        let target_version = min(
+            self.prune_strategy.next_target(
+                least_readable_version,
+                batch_target_version,
+                *self.last_batch_duration.lock(),
+            ),
+            batch_target_version,
+        );
+        span.record("target_version", &target_version);
+        *self.last_batch.lock() = Some((max_versions, target_version));
+        let started_at = Instant::now();
+        let result = prune_state_store(
             self.db.clone(),
             least_readable_version,
             target_version,
             max_versions as usize,
-        ) {
-            Ok(new_least_readable_version) => {
-                self.record_progress(new_least_readable_version);
+            self.rate_limiter.as_deref(),
+            self.write_retries.as_ref(),
+            self.never_prune_genesis(),
+        );
+        let elapsed = started_at.elapsed();
+        *self.last_batch_duration.lock() = elapsed;
+        let result = result.map_err(PrunerError::from);
+        *self.last_status.lock() = Some(PruneStatus::new(&result, elapsed));
+        return match result {
+            Ok(progress) => {
+                span.record("keys_deleted", &progress.keys_pruned);
+                warn_if_slow_batch(
+                    self.name(),
+                    self.slow_batch_threshold,
+                    elapsed,
+                    least_readable_version,
+                    progress.version,
+                    progress.keys_pruned,
+                );
+                self.record_progress(progress.version);
+                PRUNER_KEYS_DELETED_TOTAL
+                    .with_label_values(&[
+                        PrunerKind::StateStore.metric_label(),
+                        JELLYFISH_MERKLE_NODE_CF_NAME,
+                    ])
+                    .inc_by(progress.keys_pruned as u64);
+                (self.progress_callback)(progress.version, progress.keys_pruned);
                 // Try to purge the log.
                 if let Err(e) = self.maybe_purge_index() {
                     warn!(
@@ -56,7 +177,7 @@ impl DBPruner for StateStorePruner {
                         "Failed purging state node index, ignored.",
                     );
                 }
-                Ok(new_least_readable_version)
+                Ok(progress)
             }
             Err(e) => {
                 error!(
@@ -70,11 +191,28 @@ impl DBPruner for StateStorePruner {
     }
 
     fn initialize_least_readable_version(&self) -> anyhow::Result<Version> {
+        if let Some(value) = self
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::StateStorePrunerProgress)?
+        {
+            return Ok(value.expect_version());
+        }
+        // Not pruned since the progress started being persisted, so the first stale node index
+        // left tells the progress.
+        Ok(self.oldest_live_version()?.unwrap_or(0))
+    }
+
+    fn never_prune_genesis(&self) -> bool {
+        self.never_prune_genesis
+    }
+
+    fn oldest_live_version(&self) -> anyhow::Result<Option<Version>> {
+        // The nodes made stale by a version are last readable at the one before it.
         let mut iter = self
             .db
-            .iter::<StaleNodeIndexSchema>(ReadOptions::default())?;
+            .iter::<StaleNodeIndexSchema>(PruneReadOptions::default().read_options())?;
         iter.seek_to_first();
-        Ok(iter.next().transpose()?.map_or(0, |(index, _)| {
+        Ok(iter.next().transpose()?.map(|(index, _)| {
             index
                 .stale_since_version
                 .checked_sub(1)
@@ -83,7 +221,7 @@ impl DBPruner for StateStorePruner {
     }
 
     fn least_readable_version(&self) -> Version {
-        self.least_readable_version.load(Ordering::Relaxed)
+        self.least_readable_version.load(Ordering::Acquire)
     }
 
     fn set_target_version(&self, target_version: Version) {
@@ -94,12 +232,65 @@ impl DBPruner for StateStorePruner {
         self.target_version.load(Ordering::Relaxed)
     }
 
-    fn record_progress(&self, least_readable_version: Version) {
-        self.least_readable_version
-            .store(least_readable_version, Ordering::Relaxed);
+    fn last_batch(&self) -> Option<(u64, Version)> {
+        *self.last_batch.lock()
+    }
+
+    fn last_status(&self) -> Option<PruneStatus> {
+        self.last_status.lock().clone()
+    }
+
+    fn progress_updated_at(&self) -> Option<Duration> {
+        *self.progress_updated_at.lock()
+    }
+
+    fn flush_progress(&self) -> anyhow::Result<()> {
+        // Not in the middle of a round.
+        let _prune_guard = self.prune_lock.lock();
+        let mut batch = SchemaBatch::new();
+        batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::StateStorePrunerProgress,
+            &DbMetadataValue::Version(self.least_readable_version()),
+        )?;
+        match &self.write_retries {
+            Some(write_retries) => write_retries.run(|| self.db.write_schemas_ref(&batch)),
+            None => self.db.write_schemas(batch),
+        }
+    }
+
+    fn prune_window(&self) -> Version {
+        self.prune_window
+    }
+
+    fn read_floor(&self) -> Option<Version> {
+        self.read_floors
+            .as_ref()
+            .and_then(|read_floors| read_floors.min_floor())
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn commit(&self, db_batch: SchemaBatch) -> anyhow::Result<()> {
+        self.db.write_schemas(db_batch)
+    }
+
+    fn record_progress(&self, least_readable_version: Version) -> Version {
+        let previous_version = self
+            .least_readable_version
+            .swap(least_readable_version, Ordering::Release);
+        if least_readable_version != previous_version {
+            *self.progress_updated_at.lock() = Some(duration_since_epoch());
+        }
         PRUNER_LEAST_READABLE_VERSION
-            .with_label_values(&["state_store"])
+            .with_label_values(&[PrunerKind::StateStore.metric_label()])
             .set(least_readable_version as i64);
+        least_readable_version.saturating_sub(previous_version)
     }
 }
 
@@ -108,6 +299,8 @@ impl StateStorePruner {
         db: Arc<DB>,
         index_min_nonpurged_version: Version,
         index_purged_at: Instant,
+        prune_window: Version,
+        prune_strategy: Arc<dyn PruneStrategy + Send + Sync>,
     ) -> Self {
         let pruner = StateStorePruner {
             db,
@@ -115,11 +308,69 @@ impl StateStorePruner {
             index_purged_at: Mutex::new(index_purged_at),
             target_version: AtomicVersion::new(0),
             least_readable_version: AtomicVersion::new(0),
+            enabled: AtomicBool::new(true),
+            last_batch: Mutex::new(None),
+            last_status: Mutex::new(None),
+            progress_updated_at: Mutex::new(None),
+            prune_lock: Mutex::new(()),
+            prune_window,
+            prune_strategy,
+            last_batch_duration: Mutex::new(Duration::ZERO),
+            progress_callback: Box::new(|_, _| {}),
+            rate_limiter: None,
+            write_retries: None,
+            slow_batch_threshold: None,
+            read_floors: None,
+            never_prune_genesis: false,
         };
         pruner.initialize();
         pruner
     }
 
+    /// Sets the callback told about the version reached and the keys deleted by each successful
+    /// round, which runs on the pruning thread.
+    #[allow(dead_code)]
+    pub fn with_progress_callback(mut self, progress_callback: PruneProgressCallback) -> Self {
+        self.progress_callback = progress_callback;
+        self
+    }
+
+    /// Caps the bytes written by `rate_limiter`, sleeping on the pruning thread before each write
+    /// until it fits in.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Has the writes retried up to `max_retries` times on transient failures, backing off
+    /// exponentially from `base_backoff`. Nothing is recomputed, the same batch is written again.
+    pub fn with_write_retries(mut self, max_retries: u32, base_backoff: Duration) -> Self {
+        self.write_retries = Some(WriteRetries::new(max_retries, base_backoff));
+        self
+    }
+
+    /// Keeps the rounds from pruning past the lowest version registered in `read_floors`.
+    #[allow(dead_code)]
+    pub fn with_read_floors(mut self, read_floors: Arc<ReadFloorRegistry>) -> Self {
+        self.read_floors = Some(read_floors);
+        self
+    }
+
+    /// Has rounds taking longer than `slow_batch_threshold`, write included, warned about, along
+    /// with the range pruned and the number of keys deleted.
+    pub fn with_slow_batch_threshold(mut self, slow_batch_threshold: Duration) -> Self {
+        self.slow_batch_threshold = Some(slow_batch_threshold);
+        self
+    }
+
+    /// Keeps the nodes of the genesis state forever, while the nodes they are replaced by are
+    /// pruned as usual once stale, so that the state of version 0 can always be read and proven,
+    /// even though it's below the least readable version.
+    pub fn with_genesis_kept(mut self) -> Self {
+        self.never_prune_genesis = true;
+        self
+    }
+
     /// Purge the stale node index so that after restart not too much already pruned stuff is dealt
     /// with again (although no harm is done deleting those then non-existent things.)
     ///
@@ -129,18 +380,24 @@ impl StateStorePruner {
         const MIN_INTERVAL: Duration = Duration::from_secs(10);
         const MIN_VERSIONS: u64 = 60000;
 
+        // The entries of the nodes of genesis kept are to be left, so the others are deleted
+        // along with their nodes instead, see `prune_state_store()`.
+        if self.never_prune_genesis {
+            return Ok(());
+        }
+
         // A deletion is issued at most once in one minute and when the pruner has progressed by at
         // least 60000 versions (assuming the pruner deletes as slow as 1000 versions per second,
         // this imposes at most one minute of work in vain after restarting.)
         let now = Instant::now();
         if now - *self.index_purged_at.lock() > MIN_INTERVAL
-            && self.least_readable_version.load(Ordering::Relaxed)
+            && self.least_readable_version.load(Ordering::Acquire)
                 - self.index_min_nonpurged_version()
                 + 1
                 > MIN_VERSIONS
         {
             let new_min_non_purged_version =
-                self.least_readable_version.load(Ordering::Relaxed) + 1;
+                self.least_readable_version.load(Ordering::Acquire) + 1;
             self.db.range_delete::<StaleNodeIndexSchema, Version>(
                 &self.index_min_nonpurged_version(),
                 &new_min_non_purged_version, // end is exclusive
@@ -166,7 +423,10 @@ pub fn prune_state_store(
     least_readable_version: Version,
     target_version: Version,
     max_versions: usize,
-) -> anyhow::Result<Version> {
+    rate_limiter: Option<&RateLimiter>,
+    write_retries: Option<&WriteRetries>,
+    never_prune_genesis: bool,
+) -> anyhow::Result<PruneProgress> {
     let indices =
         StaleNodeIndicesByVersionIterator::new(&db, least_readable_version, target_version)?
             .take(max_versions) // Iterator<Item = Result<Vec<StaleNodeIndex>>>
@@ -176,18 +436,51 @@ pub fn prune_state_store(
             .collect::<Vec<_>>();
 
     if indices.is_empty() {
-        Ok(least_readable_version)
+        Ok(PruneProgress {
+            version: least_readable_version,
+            keys_pruned: 0,
+        })
     } else {
         let _timer = OTHER_TIMERS_SECONDS
             .with_label_values(&["pruner_commit"])
             .start_timer();
         let new_least_readable_version = indices.last().expect("Should exist.").stale_since_version;
         let mut batch = SchemaBatch::new();
-        indices
-            .into_iter()
-            .try_for_each(|index| batch.delete::<JellyfishMerkleNodeSchema>(&index.node_key))?;
-        db.write_schemas(batch)?;
-        Ok(new_least_readable_version)
+        let mut keys_pruned = 0;
+        for index in indices {
+            if never_prune_genesis {
+                // The entry is left along with the node it points to.
+                if index.node_key.version() == 0 {
+                    continue;
+                }
+                batch.delete::<StaleNodeIndexSchema>(&index)?;
+            }
+            batch.delete::<JellyfishMerkleNodeSchema>(&index.node_key)?;
+            keys_pruned += 1;
+        }
+        // Persisted along with the deletions, for the pruner to resume from after restart.
+        batch.put::<DbMetadataSchema>(
+            &DbMetadataKey::StateStorePrunerProgress,
+            &DbMetadataValue::Version(new_least_readable_version),
+        )?;
+        let size_in_bytes = batch.size_in_bytes();
+        debug!(
+            pruned_version = new_least_readable_version,
+            size_in_bytes = size_in_bytes,
+            "{} batch ready to be written.",
+            STATE_STORE_PRUNER_NAME
+        );
+        if let Some(rate_limiter) = rate_limiter {
+            rate_limiter.consume(size_in_bytes);
+        }
+        match write_retries {
+            Some(write_retries) => write_retries.run(|| db.write_schemas_ref(&batch))?,
+            None => db.write_schemas(batch)?,
+        }
+        Ok(PruneProgress {
+            version: new_least_readable_version,
+            keys_pruned,
+        })
     }
 }
 
@@ -202,7 +495,9 @@ impl<'a> StaleNodeIndicesByVersionIterator<'a> {
         least_readable_version: Version,
         target_least_readable_version: Version,
     ) -> anyhow::Result<Self> {
-        let mut iter = db.iter::<StaleNodeIndexSchema>(ReadOptions::default())?;
+        // Every index is read once right before it's deleted, which is no use caching.
+        let mut iter =
+            db.iter::<StaleNodeIndexSchema>(PruneReadOptions::default().read_options())?;
         iter.seek(&least_readable_version)?;
 
         Ok(Self {
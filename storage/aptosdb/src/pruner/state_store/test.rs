@@ -1,13 +1,31 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
 
 use aptos_crypto::HashValue;
+use aptos_infallible::duration_since_epoch;
 use aptos_temppath::TempPath;
-use aptos_types::state_store::{state_key::StateKey, state_value::StateValue};
+use aptos_types::{
+    state_store::{state_key::StateKey, state_value::StateValue},
+    transaction::Transaction,
+};
+use schemadb::{ReadOptions, SchemaBatch};
 
-use crate::{change_set::ChangeSet, pruner::*, state_store::StateStore, AptosDB};
+use crate::{
+    change_set::ChangeSet,
+    pruner::{
+        db_pruner::DBPruner, prune_strategy::FixedBatchStrategy, read_floor::ReadFloorRegistry,
+        state_store::StateStorePruner, *,
+    },
+    schema::stale_node_index::StaleNodeIndexSchema,
+    state_store::StateStore,
+    AptosDB,
+};
 
 fn put_value_set(
     db: &DB,
@@ -57,13 +75,13 @@ fn test_state_store_pruner() {
     let pruner = Pruner::new(
         Arc::clone(&db),
         StoragePrunerConfig {
-            state_store_prune_window: Some(0),
-            ledger_prune_window: Some(0),
-            pruning_batch_size: prune_batch_size,
+            never_prune_genesis: true,
+            ..StoragePrunerConfig::new(Some(0), Some(0), prune_batch_size)
         },
         Arc::clone(transaction_store),
         Arc::clone(&aptos_db.ledger_store),
         Arc::clone(&aptos_db.event_store),
+        None, /* archive_db */
     );
 
     let mut root_hashes = vec![];
@@ -106,7 +124,7 @@ fn test_state_store_pruner() {
         .is_err());
 
     // Notify the pruner to update the version to be 10 - since we use a batch size of 10,
-    // we expect versions 0 to 9 to be pruned.
+    // we expect versions 1 to 9 to be pruned.
     {
         pruner
             .wake_and_wait(
@@ -114,7 +132,14 @@ fn test_state_store_pruner() {
                 PrunerIndex::StateStorePrunerIndex as usize,
             )
             .unwrap();
-        for i in 0..prune_batch_size {
+        // But for genesis, which is kept.
+        verify_state_in_store(
+            state_store,
+            key.clone(),
+            Some(&StateValue::from(vec![0])),
+            0,
+        );
+        for i in 1..prune_batch_size {
             assert!(state_store
                 .get_value_with_proof_by_version(&key, i as u64)
                 .is_err());
@@ -130,6 +155,201 @@ fn test_state_store_pruner() {
     }
 }
 
+#[test]
+fn test_state_store_pruner_on_empty_db() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let pruner = StateStorePruner::new(
+        Arc::clone(&aptos_db.db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    );
+
+    // Nothing is stale yet, so the pruner starts from version 0.
+    assert_eq!(pruner.initialize_least_readable_version().unwrap(), 0);
+    assert_eq!(pruner.least_readable_version(), 0);
+}
+
+#[test]
+fn test_state_store_pruner_idle() {
+    let key = StateKey::Raw(String::from("test_key1").into_bytes());
+    let value = StateValue::from(String::from("test_val1").into_bytes());
+
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let db = aptos_db.db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    put_value_set(&db, state_store, vec![(key.clone(), value.clone())], 0);
+    put_value_set(&db, state_store, vec![(key.clone(), value.clone())], 1);
+
+    let pruner = StateStorePruner::new(
+        Arc::clone(&db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    );
+    pruner.record_progress(1);
+    pruner.set_target_version(1);
+    assert!(!pruner.is_pruning_pending());
+
+    // With the target not beyond the least readable version, nothing is handed over to commit
+    // and the DB is left untouched.
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, 100).unwrap();
+    assert_eq!(progress.version, 1);
+    assert_eq!(progress.keys_pruned, 0);
+    assert!(db_batch.is_empty());
+    assert_eq!(pruner.least_readable_version(), 1);
+    verify_state_in_store(state_store, key.clone(), Some(&value), 0);
+    verify_state_in_store(state_store, key, Some(&value), 1);
+}
+
+#[test]
+fn test_state_store_pruner_on_genesis_only_db() {
+    let key = StateKey::Raw(String::from("test_key1").into_bytes());
+    let value = StateValue::from(vec![0]);
+
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let db = aptos_db.db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    put_value_set(
+        &db,
+        state_store,
+        vec![(key.clone(), value.clone())],
+        0, /* version */
+    );
+    let pruner = StateStorePruner::new(
+        Arc::clone(&db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    )
+    .with_genesis_kept();
+
+    // With only genesis in the DB, the pruner stays at it whatever the target.
+    pruner.set_target_version(10);
+    assert_eq!(pruner.current_batch_target(100), 0);
+    pruner.prune(&mut SchemaBatch::new(), 100).unwrap();
+    assert_eq!(pruner.least_readable_version(), 0);
+    verify_state_in_store(state_store, key, Some(&value), 0);
+}
+
+#[test]
+fn test_state_store_pruner_keeps_genesis() {
+    let key = StateKey::Raw(String::from("test_key1").into_bytes());
+    let num_versions = 4;
+
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let db = aptos_db.db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    for i in 0..num_versions {
+        put_value_set(
+            &db,
+            state_store,
+            vec![(key.clone(), StateValue::from(vec![i as u8]))],
+            i, /* version */
+        );
+    }
+    let pruner = StateStorePruner::new(
+        Arc::clone(&db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    )
+    .with_genesis_kept();
+
+    // The state of genesis is left, while that of the versions replacing it is pruned.
+    pruner.set_target_version(num_versions - 1);
+    pruner.prune(&mut SchemaBatch::new(), 100).unwrap();
+    assert_eq!(pruner.least_readable_version(), num_versions - 1);
+    // So are the stale node index entries, but for those of the nodes of genesis.
+    let mut iter = db
+        .iter::<StaleNodeIndexSchema>(ReadOptions::default())
+        .unwrap();
+    iter.seek_to_first();
+    let pruned_indices: Vec<_> = iter
+        .map(|item| item.unwrap().0)
+        .filter(|index| index.stale_since_version <= num_versions - 1)
+        .collect();
+    assert!(!pruned_indices.is_empty());
+    assert!(pruned_indices
+        .iter()
+        .all(|index| index.node_key.version() == 0));
+    verify_state_in_store(
+        state_store,
+        key.clone(),
+        Some(&StateValue::from(vec![0])),
+        0,
+    );
+    for i in 1..num_versions - 1 {
+        assert!(state_store
+            .get_value_with_proof_by_version(&key, i)
+            .is_err());
+    }
+    verify_state_in_store(
+        state_store,
+        key,
+        Some(&StateValue::from(vec![num_versions as u8 - 1])),
+        num_versions - 1,
+    );
+}
+
+#[test]
+fn test_current_batch_target() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let pruner = StateStorePruner::new(
+        Arc::clone(&aptos_db.db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    );
+    pruner.record_progress(10);
+    pruner.set_target_version(15);
+
+    assert_eq!(pruner.current_batch_target(3), 13);
+    assert_eq!(pruner.current_batch_target(5), 15);
+    // Never goes beyond the target version, however many versions are allowed.
+    assert_eq!(pruner.current_batch_target(100), 15);
+}
+
+#[test]
+fn test_state_store_pruner_read_floor() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let read_floors = Arc::new(ReadFloorRegistry::default());
+    let pruner = StateStorePruner::new(
+        Arc::clone(&aptos_db.db),
+        0,
+        Instant::now(),
+        0,
+        Arc::new(FixedBatchStrategy),
+    )
+    .with_read_floors(Arc::clone(&read_floors));
+    pruner.record_progress(10);
+    pruner.set_target_version(15);
+
+    // Held back at the lowest floor, with nothing to do once it's below the least readable
+    // version.
+    let guard = read_floors.register(12);
+    assert_eq!(guard.version(), 12);
+    assert_eq!(pruner.current_batch_target(100), 12);
+    let lower_guard = read_floors.register(5);
+    assert!(!pruner.is_pruning_pending());
+
+    drop(lower_guard);
+    drop(guard);
+    assert_eq!(pruner.current_batch_target(100), 15);
+}
+
 #[test]
 fn test_worker_quit_eagerly() {
     let key = StateKey::Raw(String::from("test_key1").into_bytes());
@@ -171,7 +391,10 @@ fn test_worker_quit_eagerly() {
             Arc::clone(&aptos_db.event_store),
             command_receiver,
             Arc::new(Mutex::new(vec![0, 0])), /* progress */
-            100,
+            StoragePrunerConfig::new(Some(0), Some(0), 100),
+            Arc::new(AtomicBool::new(false)), /* stop_flag */
+            Arc::new(AtomicBool::new(false)), /* paused */
+            None,                             /* archive_db */
         );
         command_sender
             .send(Command::Prune {
@@ -191,3 +414,171 @@ fn test_worker_quit_eagerly() {
         verify_state_in_store(state_store, key, Some(&value2), 2);
     }
 }
+
+#[test]
+fn test_worker_paused() {
+    let key = StateKey::Raw(String::from("test_key1").into_bytes());
+    let value0 = StateValue::from(String::from("test_val1").into_bytes());
+    let value1 = StateValue::from(String::from("test_val2").into_bytes());
+
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let db = aptos_db.db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    put_value_set(&db, state_store, vec![(key.clone(), value0.clone())], 0);
+    put_value_set(&db, state_store, vec![(key.clone(), value1)], 1);
+
+    let (command_sender, command_receiver) = channel();
+    let progress = Arc::new(Mutex::new(vec![0, 0]));
+    let paused = Arc::new(AtomicBool::new(true));
+    let worker = Worker::new(
+        Arc::clone(&db),
+        Arc::clone(&aptos_db.transaction_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&aptos_db.event_store),
+        command_receiver,
+        Arc::clone(&progress),
+        StoragePrunerConfig::new(Some(0), Some(0), 100),
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        Arc::clone(&paused),
+        None, /* archive_db */
+    );
+    let worker_thread = thread::spawn(move || worker.work());
+
+    // Nothing is pruned while paused, whatever the target.
+    command_sender
+        .send(Command::Prune {
+            target_db_versions: vec![1, 0],
+        })
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    verify_state_in_store(state_store, key.clone(), Some(&value0), 0);
+
+    // Once resumed, the next command has the pruning go on up to the target.
+    paused.store(false, Ordering::Relaxed);
+    command_sender
+        .send(Command::Prune {
+            target_db_versions: vec![1, 0],
+        })
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while progress.lock()[0] < 1 {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for pruner worker."
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(state_store
+        .get_value_with_proof_by_version(&key, 0)
+        .is_err());
+    command_sender.send(Command::Quit).unwrap();
+    worker_thread.join().unwrap();
+}
+
+#[test]
+fn test_worker_waits_for_schedule() {
+    let key = StateKey::Raw(String::from("test_key1").into_bytes());
+    let value0 = StateValue::from(String::from("test_val1").into_bytes());
+    let value1 = StateValue::from(String::from("test_val2").into_bytes());
+
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let db = aptos_db.db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    put_value_set(&db, state_store, vec![(key.clone(), value0.clone())], 0);
+    put_value_set(&db, state_store, vec![(key.clone(), value1)], 1);
+
+    // A window opening at least an hour from now.
+    let hour = (duration_since_epoch().as_secs() / 3600 % 24) as u8;
+    let mut storage_pruner_config = StoragePrunerConfig::new(Some(0), Some(0), 100);
+    storage_pruner_config.pruning_window_utc_hours = Some(((hour + 2) % 24, (hour + 3) % 24));
+    let (command_sender, command_receiver) = channel();
+    let worker = Worker::new(
+        Arc::clone(&db),
+        Arc::clone(&aptos_db.transaction_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&aptos_db.event_store),
+        command_receiver,
+        Arc::new(Mutex::new(vec![0, 0])), /* progress */
+        storage_pruner_config,
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        Arc::new(AtomicBool::new(false)), /* paused */
+        None,                             /* archive_db */
+    );
+    command_sender
+        .send(Command::Prune {
+            target_db_versions: vec![1, 0],
+        })
+        .unwrap();
+    let quitter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        command_sender.send(Command::Quit).unwrap();
+    });
+    // The worker waits for the window with nothing pruned, but still quits when told to.
+    worker.work();
+    quitter.join().unwrap();
+    verify_state_in_store(state_store, key, Some(&value0), 0);
+}
+
+#[test]
+fn test_worker_min_prune_interval() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_txns = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let mut storage_pruner_config = StoragePrunerConfig::new(Some(0), Some(0), 100);
+    storage_pruner_config.pruning_min_interval_ms = 3_600_000;
+    let (command_sender, command_receiver) = channel();
+    let progress = Arc::new(Mutex::new(vec![0, 0]));
+    let worker = Worker::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&aptos_db.event_store),
+        command_receiver,
+        Arc::clone(&progress),
+        storage_pruner_config,
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        Arc::new(AtomicBool::new(false)), /* paused */
+        None,                             /* archive_db */
+    );
+    let worker_thread = thread::spawn(move || worker.work());
+
+    // The first round runs right away.
+    command_sender
+        .send(Command::Prune {
+            target_db_versions: vec![0, 5],
+        })
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while progress.lock()[1] < 5 {
+        assert!(
+            Instant::now() < deadline,
+            "Timeout waiting for pruner worker."
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(transaction_store.get_transaction(4).is_err());
+
+    // The next one does nothing within the interval since the commit, while the worker still quits
+    // when told to.
+    command_sender
+        .send(Command::Prune {
+            target_db_versions: vec![0, num_txns],
+        })
+        .unwrap();
+    thread::sleep(Duration::from_millis(100));
+    command_sender.send(Command::Quit).unwrap();
+    worker_thread.join().unwrap();
+    assert_eq!(progress.lock()[1], 5);
+    assert!(transaction_store.get_transaction(5).is_ok());
+}
@@ -0,0 +1,44 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_infallible::Mutex;
+use aptos_types::transaction::Version;
+use std::collections::BTreeMap;
+
+/// Keeps track of the ranges of versions state sync is serving chunks of, for the write set
+/// pruner given the reservation not to prune the write sets from the lowest of them on while
+/// they are served.
+#[derive(Default)]
+pub struct StateSyncReservation {
+    /// Number of times each range of versions [start, end) is reserved.
+    ranges: Mutex<BTreeMap<(Version, Version), usize>>,
+}
+
+impl StateSyncReservation {
+    /// Keeps the write sets of [start, end) until it's removed as many times as it's added. Write
+    /// sets already pruned by then aren't brought back, so the serving path is to reserve before
+    /// reading them.
+    pub fn add(&self, start: Version, end: Version) {
+        *self.ranges.lock().entry((start, end)).or_insert(0) += 1;
+    }
+
+    /// Releases a reservation of [start, end), returning false if there was none.
+    pub fn remove(&self, start: Version, end: Version) -> bool {
+        let mut ranges = self.ranges.lock();
+        match ranges.get_mut(&(start, end)) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    ranges.remove(&(start, end));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the lowest version reserved, `None` if none is.
+    pub fn lowest_reserved_version(&self) -> Option<Version> {
+        self.ranges.lock().keys().next().map(|(start, _end)| *start)
+    }
+}
@@ -2,6 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #[cfg(test)]
-mod test;
+pub(crate) mod test;
 pub(crate) mod transaction_store_pruner;
 pub(crate) mod write_set_pruner;
@@ -1,21 +1,76 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{pruner::*, AptosDB, ChangeSet, LedgerStore, TransactionStore};
+use crate::{
+    metrics::{PRUNER_INTERMEDIATE_COMMITS_TOTAL, PRUNER_SLOW_BATCHES_TOTAL},
+    pruner::{
+        checkpoint_registry::CheckpointRegistry,
+        clock::{Clock, FrozenClock},
+        db_pruner::{prune_async, DBPruner, PrunerError},
+        db_sub_pruner::DBSubPruner,
+        event_store::event_store_pruner::EventStorePruner,
+        ledger_store::ledger_store_pruner::{
+            LedgerPruner, LEDGER_PRUNER_NAME, STOP_FLAG_CHECK_INTERVAL,
+        },
+        prune_strategy::{
+            DataSizeEstimator, DiskWatermarkStrategy, FixedBatchStrategy, PruneStrategy,
+            SeededBatchStrategy,
+        },
+        pruner_kind::PrunerKind,
+        rate_limiter::RateLimiter,
+        read_floor::ReadFloorRegistry,
+        state_sync_reservation::StateSyncReservation,
+        transaction_store::write_set_pruner::WriteSetPruner,
+        *,
+    },
+    schema::{
+        db_metadata::{DbMetadataKey, DbMetadataSchema, DbMetadataValue},
+        transaction::TransactionSchema,
+        write_set::WriteSetSchema,
+        EVENT_CF_NAME,
+    },
+    AptosDB, ChangeSet, LedgerStore, TransactionStore,
+};
+use aptos_crypto::{hash::CryptoHash, HashValue};
+use aptos_infallible::Mutex;
 use aptos_temppath::TempPath;
+use move_deps::move_core_types::language_storage::TypeTag;
 use proptest::proptest;
 
 use aptos_types::{
     account_address::AccountAddress,
+    block_info::BlockInfo,
     block_metadata::BlockMetadata,
+    contract_event::ContractEvent,
+    event::EventKey,
+    ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
     transaction::{SignedTransaction, Transaction},
 };
 
 use aptos_types::{
+    state_store::state_key::StateKey,
     transaction::{TransactionInfo, Version},
-    write_set::WriteSet,
+    write_set::{WriteOp, WriteSet, WriteSetMut},
 };
 use proptest::{collection::vec, prelude::*};
+use schemadb::{ReadOptions, SchemaBatch};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::atomic::AtomicU64,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    prelude::*,
+};
 
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(10))]
@@ -32,57 +87,1327 @@ proptest! {
         verify_txn_store_pruner(txns, txn_infos, step_size)
     }
 
-     #[test]
-    fn test_write_set_pruner(
-        write_set in vec(any::<WriteSet>(), 100),
-        ) {
-            verify_write_set_pruner(write_set);
-        }
+     #[test]
+    fn test_write_set_pruner(
+        write_set in vec(any::<WriteSet>(), 100),
+        ) {
+            verify_write_set_pruner(write_set);
+        }
+
+    #[test]
+    fn test_ledger_pruner_batches(
+        num_versions in 1u64..200,
+        max_versions in 1u64..50,
+        seed in proptest::option::of(any::<u64>()),
+    ) {
+        verify_ledger_pruner_batches(num_versions, max_versions, seed);
+    }
+}
+
+/// Creates a ledger pruner over the stores of `aptos_db`, with no prune window.
+pub(crate) fn create_ledger_pruner(aptos_db: &AptosDB) -> LedgerPruner {
+    LedgerPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(&aptos_db.transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        0,                                /* prune_window */
+    )
+}
+
+/// Prunes the `num_versions` versions of a contiguous ledger in rounds of at most `max_versions`,
+/// cut by a strategy seeded with `seed` if any, and checks that each round advances by exactly
+/// what's predicted, picking up where the last one ended, until all is pruned.
+fn verify_ledger_pruner_batches(num_versions: Version, max_versions: Version, seed: Option<u64>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    // With the same seed, the twin makes the same draws as the pruner's strategy.
+    let strategy = |seed: Option<u64>| -> Arc<dyn PruneStrategy + Send + Sync> {
+        match seed {
+            Some(seed) => Arc::new(SeededBatchStrategy::new(seed)),
+            None => Arc::new(FixedBatchStrategy),
+        }
+    };
+    let twin = strategy(seed);
+    let pruner = LedgerPruner::new_with_dry_run(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        0,                                /* prune_window */
+        false,                            /* dry_run */
+        false,                            /* compact_after_prune */
+        strategy(seed),
+    );
+
+    pruner.set_target_version(num_versions);
+    let mut total_advance = 0;
+    while pruner.is_pruning_pending() {
+        let begin = pruner.least_readable_version();
+        let expected_version = twin.next_target(
+            begin,
+            min(begin + max_versions, num_versions),
+            Duration::ZERO,
+        );
+        let mut db_batch = SchemaBatch::new();
+        let progress = pruner.prune(&mut db_batch, max_versions).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+        assert_eq!(progress.version, expected_version);
+        assert!(expected_version > begin);
+        total_advance += expected_version - begin;
+        // Pruned right up to where the next round starts.
+        assert!(transaction_store
+            .get_transaction(expected_version - 1)
+            .is_err());
+        if expected_version < num_versions {
+            assert!(transaction_store.get_transaction(expected_version).is_ok());
+        }
+    }
+    assert_eq!(total_advance, num_versions);
+    assert_eq!(pruner.least_readable_version(), num_versions);
+}
+
+fn verify_write_set_pruner(write_sets: Vec<WriteSet>) {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = write_sets.len();
+
+    let pruner = Pruner::new(
+        Arc::clone(&aptos_db.db),
+        StoragePrunerConfig::new(Some(0), Some(0), 1),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&aptos_db.event_store),
+        None, /* archive_db */
+    );
+
+    // write sets
+    let mut cs = ChangeSet::new();
+    for (ver, ws) in write_sets.iter().enumerate() {
+        transaction_store
+            .put_write_set(ver as Version, ws, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    // start pruning write sets in batches of size 2 and verify transactions have been pruned from DB
+    for i in (0..=num_write_sets).step_by(2) {
+        pruner
+            .wake_and_wait(
+                i as u64, /* latest_version */
+                PrunerIndex::LedgerPrunerIndex as usize,
+            )
+            .unwrap();
+        // ensure that all transaction up to i * 2 has been pruned
+        for j in 0..i {
+            assert!(transaction_store.get_write_set(j as u64).is_err());
+        }
+        // ensure all other are valid in DB
+        for j in i..num_write_sets {
+            let write_set_from_db = transaction_store.get_write_set(j as u64).unwrap();
+            assert_eq!(write_set_from_db, *write_sets.get(j).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_write_set_pruner_stops_on_request() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 3 * STOP_FLAG_CHECK_INTERVAL;
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let pruner = LedgerPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&stop_flag),
+        0, /* prune_window */
+    );
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // With a stop requested, every round only gets through the first chunk of its range, but
+    // still makes progress and leaves the DB consistent up to the version reached. Only the last
+    // round, reaching the target, is not reported as interrupted.
+    pruner.set_target_version(num_write_sets);
+    stop_flag.store(true, Ordering::Relaxed);
+    let mut last_version = 0;
+    for round in 0..3 {
+        let mut db_batch = SchemaBatch::new();
+        let progress = match pruner.prune(&mut db_batch, num_write_sets) {
+            Err(PrunerError::Interrupted(progress)) if round < 2 => progress,
+            Ok(progress) if round == 2 => progress,
+            result => panic!("Unexpected result in round {}: {:?}", round, result),
+        };
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+
+        assert_eq!(progress.version, last_version + STOP_FLAG_CHECK_INTERVAL);
+        assert_eq!(pruner.least_readable_version(), progress.version);
+        for ver in 0..progress.version {
+            assert!(transaction_store.get_write_set(ver).is_err());
+        }
+        for ver in progress.version..num_write_sets {
+            assert!(transaction_store.get_write_set(ver).is_ok());
+        }
+        last_version = progress.version;
+    }
+    assert!(!pruner.is_pruning_pending());
+}
+
+#[test]
+fn test_write_set_pruner_progress_callback() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 2 * STOP_FLAG_CHECK_INTERVAL;
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let reported_clone = Arc::clone(&reported);
+    let pruner = LedgerPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&stop_flag),
+        0, /* prune_window */
+    )
+    .with_progress_callback(Box::new(move |version, keys_deleted| {
+        reported_clone.lock().push((version, keys_deleted))
+    }));
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // The interrupted round is not reported.
+    pruner.set_target_version(num_write_sets);
+    stop_flag.store(true, Ordering::Relaxed);
+    let mut db_batch = SchemaBatch::new();
+    assert!(matches!(
+        pruner.prune(&mut db_batch, num_write_sets),
+        Err(PrunerError::Interrupted(_))
+    ));
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert!(reported.lock().is_empty());
+
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(
+        *reported.lock(),
+        vec![(num_write_sets, progress.keys_pruned)]
+    );
+
+    // Neither is an idle one.
+    pruner
+        .prune(&mut SchemaBatch::new(), num_write_sets)
+        .unwrap();
+    assert_eq!(reported.lock().len(), 1);
+}
+
+#[test]
+fn test_write_set_pruner_leaves_no_orphaned_rows() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 10;
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        let write_set = WriteSetMut::new(vec![(
+            StateKey::Raw(ver.to_be_bytes().to_vec()),
+            WriteOp::Value(vec![ver as u8]),
+        )])
+        .freeze()
+        .unwrap();
+        transaction_store
+            .put_write_set(ver, &write_set, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(5);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // Nothing is left of the write sets pruned, whatever they wrote.
+    let mut iter = aptos_db
+        .db
+        .iter::<WriteSetSchema>(ReadOptions::default())
+        .unwrap();
+    iter.seek_to_first();
+    let versions: Vec<Version> = iter.map(|item| item.unwrap().0).collect();
+    assert_eq!(versions, (5..num_write_sets).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_write_set_pruner_rate_limit() {
+    let num_write_sets = 500;
+    let max_versions = 100;
+    let write_sets_pruned = |rate_limiter: Option<RateLimiter>| {
+        let tmp_dir = TempPath::new();
+        let aptos_db = AptosDB::new_for_test(&tmp_dir);
+        let transaction_store = &aptos_db.transaction_store;
+        let mut pruner = create_ledger_pruner(&aptos_db);
+        if let Some(rate_limiter) = rate_limiter {
+            pruner = pruner.with_rate_limiter(Arc::new(rate_limiter));
+        }
+        let mut cs = ChangeSet::new();
+        for ver in 0..num_write_sets {
+            transaction_store
+                .put_write_set(ver, &WriteSet::default(), &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+
+        pruner.set_target_version(num_write_sets);
+        let mut total_bytes = 0;
+        while pruner.is_pruning_pending() {
+            let mut db_batch = SchemaBatch::new();
+            pruner.prune(&mut db_batch, max_versions).unwrap();
+            total_bytes += db_batch.size_in_bytes();
+            aptos_db.db.write_schemas(db_batch).unwrap();
+            pruner.on_committed();
+        }
+        total_bytes
+    };
+
+    let total_bytes = write_sets_pruned(None);
+    // The bucket starts with a second's worth of bytes, and the rest is waited for as the clock
+    // doesn't move otherwise.
+    let bytes_per_sec = total_bytes as u64 / 2;
+    let clock = Arc::new(FrozenClock::default());
+    let rate_limiter = RateLimiter::new(
+        bytes_per_sec,
+        Arc::clone(&clock) as Arc<dyn Clock + Send + Sync>,
+    );
+    assert_eq!(write_sets_pruned(Some(rate_limiter)), total_bytes);
+    let slept: Duration = clock.slept.lock().iter().sum();
+    let expected =
+        Duration::from_secs_f64((total_bytes as u64 - bytes_per_sec) as f64 / bytes_per_sec as f64);
+    assert!(
+        slept.max(expected) - slept.min(expected) < Duration::from_millis(1),
+        "{:?} vs {:?}",
+        slept,
+        expected
+    );
+}
+
+#[test]
+fn test_write_set_pruner_prune_to_target() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 1000;
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let pruner = LedgerPruner::new(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::clone(&stop_flag),
+        0, /* prune_window */
+    );
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // Stopped, not even a round is run.
+    pruner.set_target_version(num_write_sets / 2);
+    stop_flag.store(true, Ordering::Relaxed);
+    assert_eq!(pruner.prune_to_target(30).unwrap(), 0);
+    assert!(transaction_store.get_write_set(0).is_ok());
+
+    // Batches of 30 versions converge to targets not a multiple of it.
+    stop_flag.store(false, Ordering::Relaxed);
+    for target_version in [num_write_sets / 2, num_write_sets] {
+        pruner.set_target_version(target_version);
+        assert_eq!(pruner.prune_to_target(30).unwrap(), target_version);
+        assert!(!pruner.is_pruning_pending());
+        for ver in 0..num_write_sets {
+            assert_eq!(
+                transaction_store.get_write_set(ver).is_ok(),
+                ver >= target_version
+            );
+        }
+    }
+}
+
+#[test]
+fn test_write_set_pruner_prune_window() {
+    let num_write_sets = 10;
+    for (prune_window, expected_version) in [(0, 10), (3, 7), (10, 0), (20, 0)] {
+        let tmp_dir = TempPath::new();
+        let aptos_db = AptosDB::new_for_test(&tmp_dir);
+        let transaction_store = &aptos_db.transaction_store;
+        let pruner = LedgerPruner::new(
+            Arc::clone(&aptos_db.db),
+            Arc::clone(transaction_store),
+            Arc::clone(&aptos_db.event_store),
+            Arc::clone(&aptos_db.ledger_store),
+            Arc::new(AtomicBool::new(false)), /* stop_flag */
+            prune_window,
+        );
+
+        let mut cs = ChangeSet::new();
+        for ver in 0..num_write_sets {
+            transaction_store
+                .put_write_set(ver, &WriteSet::default(), &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+
+        // The last `prune_window` versions before the target are kept, and a window covering the
+        // whole history leaves nothing to prune.
+        pruner.set_target_version(num_write_sets);
+        assert_eq!(pruner.is_pruning_pending(), expected_version > 0);
+        let mut db_batch = SchemaBatch::new();
+        let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+
+        assert_eq!(progress.version, expected_version);
+        assert_eq!(pruner.least_readable_version(), expected_version);
+        for ver in 0..expected_version {
+            assert!(transaction_store.get_write_set(ver).is_err());
+        }
+        for ver in expected_version..num_write_sets {
+            assert!(transaction_store.get_write_set(ver).is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_write_set_pruner_idle() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 10;
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(5);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // Once the target is reached, further rounds hand nothing over to commit.
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    assert_eq!(progress.version, 5);
+    assert_eq!(progress.keys_pruned, 0);
+    assert!(db_batch.is_empty());
+    for ver in 5..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_ok());
+    }
+}
+
+#[test]
+fn test_write_set_pruner_resumes_from_persisted_progress() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 10;
+    let create_pruner = || create_ledger_pruner(&aptos_db);
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // There are no transactions to tell the progress from, so only the persisted one is known
+    // after restart.
+    let pruner = create_pruner();
+    assert_eq!(pruner.least_readable_version(), 0);
+    pruner.set_target_version(5);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    let pruner = create_pruner();
+    assert_eq!(pruner.least_readable_version(), 5);
+
+    // Crashing before the batch is committed loses the deletions along with the progress.
+    pruner.set_target_version(8);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    assert_eq!(pruner.least_readable_version(), 5);
+    drop(db_batch);
+    let pruner = create_pruner();
+    assert_eq!(pruner.least_readable_version(), 5);
+    for ver in 0..5 {
+        assert!(transaction_store.get_write_set(ver).is_err());
+    }
+    for ver in 5..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_ok());
+    }
+}
+
+#[test]
+fn test_write_set_pruner_concurrent_prune() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 1000;
+    let versions_per_round = 10;
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let pruner = Arc::new(create_ledger_pruner(&aptos_db));
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(num_write_sets);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let pruner = Arc::clone(&pruner);
+            let reported = Arc::clone(&reported);
+            std::thread::spawn(move || {
+                let mut db_batch = SchemaBatch::new();
+                while pruner.is_pruning_pending() {
+                    let progress = pruner.prune(&mut db_batch, versions_per_round).unwrap();
+                    // A round finding the work done by the others meanwhile deletes nothing.
+                    if progress.keys_pruned > 0 {
+                        reported.lock().push(progress.version);
+                    }
+                }
+                db_batch
+            })
+        })
+        .collect();
+    for handle in handles {
+        aptos_db.db.write_schemas(handle.join().unwrap()).unwrap();
+    }
+    pruner.on_committed();
+
+    // Every round picked up exactly where the one before left off.
+    reported.lock().sort_unstable();
+    assert_eq!(
+        *reported.lock(),
+        (1..=num_write_sets / versions_per_round)
+            .map(|round| round * versions_per_round)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(pruner.least_readable_version(), num_write_sets);
+    for ver in 0..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_err());
+    }
+}
+
+#[test]
+fn test_write_set_pruner_commit_threshold() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 10 * STOP_FLAG_CHECK_INTERVAL;
+    let pruner = create_ledger_pruner(&aptos_db)
+        .with_commit_threshold(STOP_FLAG_CHECK_INTERVAL as usize / 2);
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // Every chunk but the last one is committed by the round itself.
+    let commits = || {
+        PRUNER_INTERMEDIATE_COMMITS_TOTAL
+            .with_label_values(&["ledger_pruner"])
+            .get()
+    };
+    let commits_before = commits();
+    pruner.set_target_version(num_write_sets);
+    // What the caller, e.g. another pruner, put in the batch is left for the caller to commit.
+    let state_store_progress = || {
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::StateStorePrunerProgress)
+            .unwrap()
+    };
+    let mut db_batch = SchemaBatch::new();
+    db_batch
+        .put::<DbMetadataSchema>(
+            &DbMetadataKey::StateStorePrunerProgress,
+            &DbMetadataValue::Version(42),
+        )
+        .unwrap();
+    let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+    assert_eq!(commits() - commits_before, 9);
+    assert_eq!(state_store_progress(), None);
+    assert_eq!(progress.version, num_write_sets);
+    // Deletions committed along the way are counted too.
+    assert!(progress.keys_pruned >= num_write_sets as usize);
+    let last_chunk_begin = num_write_sets - STOP_FLAG_CHECK_INTERVAL;
+    assert_eq!(
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version()),
+        Some(last_chunk_begin)
+    );
+    for ver in 0..num_write_sets {
+        assert_eq!(
+            transaction_store.get_write_set(ver).is_ok(),
+            ver >= last_chunk_begin
+        );
+    }
+
+    // The rest is handed over as usual.
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    for ver in 0..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_err());
+    }
+    assert_eq!(state_store_progress(), Some(DbMetadataValue::Version(42)));
+}
+
+#[test]
+fn test_write_set_pruner_commit_threshold_waits_for_uncommitted_batch() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 4 * STOP_FLAG_CHECK_INTERVAL;
+    let pruner = create_ledger_pruner(&aptos_db)
+        .with_commit_threshold(STOP_FLAG_CHECK_INTERVAL as usize / 2);
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let commits = || {
+        PRUNER_INTERMEDIATE_COMMITS_TOTAL
+            .with_label_values(&["ledger_pruner"])
+            .get()
+    };
+    let persisted_progress = || {
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version())
+    };
+    pruner.set_target_version(num_write_sets);
+    // The first round is handed over without being committed.
+    let mut first_batch = SchemaBatch::new();
+    pruner
+        .prune(&mut first_batch, STOP_FLAG_CHECK_INTERVAL)
+        .unwrap();
+
+    // The next one, however large, then commits nothing by itself.
+    let commits_before = commits();
+    let mut second_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut second_batch, num_write_sets).unwrap();
+    assert_eq!(progress.version, num_write_sets);
+    assert_eq!(commits(), commits_before);
+    assert_eq!(persisted_progress(), None);
+    assert_eq!(pruner.least_readable_version(), 0);
+    for ver in 0..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_ok());
+    }
+
+    // Both go once the caller commits them.
+    aptos_db.db.write_schemas(first_batch).unwrap();
+    aptos_db.db.write_schemas(second_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(persisted_progress(), Some(num_write_sets));
+    assert_eq!(pruner.least_readable_version(), num_write_sets);
+    for ver in 0..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_err());
+    }
+}
+
+/// Fails pruning any range reaching `fail_from`, and behaves like `inner` otherwise.
+struct FailingSubPruner {
+    inner: Arc<dyn DBSubPruner + Send + Sync>,
+    fail_from: Version,
+}
+
+impl DBSubPruner for FailingSubPruner {
+    fn prune(
+        &self,
+        db_batch: &mut SchemaBatch,
+        least_readable_version: u64,
+        target_version: u64,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(target_version <= self.fail_from, "Injected failure.");
+        self.inner
+            .prune(db_batch, least_readable_version, target_version)
+    }
+}
+
+/// Takes `delay` to prune nothing, slowing the rounds down.
+struct SlowSubPruner {
+    delay: Duration,
+}
+
+impl DBSubPruner for SlowSubPruner {
+    fn prune(
+        &self,
+        _db_batch: &mut SchemaBatch,
+        _least_readable_version: u64,
+        _target_version: u64,
+    ) -> anyhow::Result<()> {
+        thread::sleep(self.delay);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ledger_pruner_warns_about_slow_batches() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let create_pruner = || {
+        create_ledger_pruner(&aptos_db).with_event_store_pruner(Arc::new(SlowSubPruner {
+            delay: Duration::from_millis(20),
+        }))
+    };
+    let slow_batches = || {
+        PRUNER_SLOW_BATCHES_TOTAL
+            .with_label_values(&[PrunerKind::Ledger.metric_label()])
+            .get()
+    };
+    let slow_batches_before = slow_batches();
+
+    // Without a threshold, slow rounds go unnoticed.
+    let pruner = create_pruner();
+    pruner.set_target_version(10);
+    pruner.prune(&mut SchemaBatch::new(), 10).unwrap();
+    assert_eq!(slow_batches(), slow_batches_before);
+
+    let pruner = create_pruner().with_slow_batch_threshold(Duration::from_secs(3600));
+    pruner.set_target_version(10);
+    pruner.prune(&mut SchemaBatch::new(), 10).unwrap();
+    assert_eq!(slow_batches(), slow_batches_before);
+
+    let pruner = create_pruner().with_slow_batch_threshold(Duration::from_millis(10));
+    pruner.set_target_version(10);
+    pruner.prune(&mut SchemaBatch::new(), 10).unwrap();
+    assert_eq!(slow_batches(), slow_batches_before + 1);
+}
+
+#[test]
+fn test_ledger_pruner_prunes_versions_atomically() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let event_store = &aptos_db.event_store;
+    let num_versions = 2 * STOP_FLAG_CHECK_INTERVAL;
+    // The events are pruned last, and fail on the second chunk.
+    let pruner =
+        create_ledger_pruner(&aptos_db).with_event_store_pruner(Arc::new(FailingSubPruner {
+            inner: Arc::new(EventStorePruner::new(Arc::clone(event_store))),
+            fail_from: STOP_FLAG_CHECK_INTERVAL,
+        }));
+
+    let key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+        let events = vec![ContractEvent::new(key, version, TypeTag::Bool, vec![])];
+        event_store.put_events(version, &events, &mut cs).unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // The batch is committed regardless of the failure, like the pruner worker does.
+    pruner.set_target_version(num_versions);
+    let mut db_batch = SchemaBatch::new();
+    assert!(matches!(
+        pruner.prune(&mut db_batch, num_versions),
+        Err(PrunerError::Io(_))
+    ));
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // Every version is either gone as a whole or left as a whole.
+    for version in 0..num_versions {
+        let is_left = version >= STOP_FLAG_CHECK_INTERVAL;
+        assert_eq!(transaction_store.get_transaction(version).is_ok(), is_left);
+        assert_eq!(transaction_store.get_write_set(version).is_ok(), is_left);
+        assert_eq!(
+            !event_store
+                .get_events_by_version(version)
+                .unwrap()
+                .is_empty(),
+            is_left
+        );
+    }
+}
+
+#[test]
+fn test_ledger_pruner_oldest_live_version_lag() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_txns = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = create_ledger_pruner(&aptos_db);
+    pruner.set_target_version(num_txns);
+    assert_eq!(pruner.oldest_live_version().unwrap(), Some(0));
+    assert_eq!(
+        pruner.record_oldest_live_version_lag().unwrap(),
+        Some(num_txns)
+    );
+
+    // The deletions of a round are dropped while the pruner is told they are committed, which
+    // leaves the least readable version recorded ahead of the data left.
+    pruner.set_target_version(6);
+    pruner.prune(&mut SchemaBatch::new(), num_txns).unwrap();
+    pruner.on_committed();
+    pruner.set_target_version(num_txns);
+    assert_eq!(pruner.least_readable_version(), 6);
+    assert_eq!(pruner.estimated_remaining_versions(), 4);
+    assert_eq!(pruner.oldest_live_version().unwrap(), Some(0));
+    assert_eq!(
+        pruner.record_oldest_live_version_lag().unwrap(),
+        Some(num_txns)
+    );
+
+    // Once the range is pruned for real, the two agree.
+    pruner.record_progress(0);
+    pruner.set_target_version(6);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    pruner.set_target_version(num_txns);
+    assert_eq!(pruner.oldest_live_version().unwrap(), Some(6));
+    assert_eq!(pruner.record_oldest_live_version_lag().unwrap(), Some(4));
+}
+
+#[test]
+fn test_ledger_pruner_keeps_genesis() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let put_versions = |versions: std::ops::Range<Version>| {
+        let mut cs = ChangeSet::new();
+        for version in versions {
+            transaction_store
+                .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+                .unwrap();
+            transaction_store
+                .put_write_set(version, &WriteSet::default(), &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+    };
+    let prune = |pruner: &LedgerPruner| {
+        let mut db_batch = SchemaBatch::new();
+        pruner.prune(&mut db_batch, 100).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+    };
+    put_versions(0..1);
+    let pruner = create_ledger_pruner(&aptos_db).with_genesis_kept();
+
+    // With only genesis in the DB, the pruner stays at it whatever the target.
+    pruner.set_target_version(10);
+    assert_eq!(pruner.current_batch_target(100), 0);
+    prune(&pruner);
+    assert_eq!(pruner.least_readable_version(), 0);
+    assert!(transaction_store.get_transaction(0).is_ok());
+    assert!(transaction_store.get_write_set(0).is_ok());
+
+    // Once there is more, the rounds start past genesis, which is left. The least readable
+    // version stays at genesis until the round is committed.
+    put_versions(1..8);
+    pruner.set_target_version(5);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    assert_eq!(pruner.least_readable_version(), 0);
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(pruner.least_readable_version(), 5);
+    for version in 0..8 {
+        let is_left = version == 0 || version >= 5;
+        assert_eq!(transaction_store.get_transaction(version).is_ok(), is_left);
+        assert_eq!(transaction_store.get_write_set(version).is_ok(), is_left);
+    }
+    pruner.set_target_version(8);
+    prune(&pruner);
+    assert_eq!(pruner.least_readable_version(), 8);
+    assert!(transaction_store.get_transaction(0).is_ok());
+    assert!(transaction_store.get_write_set(0).is_ok());
+}
+
+#[test]
+fn test_ledger_pruner_health() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let event_store = &aptos_db.event_store;
+    let pruner =
+        create_ledger_pruner(&aptos_db).with_event_store_pruner(Arc::new(FailingSubPruner {
+            inner: Arc::new(EventStorePruner::new(Arc::clone(event_store))),
+            fail_from: 5,
+        }));
+    let num_txns = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let health = pruner.health();
+    assert_eq!(health.name, LEDGER_PRUNER_NAME);
+    assert_eq!(health.least_readable_version, 0);
+    assert!(health.enabled);
+    assert_eq!(health.last_batch_duration, None);
+    assert_eq!(health.last_error, None);
+
+    pruner.set_target_version(4);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    let health = pruner.health();
+    assert_eq!(health.least_readable_version, 4);
+    assert_eq!(health.target_version, 4);
+    assert_eq!(health.remaining_versions, 0);
+    assert!(health.last_batch_duration.is_some());
+    assert_eq!(health.last_error, None);
+    assert_eq!(pruner.last_error(), None);
+
+    // The error is kept until a round which does work succeeds.
+    pruner.set_target_version(num_txns);
+    let failing_since = Instant::now();
+    assert!(pruner.prune(&mut SchemaBatch::new(), num_txns).is_err());
+    pruner.on_discarded();
+    let health = pruner.health();
+    assert_eq!(health.least_readable_version, 4);
+    assert_eq!(health.target_version, num_txns);
+    assert_eq!(health.remaining_versions, num_txns - 4);
+    assert!(health.last_error.unwrap().contains("Injected failure."));
+    let (failed_at, error) = pruner.last_error().unwrap();
+    assert!(failed_at >= failing_since);
+    assert!(error.contains("Injected failure."));
+    assert!(pruner.record_last_error_age().is_some());
+
+    pruner.set_target_version(5);
+    pruner.prune(&mut SchemaBatch::new(), num_txns).unwrap();
+    assert_eq!(pruner.health().last_error, None);
+    assert_eq!(pruner.last_error(), None);
+    assert_eq!(pruner.record_last_error_age(), None);
+
+    pruner.set_enabled(false);
+    assert!(!pruner.health().enabled);
+}
+
+#[test]
+fn test_ledger_pruner_reconciles_rolled_back_db() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let create_pruner = || create_ledger_pruner(&aptos_db);
+
+    let num_txns = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = create_pruner();
+    pruner.set_target_version(4);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // The DB is rolled back to a snapshot holding versions 6 and 7 only, while the progress
+    // persisted is still 4.
+    let mut db_batch = SchemaBatch::new();
+    for version in (4..6).chain(8..num_txns) {
+        db_batch.delete::<TransactionSchema>(&version).unwrap();
+    }
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    // As on startup, the pruner is initialized before it's given a target past the latest
+    // version, which the round is clamped to, keeping the latest version.
+    let pruner = create_pruner();
+    assert_eq!(pruner.least_readable_version(), 6);
+    pruner.set_target_version(20);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(progress.version, 7);
+    assert!(transaction_store.get_transaction(6).is_err());
+    assert!(transaction_store.get_transaction(7).is_ok());
+    assert_eq!(pruner.target_version(), 20);
+}
+
+#[test]
+fn test_ledger_pruner_least_readable_version_hint() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // An empty DB has nothing to trust the hint with.
+    assert_eq!(
+        pruner
+            .initialize_least_readable_version_with_hint(5)
+            .unwrap(),
+        0
+    );
+
+    let mut cs = ChangeSet::new();
+    for version in 3..8 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    assert_eq!(
+        pruner
+            .initialize_least_readable_version_with_hint(3)
+            .unwrap(),
+        3
+    );
+    // A hint past the first transaction, before it or past the latest one is stale.
+    for hint in [5, 1, 20] {
+        assert_eq!(
+            pruner
+                .initialize_least_readable_version_with_hint(hint)
+                .unwrap(),
+            3
+        );
+    }
+}
+
+/// Halves the number of versions pruned per round whenever a round takes longer than the
+/// threshold.
+struct HalvingStrategy {
+    batch_size: AtomicU64,
+    threshold: Duration,
+}
+
+impl PruneStrategy for HalvingStrategy {
+    fn next_target(
+        &self,
+        least_readable: Version,
+        target: Version,
+        last_batch_duration: Duration,
+    ) -> Version {
+        if last_batch_duration > self.threshold {
+            let batch_size = self.batch_size.load(Ordering::Relaxed);
+            self.batch_size
+                .store((batch_size / 2).max(1), Ordering::Relaxed);
+        }
+        min(
+            least_readable + self.batch_size.load(Ordering::Relaxed),
+            target,
+        )
+    }
+}
+
+#[test]
+fn test_write_set_pruner_adaptive_strategy() {
+    let num_write_sets = 100;
+    // No round is faster than a zero threshold, while none is slower than the maximum one.
+    for (threshold, expected_versions) in [
+        (Duration::ZERO, vec![8, 12, 14, 15, 16]),
+        (Duration::MAX, vec![8, 16, 24, 32, 40]),
+    ] {
+        let tmp_dir = TempPath::new();
+        let aptos_db = AptosDB::new_for_test(&tmp_dir);
+        let transaction_store = &aptos_db.transaction_store;
+        let pruner = LedgerPruner::new_with_dry_run(
+            Arc::clone(&aptos_db.db),
+            Arc::clone(transaction_store),
+            Arc::clone(&aptos_db.event_store),
+            Arc::clone(&aptos_db.ledger_store),
+            Arc::new(AtomicBool::new(false)), /* stop_flag */
+            0,                                /* prune_window */
+            false,                            /* dry_run */
+            false,                            /* compact_after_prune */
+            Arc::new(HalvingStrategy {
+                batch_size: AtomicU64::new(8),
+                threshold,
+            }),
+        );
+
+        let mut cs = ChangeSet::new();
+        for ver in 0..num_write_sets {
+            transaction_store
+                .put_write_set(ver, &WriteSet::default(), &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+
+        // The strategy only narrows down what the caller allows per round.
+        pruner.set_target_version(num_write_sets);
+        for expected_version in expected_versions {
+            let mut db_batch = SchemaBatch::new();
+            let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+            aptos_db.db.write_schemas(db_batch).unwrap();
+            pruner.on_committed();
+            assert_eq!(progress.version, expected_version);
+            // The batch target read back is the one the strategy came up with.
+            let config = pruner.config();
+            assert_eq!(config.max_versions, Some(num_write_sets));
+            assert_eq!(config.batch_target, Some(expected_version));
+        }
+        assert!(transaction_store.get_write_set(num_write_sets - 1).is_ok());
+    }
+}
+
+/// Reports whatever size it's set to.
+struct MockSizeEstimator(AtomicU64);
+
+impl DataSizeEstimator for MockSizeEstimator {
+    fn estimate_live_data_size(&self) -> anyhow::Result<u64> {
+        Ok(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[test]
+fn test_write_set_pruner_disk_watermark_strategy() {
+    let num_write_sets = 100;
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let size_estimator = Arc::new(MockSizeEstimator(AtomicU64::new(0)));
+    let pruner = LedgerPruner::new_with_dry_run(
+        Arc::clone(&aptos_db.db),
+        Arc::clone(transaction_store),
+        Arc::clone(&aptos_db.event_store),
+        Arc::clone(&aptos_db.ledger_store),
+        Arc::new(AtomicBool::new(false)), /* stop_flag */
+        0,                                /* prune_window */
+        false,                            /* dry_run */
+        false,                            /* compact_after_prune */
+        Arc::new(DiskWatermarkStrategy::new(
+            size_estimator.clone(),
+            50,  /* low_watermark_bytes */
+            100, /* high_watermark_bytes */
+            10,  /* versions_per_round */
+            35,  /* retained_version_floor */
+        )),
+    );
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    // Pruning starts at the high watermark and goes on until below the low one, stopping at the
+    // floor meanwhile.
+    pruner.set_target_version(num_write_sets);
+    for (size, expected_version) in [
+        (80, 0),
+        (120, 10),
+        (80, 20),
+        (60, 30),
+        (55, 35),
+        (55, 35),
+        (40, 35),
+        (70, 35),
+    ] {
+        size_estimator.0.store(size, Ordering::Relaxed);
+        let mut db_batch = SchemaBatch::new();
+        let progress = pruner.prune(&mut db_batch, num_write_sets).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+        assert_eq!(progress.version, expected_version);
+    }
+    assert!(transaction_store.get_write_set(34).is_err());
+    assert!(transaction_store.get_write_set(35).is_ok());
+}
+
+#[tokio::test]
+async fn test_write_set_pruner_prune_async() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_write_sets = 10;
+    let pruner = Arc::new(create_ledger_pruner(&aptos_db));
+
+    let mut cs = ChangeSet::new();
+    for ver in 0..num_write_sets {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(num_write_sets);
+    let (db_batch, progress) = prune_async(
+        Arc::clone(&pruner) as Arc<dyn DBPruner + Send + Sync>,
+        SchemaBatch::new(),
+        num_write_sets,
+    )
+    .await
+    .unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    assert_eq!(progress.version, num_write_sets);
+    assert_eq!(pruner.least_readable_version(), num_write_sets);
+    for ver in 0..num_write_sets {
+        assert!(transaction_store.get_write_set(ver).is_err());
+    }
 }
 
-fn verify_write_set_pruner(write_sets: Vec<WriteSet>) {
+#[test]
+fn test_set_target_by_timestamp() {
     let tmp_dir = TempPath::new();
     let aptos_db = AptosDB::new_for_test(&tmp_dir);
     let transaction_store = &aptos_db.transaction_store;
-    let num_write_sets = write_sets.len();
+    let ledger_store = &aptos_db.ledger_store;
+    let pruner = create_ledger_pruner(&aptos_db);
 
-    let pruner = Pruner::new(
-        Arc::clone(&aptos_db.db),
-        StoragePrunerConfig {
-            state_store_prune_window: Some(0),
-            ledger_prune_window: Some(0),
-            pruning_batch_size: 1,
-        },
-        Arc::clone(transaction_store),
-        Arc::clone(&aptos_db.ledger_store),
-        Arc::clone(&aptos_db.event_store),
+    // Nothing committed yet.
+    assert_eq!(
+        pruner
+            .set_target_by_timestamp(100, ledger_store, transaction_store)
+            .unwrap(),
+        0
     );
 
-    // write sets
+    // Blocks start at versions 1, 4 and 7, with timestamps 100, 200 and 300.
+    let latest_version = 9;
     let mut cs = ChangeSet::new();
-    for (ver, ws) in write_sets.iter().enumerate() {
+    for version in 0..=latest_version {
+        let txn = if version % 3 == 1 {
+            Transaction::BlockMetadata(BlockMetadata::new(
+                HashValue::zero(),
+                0,       /* epoch */
+                version, /* round */
+                vec![],
+                AccountAddress::random(),
+                (version / 3 + 1) * 100,
+            ))
+        } else {
+            Transaction::StateCheckpoint
+        };
         transaction_store
-            .put_write_set(ver as Version, ws, &mut cs)
+            .put_transaction(version, &txn, &mut cs)
             .unwrap();
     }
     aptos_db.db.write_schemas(cs.batch).unwrap();
-    // start pruning write sets in batches of size 2 and verify transactions have been pruned from DB
-    for i in (0..=num_write_sets).step_by(2) {
-        pruner
-            .wake_and_wait(
-                i as u64, /* latest_version */
-                PrunerIndex::LedgerPrunerIndex as usize,
-            )
-            .unwrap();
-        // ensure that all transaction up to i * 2 has been pruned
-        for j in 0..i {
-            assert!(transaction_store.get_write_set(j as u64).is_err());
-        }
-        // ensure all other are valid in DB
-        for j in i..num_write_sets {
-            let write_set_from_db = transaction_store.get_write_set(j as u64).unwrap();
-            assert_eq!(write_set_from_db, *write_sets.get(j).unwrap());
-        }
+    ledger_store.set_latest_ledger_info(LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                0,
+                latest_version,
+                HashValue::zero(),
+                HashValue::zero(),
+                latest_version,
+                300,
+                None,
+            ),
+            HashValue::zero(),
+        ),
+        BTreeMap::new(),
+    ));
+
+    for (timestamp_usecs, expected_version) in [
+        (0, 0),
+        (99, 0),
+        (100, 3),
+        (199, 3),
+        (250, 6),
+        (300, latest_version),
+        (u64::max_value(), latest_version),
+    ] {
+        assert_eq!(
+            pruner
+                .set_target_by_timestamp(timestamp_usecs, ledger_store, transaction_store)
+                .unwrap(),
+            expected_version
+        );
+        assert_eq!(pruner.target_version(), expected_version);
+    }
+
+    // Pruned up to the middle of the second block, the search starts from there.
+    let mut db_batch = SchemaBatch::new();
+    db_batch.delete_range::<TransactionSchema>(&0, &5).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.record_progress(5);
+    for (timestamp_usecs, expected_version) in [(0, 6), (250, 6), (300, latest_version)] {
+        assert_eq!(
+            pruner
+                .set_target_by_timestamp(timestamp_usecs, ledger_store, transaction_store)
+                .unwrap(),
+            expected_version
+        );
     }
 }
 
@@ -99,14 +1424,11 @@ fn verify_txn_store_pruner(
 
     let pruner = Pruner::new(
         Arc::clone(&aptos_db.db),
-        StoragePrunerConfig {
-            state_store_prune_window: Some(0),
-            ledger_prune_window: Some(0),
-            pruning_batch_size: 1,
-        },
+        StoragePrunerConfig::new(Some(0), Some(0), 1),
         Arc::clone(transaction_store),
         Arc::clone(&aptos_db.ledger_store),
         Arc::clone(&aptos_db.event_store),
+        None, /* archive_db */
     );
 
     let ledger_version = num_transaction as Version - 1;
@@ -152,6 +1474,12 @@ fn verify_txn_not_in_store(
 ) {
     // Ensure that all transaction from transaction schema store has been pruned
     assert!(transaction_store.get_transaction(index).is_err());
+    // Ensure that transaction by hash store has been pruned
+    let txn = txns.get(index as usize).unwrap();
+    assert!(transaction_store
+        .get_transaction_version_by_hash(&txn.hash(), ledger_version)
+        .unwrap()
+        .is_none());
     // Ensure that transaction by account store has been pruned
     if let Transaction::UserTransaction(txn) = txns.get(index as usize).unwrap() {
         assert!(transaction_store
@@ -230,3 +1558,603 @@ fn verify_transaction_in_account_txn_by_version_index(
         .unwrap();
     assert_eq!(transaction, expected_value)
 }
+
+#[test]
+fn test_ledger_pruner_reset_progress() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let pruner = create_ledger_pruner(&aptos_db);
+    let put_transactions = |versions: std::ops::Range<Version>| {
+        let mut cs = ChangeSet::new();
+        for version in versions {
+            transaction_store
+                .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+                .unwrap();
+        }
+        aptos_db.db.write_schemas(cs.batch).unwrap();
+    };
+
+    let num_txns = 10;
+    put_transactions(0..num_txns);
+    pruner.set_target_version(6);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+
+    // Partial data is restored into the pruned range.
+    put_transactions(2..6);
+    assert!(pruner.reset_progress(2).is_err());
+    pruner.set_enabled(false);
+    // Past the target, or where no transaction is left.
+    assert!(pruner.reset_progress(7).is_err());
+    assert!(pruner.reset_progress(1).is_err());
+    pruner.reset_progress(2).unwrap();
+    assert_eq!(pruner.least_readable_version(), 2);
+    assert_eq!(
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version()),
+        Some(2)
+    );
+
+    // The restored range is pruned again.
+    pruner.set_enabled(true);
+    let mut db_batch = SchemaBatch::new();
+    let progress = pruner.prune(&mut db_batch, num_txns).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(progress.version, 6);
+    for version in 2..6 {
+        assert!(transaction_store.get_transaction(version).is_err());
+    }
+    assert!(transaction_store.get_transaction(6).is_ok());
+}
+
+#[test]
+fn test_ledger_pruner_flush_progress() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let create_pruner = || create_ledger_pruner(&aptos_db);
+    let persisted_progress = || {
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version())
+    };
+
+    // Moved in memory only, the progress is lost on reload.
+    let pruner = create_pruner();
+    pruner.record_progress(4);
+    assert_eq!(persisted_progress(), None);
+    assert_eq!(create_pruner().least_readable_version(), 0);
+
+    // Until it's flushed.
+    pruner.flush_progress().unwrap();
+    assert_eq!(persisted_progress(), Some(4));
+    assert_eq!(create_pruner().least_readable_version(), 4);
+}
+
+#[test]
+fn test_ledger_pruner_prune_unbounded() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_txns = 20 * STOP_FLAG_CHECK_INTERVAL;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // All in one round, committed already.
+    pruner.set_target_version(num_txns - 1);
+    assert_eq!(pruner.prune_unbounded().unwrap(), num_txns - 1);
+    assert_eq!(pruner.last_batch(), Some((Version::MAX, num_txns - 1)));
+    for version in 0..num_txns - 1 {
+        assert!(transaction_store.get_transaction(version).is_err());
+        assert!(transaction_store.get_write_set(version).is_err());
+    }
+    assert!(transaction_store.get_transaction(num_txns - 1).is_ok());
+    assert_eq!(
+        aptos_db
+            .db
+            .get::<DbMetadataSchema>(&DbMetadataKey::LedgerPrunerProgress)
+            .unwrap()
+            .map(|value| value.expect_version()),
+        Some(num_txns - 1)
+    );
+
+    // Nothing left to do.
+    assert_eq!(pruner.prune_unbounded().unwrap(), num_txns - 1);
+}
+
+#[test]
+fn test_ledger_pruner_clamps_target_to_latest_version() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let latest_version = Arc::new(Mutex::new(None));
+    let pruner = create_ledger_pruner(&aptos_db).with_latest_version({
+        let latest_version = Arc::clone(&latest_version);
+        Box::new(move || *latest_version.lock())
+    });
+
+    // With nothing committed, there is nothing to prune.
+    pruner.set_target_version(100);
+    assert_eq!(pruner.target_version(), 0);
+
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    *latest_version.lock() = Some(9);
+
+    // A target past the data is clamped, the rounds stop at the latest version.
+    pruner.set_target_version(100);
+    assert_eq!(pruner.target_version(), 9);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    assert_eq!(pruner.least_readable_version(), 9);
+    for version in 0..9 {
+        assert!(transaction_store.get_transaction(version).is_err());
+    }
+    assert!(transaction_store.get_transaction(9).is_ok());
+    assert!(transaction_store.get_write_set(9).is_ok());
+
+    // One within the data is left as is.
+    pruner.set_target_version(5);
+    assert_eq!(pruner.target_version(), 5);
+}
+
+#[test]
+fn test_ledger_pruner_min_prune_interval() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let prune = |pruner: &LedgerPruner| {
+        let mut db_batch = SchemaBatch::new();
+        pruner.prune(&mut db_batch, 1).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+    };
+
+    // Calls in a tight loop only get the first one through, the pruner having nothing pending in
+    // the meantime.
+    let pruner = create_ledger_pruner(&aptos_db).with_min_prune_interval(Duration::from_secs(3600));
+    pruner.set_target_version(9);
+    for _ in 0..10 {
+        prune(&pruner);
+    }
+    assert_eq!(pruner.least_readable_version(), 1);
+    assert!(!pruner.is_pruning_pending());
+    assert!(transaction_store.get_transaction(1).is_ok());
+
+    // Without an interval, each call does a round.
+    let pruner = create_ledger_pruner(&aptos_db);
+    pruner.set_target_version(9);
+    for _ in 0..8 {
+        prune(&pruner);
+    }
+    assert_eq!(pruner.least_readable_version(), 9);
+    assert!(transaction_store.get_transaction(8).is_err());
+}
+
+#[test]
+fn test_ledger_pruner_warmup() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+        let events: Vec<_> = (0..2)
+            .map(|i| ContractEvent::new(event_key, 2 * version + i, TypeTag::Bool, vec![]))
+            .collect();
+        aptos_db
+            .event_store
+            .put_events(version, &events, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    // Out of the memtables, so the reads go through the block cache.
+    aptos_db.db.flush_all().unwrap();
+    let block_cache_usage = || {
+        aptos_db
+            .db
+            .get_property(EVENT_CF_NAME, "rocksdb.block-cache-usage")
+            .unwrap()
+    };
+    let pruner = create_ledger_pruner(&aptos_db);
+
+    // A transaction, a write set and two events per version, cached and left as is.
+    let cache_usage = block_cache_usage();
+    assert_eq!(pruner.warmup(4).unwrap(), 4 * 4);
+    assert!(block_cache_usage() > cache_usage);
+    assert_eq!(pruner.least_readable_version(), 0);
+    assert!(transaction_store.get_transaction(0).is_ok());
+
+    // From the least readable version on, up to the data left.
+    pruner.set_target_version(3);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(pruner.warmup(100).unwrap(), 7 * 4);
+}
+
+/// Captures the fields of the spans created while it's the default subscriber, including the ones
+/// recorded after creation, by span name, and the names of the spans closed.
+#[derive(Clone, Default)]
+struct SpanFieldsLayer {
+    fields_by_span: Arc<Mutex<HashMap<String, BTreeMap<String, String>>>>,
+    names_by_id: Arc<Mutex<HashMap<u64, String>>>,
+    closed_spans: Arc<Mutex<Vec<String>>>,
+}
+
+struct FieldsVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl<'a> Visit for FieldsVisitor<'a> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SpanFieldsLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+        let name = attrs.metadata().name().to_string();
+        let mut fields = BTreeMap::new();
+        attrs.record(&mut FieldsVisitor(&mut fields));
+        self.names_by_id.lock().insert(id.into_u64(), name.clone());
+        self.fields_by_span.lock().insert(name, fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+        if let Some(name) = self.names_by_id.lock().get(&id.into_u64()) {
+            if let Some(fields) = self.fields_by_span.lock().get_mut(name) {
+                values.record(&mut FieldsVisitor(fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, _ctx: Context<'_, S>) {
+        if let Some(name) = self.names_by_id.lock().get(&id.into_u64()) {
+            self.closed_spans.lock().push(name.clone());
+        }
+    }
+}
+
+#[test]
+fn test_ledger_pruner_tracing_span() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let pruner = create_ledger_pruner(&aptos_db);
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    let layer = SpanFieldsLayer::default();
+    pruner.set_target_version(4);
+    let progress = tracing::subscriber::with_default(
+        tracing_subscriber::registry().with(layer.clone()),
+        || {
+            let mut db_batch = SchemaBatch::new();
+            let progress = pruner.prune(&mut db_batch, 10).unwrap();
+            // The span is left open until the deletions handed over are committed.
+            assert!(layer.closed_spans.lock().is_empty());
+            pruner
+                .round_span()
+                .in_scope(|| pruner.commit(db_batch))
+                .unwrap();
+            pruner.on_committed();
+            assert_eq!(*layer.closed_spans.lock(), vec![pruner.name().to_string()]);
+            progress
+        },
+    );
+
+    let fields_by_span = layer.fields_by_span.lock();
+    let fields = &fields_by_span[pruner.name()];
+    assert_eq!(fields["start_version"], "0");
+    assert_eq!(fields["target_version"], "4");
+    assert_eq!(fields["keys_deleted"], progress.keys_pruned.to_string());
+}
+
+#[test]
+fn test_ledger_pruner_read_floor() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let read_floors = Arc::new(ReadFloorRegistry::default());
+    let pruner = create_ledger_pruner(&aptos_db).with_read_floors(Arc::clone(&read_floors));
+    let num_txns = 10;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let prune = || {
+        let mut db_batch = SchemaBatch::new();
+        let progress = pruner.prune(&mut db_batch, num_txns).unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+        pruner.on_committed();
+        progress.version
+    };
+
+    // The lowest of the floors held is what the pruner stops at.
+    let guard = read_floors.register(5);
+    let lowest_guard = read_floors.register(3);
+    pruner.set_target_version(8);
+    assert_eq!(prune(), 3);
+    assert!(!pruner.is_pruning_pending());
+    assert!(transaction_store.get_transaction(3).is_ok());
+
+    drop(lowest_guard);
+    assert_eq!(prune(), 5);
+    assert!(transaction_store.get_transaction(5).is_ok());
+
+    // Without any floor held, the target is reached.
+    drop(guard);
+    assert_eq!(read_floors.min_floor(), None);
+    assert_eq!(prune(), 8);
+    assert!(transaction_store.get_transaction(7).is_err());
+}
+
+#[test]
+fn test_write_set_pruner_checkpoints() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let checkpoints = Arc::new(CheckpointRegistry::default());
+    let pruner = WriteSetPruner::new(Arc::clone(transaction_store))
+        .with_checkpoints(Arc::clone(&checkpoints));
+    let mut cs = ChangeSet::new();
+    for ver in 0..10 {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let prune = |least_readable_version, target_version| {
+        let mut db_batch = SchemaBatch::new();
+        pruner
+            .prune(&mut db_batch, least_readable_version, target_version)
+            .unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+    };
+    let write_sets_left = || -> Vec<Version> {
+        (0..10)
+            .filter(|ver| transaction_store.get_write_set(*ver).is_ok())
+            .collect()
+    };
+
+    // The write sets from the oldest checkpoint on are kept, however far the ledger goes.
+    checkpoints.register(6);
+    checkpoints.register(4);
+    prune(0, 6);
+    assert_eq!(pruner.write_set_floor(), Some(4));
+    assert_eq!(write_sets_left(), (4..10).collect::<Vec<_>>());
+    prune(6, 8);
+    assert_eq!(pruner.write_set_floor(), Some(4));
+    assert_eq!(write_sets_left(), (4..10).collect::<Vec<_>>());
+
+    // Once the checkpoint is gone, the write sets held back are caught up with, up to the next
+    // one.
+    assert!(checkpoints.unregister(4));
+    assert!(!checkpoints.unregister(4));
+    prune(8, 9);
+    assert_eq!(pruner.write_set_floor(), Some(6));
+    assert_eq!(write_sets_left(), (6..10).collect::<Vec<_>>());
+
+    assert!(checkpoints.unregister(6));
+    prune(9, 10);
+    assert_eq!(pruner.write_set_floor(), None);
+    assert!(write_sets_left().is_empty());
+}
+
+#[test]
+fn test_ledger_pruner_with_write_set_checkpoints() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let mut cs = ChangeSet::new();
+    for version in 0..10 {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+        transaction_store
+            .put_write_set(version, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let checkpoints = Arc::new(CheckpointRegistry::default());
+    checkpoints.register(3);
+    let pruner = create_ledger_pruner(&aptos_db).with_write_set_checkpoints(checkpoints);
+
+    // The rest of the ledger goes as usual, the write sets from the checkpoint on stay.
+    pruner.set_target_version(6);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    assert_eq!(pruner.least_readable_version(), 6);
+    for version in 0..6 {
+        assert!(transaction_store.get_transaction(version).is_err());
+        assert_eq!(
+            transaction_store.get_write_set(version).is_ok(),
+            version >= 3
+        );
+    }
+}
+
+#[test]
+fn test_write_set_pruner_state_sync_reservation() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let reservation = Arc::new(StateSyncReservation::default());
+    let pruner = WriteSetPruner::new(Arc::clone(transaction_store))
+        .with_state_sync_reservation(Arc::clone(&reservation));
+    let mut cs = ChangeSet::new();
+    for ver in 0..10 {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let prune = |least_readable_version, target_version| {
+        let mut db_batch = SchemaBatch::new();
+        pruner
+            .prune(&mut db_batch, least_readable_version, target_version)
+            .unwrap();
+        aptos_db.db.write_schemas(db_batch).unwrap();
+    };
+    let write_sets_left = || -> Vec<Version> {
+        (0..10)
+            .filter(|ver| transaction_store.get_write_set(*ver).is_ok())
+            .collect()
+    };
+
+    // A chunk being served holds the write sets from its start on.
+    reservation.add(3, 6);
+    reservation.add(5, 8);
+    assert_eq!(reservation.lowest_reserved_version(), Some(3));
+    prune(0, 7);
+    assert_eq!(pruner.write_set_floor(), Some(3));
+    assert_eq!(write_sets_left(), (3..10).collect::<Vec<_>>());
+
+    // Once served, the write sets held back are caught up with, up to the next chunk.
+    assert!(reservation.remove(3, 6));
+    assert!(!reservation.remove(3, 6));
+    prune(7, 8);
+    assert_eq!(pruner.write_set_floor(), Some(5));
+    assert_eq!(write_sets_left(), (5..10).collect::<Vec<_>>());
+
+    assert!(reservation.remove(5, 8));
+    assert_eq!(reservation.lowest_reserved_version(), None);
+    prune(8, 9);
+    assert_eq!(pruner.write_set_floor(), None);
+    assert_eq!(write_sets_left(), vec![9]);
+}
+
+#[test]
+fn test_write_set_pruner_archive_db() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let archive_tmp_dir = TempPath::new();
+    let archive_db = AptosDB::new_for_test(&archive_tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let pruner = create_ledger_pruner(&aptos_db).with_archive_db(Arc::clone(&archive_db.db));
+    let mut cs = ChangeSet::new();
+    for ver in 0..10 {
+        transaction_store
+            .put_write_set(ver, &WriteSet::default(), &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+
+    pruner.set_target_version(6);
+    let mut db_batch = SchemaBatch::new();
+    pruner.prune(&mut db_batch, 100).unwrap();
+    aptos_db.db.write_schemas(db_batch).unwrap();
+    pruner.on_committed();
+    for ver in 0..10 {
+        assert_eq!(transaction_store.get_write_set(ver).is_ok(), ver >= 6);
+        assert_eq!(
+            archive_db.transaction_store.get_write_set(ver).is_ok(),
+            ver < 6
+        );
+    }
+}
+
+#[test]
+fn test_ledger_pruner_progress_gates_reads() {
+    let tmp_dir = TempPath::new();
+    let aptos_db = AptosDB::new_for_test(&tmp_dir);
+    let transaction_store = &aptos_db.transaction_store;
+    let num_txns = 200;
+    let mut cs = ChangeSet::new();
+    for version in 0..num_txns {
+        transaction_store
+            .put_transaction(version, &Transaction::StateCheckpoint, &mut cs)
+            .unwrap();
+    }
+    aptos_db.db.write_schemas(cs.batch).unwrap();
+    let pruner = Arc::new(create_ledger_pruner(&aptos_db));
+
+    // One transaction a round is pruned, each committed like the pruner worker does.
+    pruner.set_target_version(num_txns);
+    let writer = {
+        let pruner = Arc::clone(&pruner);
+        let db = Arc::clone(&aptos_db.db);
+        thread::spawn(move || {
+            while pruner.is_pruning_pending() {
+                let mut db_batch = SchemaBatch::new();
+                pruner.prune(&mut db_batch, 1).unwrap();
+                db.write_schemas(db_batch).unwrap();
+                pruner.on_committed();
+            }
+        })
+    };
+    // Whichever version is seen recorded, the transaction right below it is seen deleted.
+    loop {
+        let least_readable_version = pruner.least_readable_version();
+        if least_readable_version > 0 {
+            assert!(transaction_store
+                .get_transaction(least_readable_version - 1)
+                .is_err());
+        }
+        if least_readable_version == num_txns {
+            break;
+        }
+    }
+    writer.join().unwrap();
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 use crate::{pruner::db_sub_pruner::DBSubPruner, TransactionStore};
+use aptos_logger::warn;
 use aptos_types::transaction::{Transaction, Version};
 use schemadb::SchemaBatch;
 use std::sync::Arc;
@@ -49,8 +50,19 @@ impl TransactionStorePruner {
         start: Version,
         end: Version,
     ) -> anyhow::Result<Vec<Transaction>> {
+        let num_versions = match end.checked_sub(start) {
+            Some(num_versions) => num_versions,
+            None => {
+                warn!(
+                    start = start,
+                    end = end,
+                    "Transaction pruning range ends before it starts, skipped."
+                );
+                return Ok(vec![]);
+            }
+        };
         self.transaction_store
-            .get_transaction_iter(start, (end - start) as usize)?
+            .get_transaction_iter(start, num_versions as usize)?
             .collect()
     }
 }
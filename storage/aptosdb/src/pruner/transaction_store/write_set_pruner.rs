@@ -1,11 +1,29 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
-use crate::{pruner::db_sub_pruner::DBSubPruner, TransactionStore};
+use crate::{
+    pruner::{
+        checkpoint_registry::CheckpointRegistry, db_sub_pruner::DBSubPruner,
+        state_sync_reservation::StateSyncReservation,
+    },
+    TransactionStore,
+};
+use aptos_infallible::Mutex;
+use aptos_types::transaction::Version;
 use schemadb::SchemaBatch;
-use std::sync::Arc;
+use std::{
+    cmp::{max, min},
+    sync::Arc,
+};
 
 pub struct WriteSetPruner {
     transaction_store: Arc<TransactionStore>,
+    /// If set, holds the checkpoint versions the write sets are kept from.
+    checkpoints: Option<Arc<CheckpointRegistry>>,
+    /// Where the write sets are left unpruned from since a checkpoint or a reservation held them
+    /// back, to be caught up with once it's gone.
+    pending_start: Mutex<Option<Version>>,
+    /// If set, holds the ranges of versions state sync is serving the write sets are kept from.
+    state_sync_reservation: Option<Arc<StateSyncReservation>>,
 }
 
 impl DBSubPruner for WriteSetPruner {
@@ -15,14 +33,87 @@ impl DBSubPruner for WriteSetPruner {
         least_readable_version: u64,
         target_version: u64,
     ) -> anyhow::Result<()> {
-        self.transaction_store
-            .prune_write_set(least_readable_version, target_version, db_batch)?;
+        let mut pending_start = self.pending_start.lock();
+        let start = pending_start.map_or(least_readable_version, |pending| {
+            min(pending, least_readable_version)
+        });
+        let current_target_version = self
+            .checkpoints
+            .as_ref()
+            .and_then(|checkpoints| checkpoints.oldest_checkpoint())
+            .into_iter()
+            .chain(
+                self.state_sync_reservation
+                    .as_ref()
+                    .and_then(|reservation| reservation.lowest_reserved_version()),
+            )
+            .fold(target_version, min);
+        if start < current_target_version {
+            self.transaction_store
+                .prune_write_set(start, current_target_version, db_batch)?;
+        }
+        *pending_start = if current_target_version < target_version {
+            Some(max(start, current_target_version))
+        } else {
+            None
+        };
         Ok(())
     }
+
+    fn archive(
+        &self,
+        archive_batch: &mut SchemaBatch,
+        least_readable_version: Version,
+        target_version: Version,
+    ) -> anyhow::Result<()> {
+        self.transaction_store.copy_write_sets(
+            least_readable_version,
+            target_version,
+            archive_batch,
+        )
+    }
 }
 
 impl WriteSetPruner {
     pub(in crate::pruner) fn new(transaction_store: Arc<TransactionStore>) -> Self {
-        WriteSetPruner { transaction_store }
+        WriteSetPruner {
+            transaction_store,
+            checkpoints: None,
+            pending_start: Mutex::new(None),
+            state_sync_reservation: None,
+        }
+    }
+
+    /// Keeps the write sets from the oldest version registered in `checkpoints` on, while the
+    /// rest of the ledger is pruned as usual. The write sets held back are pruned in the first
+    /// round after the checkpoint is gone. What's held back is only kept in memory, so a restart
+    /// in the meantime leaves it behind for good.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn with_checkpoints(
+        mut self,
+        checkpoints: Arc<CheckpointRegistry>,
+    ) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Keeps the write sets from the lowest version reserved in `state_sync_reservation` on, so
+    /// that the chunks state sync is serving don't fail midway, while the rest of the ledger is
+    /// pruned as usual. Like for checkpoints, the write sets held back are pruned in the first
+    /// round after the reservation is removed, unless restarted in the meantime.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn with_state_sync_reservation(
+        mut self,
+        state_sync_reservation: Arc<StateSyncReservation>,
+    ) -> Self {
+        self.state_sync_reservation = Some(state_sync_reservation);
+        self
+    }
+
+    /// Returns the first version whose write set is held back by a checkpoint or a state sync
+    /// reservation, `None` if the write sets are pruned as far as the rest of the ledger.
+    #[allow(dead_code)]
+    pub(in crate::pruner) fn write_set_floor(&self) -> Option<Version> {
+        *self.pending_start.lock()
     }
 }
@@ -5,33 +5,156 @@
 
 use crate::{
     pruner::{
-        db_pruner::DBPruner, ledger_store::ledger_store_pruner::LedgerPruner,
+        clock::SystemClock,
+        db_pruner::DBPruner,
+        event_store::{audit_sink::LogAuditSink, event_store_pruner::EventStorePruner},
+        ledger_store::ledger_store_pruner::LedgerPruner,
+        prune_strategy::FixedBatchStrategy,
+        rate_limiter::RateLimiter,
         state_store::StateStorePruner,
+        write_retries::DEFAULT_BASE_BACKOFF,
     },
     EventStore, LedgerStore, TransactionStore,
 };
-use aptos_infallible::Mutex;
+use aptos_config::config::StoragePrunerConfig;
+use aptos_types::transaction::Version;
+use itertools::zip_eq;
 use schemadb::DB;
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
 
-/// A useful utility function to instantiate all db pruners.
+/// A useful utility function to instantiate all db pruners. Unless they are meant to delete
+/// genesis, i.e. `never_prune_genesis` is unset, they keep it, see
+/// `DBPruner::never_prune_genesis()`.
 pub fn create_db_pruners(
     db: Arc<DB>,
     transaction_store: Arc<TransactionStore>,
     ledger_store: Arc<LedgerStore>,
     event_store: Arc<EventStore>,
-) -> Vec<Mutex<Arc<dyn DBPruner + Send + Sync>>> {
-    vec![
-        Mutex::new(Arc::new(StateStorePruner::new(
-            Arc::clone(&db),
-            0,
-            Instant::now(),
-        ))),
-        Mutex::new(Arc::new(LedgerPruner::new(
-            Arc::clone(&db),
-            Arc::clone(&transaction_store),
-            Arc::clone(&event_store),
-            Arc::clone(&ledger_store),
-        ))),
-    ]
+    stop_flag: Arc<AtomicBool>,
+    storage_pruner_config: StoragePrunerConfig,
+    archive_db: Option<Arc<DB>>,
+    never_prune_genesis: bool,
+) -> Vec<Arc<dyn DBPruner + Send + Sync>> {
+    // The pruner targets are sent with the prune windows already applied.
+    let mut state_store_pruner = StateStorePruner::new(
+        Arc::clone(&db),
+        0,
+        Instant::now(),
+        0, /* prune_window */
+        Arc::new(FixedBatchStrategy),
+    );
+    let mut ledger_pruner = LedgerPruner::new(
+        Arc::clone(&db),
+        Arc::clone(&transaction_store),
+        Arc::clone(&event_store),
+        Arc::clone(&ledger_store),
+        stop_flag,
+        0, /* prune_window */
+    );
+    // The rate limit is shared, capping what the pruners write altogether.
+    if let Some(bytes_per_sec) = storage_pruner_config.pruning_rate_limit_bytes_per_sec {
+        let rate_limiter = Arc::new(RateLimiter::new(bytes_per_sec, Arc::new(SystemClock)));
+        state_store_pruner = state_store_pruner.with_rate_limiter(Arc::clone(&rate_limiter));
+        ledger_pruner = ledger_pruner.with_rate_limiter(rate_limiter);
+    }
+    if never_prune_genesis {
+        state_store_pruner = state_store_pruner.with_genesis_kept();
+        ledger_pruner = ledger_pruner.with_genesis_kept();
+    }
+    if let Some(archive_db) = archive_db {
+        ledger_pruner = ledger_pruner.with_archive_db(archive_db);
+    }
+    let mut event_store_pruner = EventStorePruner::new(Arc::clone(&event_store));
+    if storage_pruner_config.parallel_event_pruning {
+        event_store_pruner = event_store_pruner.with_parallel_sub_prunes();
+    }
+    if storage_pruner_config.keep_latest_event_per_key {
+        event_store_pruner = event_store_pruner.with_latest_event_per_key_kept();
+    }
+    if storage_pruner_config.isolate_event_accumulator_failures {
+        event_store_pruner = event_store_pruner.with_accumulator_failure_isolation();
+    }
+    if storage_pruner_config.log_pruned_events {
+        event_store_pruner = event_store_pruner.with_audit_sink(Arc::new(LogAuditSink));
+    }
+    ledger_pruner = ledger_pruner.with_event_store_pruner(Arc::new(event_store_pruner));
+    if let Some(commit_threshold) = storage_pruner_config.ledger_pruning_commit_threshold {
+        ledger_pruner = ledger_pruner.with_commit_threshold(commit_threshold);
+    }
+    if storage_pruner_config.prune_transaction_accumulator {
+        ledger_pruner = ledger_pruner.with_transaction_accumulator_pruning();
+    }
+    if storage_pruner_config.epoch_aligned_ledger_pruning {
+        ledger_pruner.set_epoch_aligned(true);
+    }
+    if let Some(max_retries) = storage_pruner_config.pruning_write_retries {
+        state_store_pruner =
+            state_store_pruner.with_write_retries(max_retries, DEFAULT_BASE_BACKOFF);
+        ledger_pruner = ledger_pruner.with_write_retries(max_retries, DEFAULT_BASE_BACKOFF);
+    }
+    if let Some(threshold_ms) = storage_pruner_config.pruning_slow_batch_threshold_ms {
+        let slow_batch_threshold = Duration::from_millis(threshold_ms);
+        state_store_pruner = state_store_pruner.with_slow_batch_threshold(slow_batch_threshold);
+        ledger_pruner = ledger_pruner.with_slow_batch_threshold(slow_batch_threshold);
+    }
+    if storage_pruner_config.pruning_min_interval_ms > 0 {
+        ledger_pruner = ledger_pruner.with_min_prune_interval(Duration::from_millis(
+            storage_pruner_config.pruning_min_interval_ms,
+        ));
+    }
+    // In the order of `PrunerIndex`.
+    vec![Arc::new(state_store_pruner), Arc::new(ledger_pruner)]
+}
+
+/// Returns the least of the least readable versions of a set of pruners, 0 if there is none,
+/// which is what reads spanning the stores, e.g. a transaction along with its events and write
+/// set, are to be gated by rather than by the floor of any one pruner. Since each least readable
+/// version only ever grows, so does the combined one, as long as those of the same pruners are
+/// given.
+pub fn combined_least_readable_version(least_readable_versions: &[Version]) -> Version {
+    least_readable_versions.iter().min().copied().unwrap_or(0)
+}
+
+/// Tells which of a set of pruners are starving, i.e. unable to keep up with the versions
+/// committed, as the versions they have left to prune grew in each of the last
+/// `starvation_rounds` rounds.
+pub struct StarvationTracker {
+    starvation_rounds: usize,
+    /// For each pruner, the versions it had left after the last round and the number of rounds
+    /// in a row they grew.
+    remaining_versions: Vec<(Version, usize)>,
+}
+
+impl StarvationTracker {
+    /// Starts from the versions each pruner has left to prune before the first round.
+    pub fn new(remaining_versions: Vec<Version>, starvation_rounds: usize) -> Self {
+        Self {
+            starvation_rounds,
+            remaining_versions: remaining_versions
+                .into_iter()
+                .map(|remaining_versions| (remaining_versions, 0))
+                .collect(),
+        }
+    }
+
+    /// Takes the versions each pruner, in the same order as given to `new()`, has left to prune
+    /// after a round, and tells for each whether it's starving.
+    pub fn track(&mut self, remaining_versions: &[Version]) -> Vec<bool> {
+        zip_eq(&mut self.remaining_versions, remaining_versions)
+            .map(
+                |((last_remaining_versions, rounds_growing), remaining_versions)| {
+                    if *remaining_versions > *last_remaining_versions {
+                        *rounds_growing += 1;
+                    } else {
+                        *rounds_growing = 0;
+                    }
+                    *last_remaining_versions = *remaining_versions;
+                    *rounds_growing >= self.starvation_rounds
+                },
+            )
+            .collect()
+    }
 }
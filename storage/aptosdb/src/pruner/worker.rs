@@ -4,17 +4,44 @@ use aptos_types::transaction::Version;
 use schemadb::{SchemaBatch, DB};
 
 use crate::{
-    pruner::{db_pruner::DBPruner, utils},
+    metrics::{PRUNER_COMBINED_FLOOR, PRUNER_LAG_SECONDS, PRUNER_STARVING},
+    pruner::{
+        db_pruner::{DBPruner, PrunerError},
+        prune_schedule::PruneSchedule,
+        utils::{self, StarvationTracker},
+    },
     EventStore, LedgerStore, TransactionStore,
 };
-use aptos_infallible::Mutex;
+use aptos_config::config::StoragePrunerConfig;
+use aptos_infallible::{duration_since_epoch, Mutex};
+use aptos_logger::warn;
 use itertools::zip_eq;
-use std::sync::{mpsc::Receiver, Arc};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::Span;
+
+/// How often the lag of the oldest version left in the DB is refreshed.
+const OLDEST_LIVE_VERSION_LAG_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the block timestamps the lags are measured from are looked up again.
+const LAG_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of rounds in a row the versions left to a pruner have to grow for it to be flagged as
+/// starving.
+const STARVATION_ROUNDS: usize = 10;
 
 /// Maintains all the DBPruners and periodically calls the db_pruner's prune method to prune the DB.
 /// This also exposes API to report the progress to the parent thread.
 pub struct Worker {
     db: Arc<DB>,
+    /// For looking up how old the least readable versions are.
+    transaction_store: Arc<TransactionStore>,
     command_receiver: Receiver<Command>,
     /// Keeps tracks of all the DB pruners
     db_pruners: Vec<Mutex<Arc<dyn DBPruner + Send + Sync>>>,
@@ -26,6 +53,21 @@ pub struct Worker {
     /// `Self::receive_commands()` to `recv()` blocking-ly.
     blocking_recv: bool,
     max_version_to_prune_per_batch: u64,
+    /// When the lag of the oldest version left in the DB was last refreshed.
+    oldest_live_version_lag_refreshed_at: Option<Instant>,
+    /// For each pruner, the timestamp of the block its least readable version was last found in,
+    /// see `record_lag()`.
+    lag_block_timestamps: Vec<Option<u64>>,
+    /// When the block timestamps the lags are measured from were last looked up.
+    lag_refreshed_at: Option<Instant>,
+    /// Flags the pruners falling further behind their targets round after round.
+    starvation_tracker: StarvationTracker,
+    /// If set, rounds only run within its windows.
+    schedule: Option<PruneSchedule>,
+    /// While set, no round is run, see `Pruner::pause()`.
+    paused: Arc<AtomicBool>,
+    /// If set, the pruners are warmed up on start, see `warm_up()`.
+    warm_up_on_start: bool,
 }
 
 impl Worker {
@@ -36,34 +78,124 @@ impl Worker {
         event_store: Arc<EventStore>,
         command_receiver: Receiver<Command>,
         least_readable_versions: Arc<Mutex<Vec<Version>>>,
-        max_version_to_prune_per_batch: u64,
+        storage_pruner_config: StoragePrunerConfig,
+        stop_flag: Arc<AtomicBool>,
+        paused: Arc<AtomicBool>,
+        archive_db: Option<Arc<DB>>,
     ) -> Self {
-        let db_pruners =
-            utils::create_db_pruners(db.clone(), transaction_store, ledger_store, event_store);
+        let never_prune_genesis = storage_pruner_config.never_prune_genesis;
+        let db_pruners = utils::create_db_pruners(
+            db.clone(),
+            Arc::clone(&transaction_store),
+            ledger_store,
+            event_store,
+            stop_flag,
+            storage_pruner_config,
+            archive_db,
+            never_prune_genesis,
+        );
+        let starvation_tracker = StarvationTracker::new(
+            db_pruners
+                .iter()
+                .map(|db_pruner| db_pruner.estimated_remaining_versions())
+                .collect(),
+            STARVATION_ROUNDS,
+        );
+        let num_pruners = db_pruners.len();
+        let db_pruners = db_pruners.into_iter().map(Mutex::new).collect();
+        let schedule =
+            storage_pruner_config
+                .pruning_window_utc_hours
+                .and_then(|(start_hour, end_hour)| {
+                    match PruneSchedule::from_utc_hours(start_hour, end_hour) {
+                        Ok(schedule) => Some(schedule),
+                        Err(e) => {
+                            warn!(error = ?e, "Invalid pruning window, pruning at any time.");
+                            None
+                        }
+                    }
+                });
         Self {
             db: Arc::clone(&db),
+            transaction_store,
             db_pruners,
             command_receiver,
             least_readable_versions,
             blocking_recv: true,
-            max_version_to_prune_per_batch,
+            max_version_to_prune_per_batch: storage_pruner_config.pruning_batch_size as u64,
+            oldest_live_version_lag_refreshed_at: None,
+            lag_block_timestamps: vec![None; num_pruners],
+            lag_refreshed_at: None,
+            starvation_tracker,
+            schedule,
+            paused,
+            warm_up_on_start: storage_pruner_config.warm_up_pruners,
         }
     }
 
     pub(crate) fn work(mut self) {
+        // Fills the block cache for the first round of each pruner before waiting for the first
+        // command, see `DBPruner::warmup()`.
+        if self.warm_up_on_start {
+            self.warm_up();
+        }
         while self.receive_commands() {
+            if self.paused.load(Ordering::Relaxed) {
+                // Waits for the next command rather than spinning, as `Pruner::resume()` sends
+                // one.
+                self.blocking_recv = true;
+                continue;
+            }
             // Process a reasonably small batch of work before trying to receive commands again,
             // in case `Command::Quit` is received (that's when we should quit.)
             let mut error_in_pruning = false;
             let mut db_batch = SchemaBatch::new();
+            let mut pruned_ranges = Vec::with_capacity(self.db_pruners.len());
             for db_pruner in &self.db_pruners {
+                let begin = db_pruner.lock().least_readable_version();
+                // Gathered apart, so the deletions of a failed round are left out of the commit.
+                let mut pruner_batch = SchemaBatch::new();
                 let result = db_pruner
                     .lock()
-                    .prune(&mut db_batch, self.max_version_to_prune_per_batch);
-                result.map_err(|_| error_in_pruning = true).ok();
+                    .prune(&mut pruner_batch, self.max_version_to_prune_per_batch);
+                // An interrupted round still made progress, which gets committed below.
+                pruned_ranges.push(match result {
+                    Ok(progress) | Err(PrunerError::Interrupted(progress)) => {
+                        db_batch.merge(pruner_batch);
+                        Some((begin, progress.version))
+                    }
+                    Err(_) => {
+                        error_in_pruning = true;
+                        db_pruner.lock().on_discarded();
+                        None
+                    }
+                });
+            }
+            // Commit all the changes to DB atomically, unless none of the pruners had any work.
+            let result = if db_batch.is_empty() {
+                Ok(())
+            } else {
+                // The write is part of the rounds it commits.
+                let round_spans: Vec<_> = self
+                    .db_pruners
+                    .iter()
+                    .map(|db_pruner| db_pruner.lock().round_span())
+                    .collect();
+                let _entered: Vec<_> = round_spans.iter().map(Span::enter).collect();
+                self.db.write_schemas(db_batch)
+            };
+            for (db_pruner, pruned_range) in zip_eq(&self.db_pruners, pruned_ranges) {
+                if let Some((begin, end)) = pruned_range {
+                    if result.is_err() {
+                        db_pruner.lock().on_discarded();
+                        continue;
+                    }
+                    db_pruner.lock().on_committed();
+                    if let Err(e) = db_pruner.lock().compact_pruned_range(begin, end) {
+                        warn!(error = ?e, "Failed compacting pruned range, ignored.");
+                    }
+                }
             }
-            // Commit all the changes to DB atomically
-            let result = self.db.write_schemas(db_batch);
             result.map_err(|_| error_in_pruning = true).ok();
             let mut pruning_pending = false;
             for db_pruner in &self.db_pruners {
@@ -77,8 +209,54 @@ impl Worker {
             } else {
                 self.blocking_recv = false;
             }
+            self.track_starvation();
             self.record_progress();
         }
+        self.flush_progress();
+    }
+
+    /// Warms up each pruner for its first round, a failure only leaving the round to read cold.
+    fn warm_up(&self) {
+        for db_pruner in &self.db_pruners {
+            let db_pruner = db_pruner.lock();
+            if let Err(e) = db_pruner.warmup(self.max_version_to_prune_per_batch) {
+                warn!(
+                    error = ?e,
+                    "Failed warming up {}, ignored.",
+                    db_pruner.name()
+                );
+            }
+        }
+    }
+
+    /// Persists the progress of each pruner, on quitting.
+    fn flush_progress(&self) {
+        for db_pruner in &self.db_pruners {
+            let db_pruner = db_pruner.lock();
+            if let Err(e) = db_pruner.flush_progress() {
+                warn!(
+                    error = ?e,
+                    "Failed flushing {} progress, ignored.",
+                    db_pruner.name()
+                );
+            }
+        }
+    }
+
+    /// Flags the pruners whose versions left to prune grew in each of the last
+    /// `STARVATION_ROUNDS` rounds as starving.
+    fn track_starvation(&mut self) {
+        let remaining_versions: Vec<_> = self
+            .db_pruners
+            .iter()
+            .map(|db_pruner| db_pruner.lock().estimated_remaining_versions())
+            .collect();
+        let starving = self.starvation_tracker.track(&remaining_versions);
+        for (db_pruner, starving) in zip_eq(&self.db_pruners, starving) {
+            PRUNER_STARVING
+                .with_label_values(&[db_pruner.lock().kind().metric_label()])
+                .set(starving as i64);
+        }
     }
 
     fn record_progress(&mut self) {
@@ -86,12 +264,83 @@ impl Worker {
         for x in &self.db_pruners {
             updated_least_readable_versions.push(x.lock().least_readable_version())
         }
+        PRUNER_COMBINED_FLOOR
+            .set(utils::combined_least_readable_version(&updated_least_readable_versions) as i64);
+        self.record_lag(&updated_least_readable_versions);
+        for db_pruner in &self.db_pruners {
+            db_pruner.lock().record_last_error_age();
+        }
+        self.refresh_oldest_live_version_lag();
         *self.least_readable_versions.lock() = updated_least_readable_versions;
     }
 
+    /// Refreshes the lag of the oldest version left in the DB of each pruner, at most once every
+    /// `OLDEST_LIVE_VERSION_LAG_REFRESH_INTERVAL`.
+    fn refresh_oldest_live_version_lag(&mut self) {
+        if self
+            .oldest_live_version_lag_refreshed_at
+            .map_or(false, |refreshed_at| {
+                refreshed_at.elapsed() < OLDEST_LIVE_VERSION_LAG_REFRESH_INTERVAL
+            })
+        {
+            return;
+        }
+        self.oldest_live_version_lag_refreshed_at = Some(Instant::now());
+        for db_pruner in &self.db_pruners {
+            let db_pruner = db_pruner.lock();
+            if let Err(e) = db_pruner.record_oldest_live_version_lag() {
+                warn!(
+                    error = ?e,
+                    "Failed refreshing {} oldest live version lag, ignored.",
+                    db_pruner.name()
+                );
+            }
+        }
+    }
+
+    /// Sets the lag of each pruner to the age of the block its least readable version is in,
+    /// estimated by the next block start if that of its own is pruned. Finding the block takes a
+    /// scan, so it's only looked up again every `LAG_REFRESH_INTERVAL`, the lag overestimating by
+    /// the progress made in between. The lag is left as it was if the block can't be found.
+    fn record_lag(&mut self, least_readable_versions: &[Version]) {
+        if self.lag_refreshed_at.map_or(true, |refreshed_at| {
+            refreshed_at.elapsed() >= LAG_REFRESH_INTERVAL
+        }) {
+            self.lag_refreshed_at = Some(Instant::now());
+            for (lag_block_timestamp, least_readable_version) in
+                zip_eq(&mut self.lag_block_timestamps, least_readable_versions)
+            {
+                if let Ok(Some(timestamp_usecs)) = self
+                    .transaction_store
+                    .get_next_block_timestamp(*least_readable_version)
+                {
+                    *lag_block_timestamp = Some(timestamp_usecs);
+                }
+            }
+        }
+        let now_usecs = duration_since_epoch().as_micros() as u64;
+        for (db_pruner, lag_block_timestamp) in zip_eq(&self.db_pruners, &self.lag_block_timestamps)
+        {
+            if let Some(timestamp_usecs) = lag_block_timestamp {
+                PRUNER_LAG_SECONDS
+                    .with_label_values(&[db_pruner.lock().kind().metric_label()])
+                    .set((now_usecs.saturating_sub(*timestamp_usecs) / 1_000_000) as i64);
+            }
+        }
+    }
+
+    /// Returns the time until the schedule opens, `None` if it's open or there is none.
+    fn time_until_schedule_opens(&self) -> Option<Duration> {
+        self.schedule
+            .as_ref()
+            .map(PruneSchedule::time_until_open)
+            .filter(|time_until_open| *time_until_open > Duration::ZERO)
+    }
+
     /// Tries to receive all pending commands, blocking waits for the next command if no work needs
     /// to be done, otherwise quits with `true` to allow the outer loop to do some work before
-    /// getting back here.
+    /// getting back here. With work to do outside of the schedule, waits for the next command
+    /// until it opens instead.
     ///
     /// Returns `false` if `Command::Quit` is received, to break the outer loop and let
     /// `work_loop()` return.
@@ -102,6 +351,16 @@ impl Worker {
                 self.command_receiver
                     .recv()
                     .expect("Sender should not destruct prematurely.")
+            } else if let Some(time_until_open) = self.time_until_schedule_opens() {
+                // Worker has pending work to do but not before the schedule opens, waits for it
+                // while still taking commands, `Command::Quit` in particular.
+                match self.command_receiver.recv_timeout(time_until_open) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => return true,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        panic!("Sender should not destruct prematurely.")
+                    }
+                }
             } else {
                 // Worker has pending work to do, non-blocking recv.
                 match self.command_receiver.try_recv() {
@@ -0,0 +1,50 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_logger::warn;
+use schemadb::is_transient_error;
+use std::{thread::sleep, time::Duration};
+
+/// What the first retry of a write backs off for, unless told otherwise.
+pub const DEFAULT_BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Retries a write the DB failed for a reason which may go away, e.g. an I/O error, backing off
+/// exponentially in between. Since a pruning batch is fully built before it's written and
+/// deleting the same keys again is harmless, writing it again as is is always safe.
+pub struct WriteRetries {
+    max_retries: u32,
+    base_backoff: Duration,
+}
+
+impl WriteRetries {
+    pub fn new(max_retries: u32, base_backoff: Duration) -> Self {
+        Self {
+            max_retries,
+            base_backoff,
+        }
+    }
+
+    /// Runs `write` until it succeeds, up to `max_retries` times more if it keeps failing with a
+    /// transient error, sleeping `base_backoff` before the first retry and twice as long as the
+    /// previous time before each next one. Any other error is returned right away.
+    pub fn run(&self, mut write: impl FnMut() -> anyhow::Result<()>) -> anyhow::Result<()> {
+        let mut backoff = self.base_backoff;
+        let mut retries = 0;
+        loop {
+            match write() {
+                Err(e) if retries < self.max_retries && is_transient_error(&e) => {
+                    retries += 1;
+                    warn!(
+                        error = ?e,
+                        retries = retries,
+                        backoff_ms = backoff.as_millis() as u64,
+                        "Pruner failed writing to DB, retrying.",
+                    );
+                    sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
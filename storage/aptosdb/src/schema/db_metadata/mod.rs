@@ -0,0 +1,80 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines physical storage schema for miscellaneous DB metadata, e.g. the progress
+//! of the pruners.
+//!
+//! ```text
+//! |<-----key----->|<------value------>|
+//! | metadata key  | metadata value    |
+//! ```
+//!
+//! Both are serialized with BCS, the key being an enum so that new kinds of metadata can be added.
+
+use super::DB_METADATA_CF_NAME;
+use anyhow::Result;
+use aptos_types::transaction::Version;
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+};
+use serde::{Deserialize, Serialize};
+
+/// Kinds of DB metadata.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum DbMetadataKey {
+    /// The least readable version of the ledger pruner.
+    LedgerPrunerProgress,
+    /// The least readable version of the state store pruner.
+    StateStorePrunerProgress,
+    /// The least readable version of the ledger info pruner.
+    LedgerInfoPrunerProgress,
+}
+
+/// Values of DB metadata.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum DbMetadataValue {
+    Version(Version),
+}
+
+impl DbMetadataValue {
+    pub fn expect_version(self) -> Version {
+        match self {
+            Self::Version(version) => version,
+        }
+    }
+}
+
+define_schema!(
+    DbMetadataSchema,
+    DbMetadataKey,
+    DbMetadataValue,
+    DB_METADATA_CF_NAME
+);
+
+impl KeyCodec<DbMetadataSchema> for DbMetadataKey {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        bcs::to_bytes(self).map_err(Into::into)
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        bcs::from_bytes(data).map_err(Into::into)
+    }
+}
+
+impl ValueCodec<DbMetadataSchema> for DbMetadataValue {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        bcs::to_bytes(self).map_err(Into::into)
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        bcs::from_bytes(data).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test;
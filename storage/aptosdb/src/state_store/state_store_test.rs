@@ -49,6 +49,9 @@ fn prune_stale_indices(
         least_readable_version,
         target_least_readable_version,
         limit,
+        None,  /* rate_limiter */
+        None,  /* write_retries */
+        false, /* never_prune_genesis */
     )
     .unwrap();
 }
@@ -3,7 +3,12 @@
 
 ///! This module provides reusable helpers in tests.
 use super::*;
-use crate::jellyfish_merkle_node::JellyfishMerkleNodeSchema;
+use crate::{
+    event::EventSchema,
+    event_store::PruneEventsOptions,
+    jellyfish_merkle_node::JellyfishMerkleNodeSchema,
+    pruner::{deletion_order::DeletionOrder, prune_read_options::PruneReadOptions},
+};
 use aptos_crypto::hash::{CryptoHash, EventAccumulatorHasher, TransactionAccumulatorHasher};
 use aptos_jellyfish_merkle::node_type::{Node, NodeKey};
 use aptos_temppath::TempPath;
@@ -13,8 +18,11 @@ use aptos_types::{
     proptest_types::{AccountInfoUniverse, BlockGen},
 };
 use executor_types::ProofReader;
+use move_deps::move_core_types::language_storage::TypeTag;
 use proptest::{collection::vec, prelude::*};
+use schemadb::ReadOptions;
 use scratchpad::SparseMerkleTree;
+use std::cmp::min;
 
 prop_compose! {
     /// This returns a [`proptest`](https://altsysrq.github.io/proptest-book/intro.html)
@@ -617,6 +625,127 @@ pub fn put_as_state_root(db: &AptosDB, version: Version, key: StateKey, value: S
     db.state_store.set_latest_version(version);
 }
 
+/// Puts `num_versions` versions of `events_per_version` events each, emitted under as many event
+/// keys, one per index within the version.
+pub fn put_events(db: &AptosDB, num_versions: Version, events_per_version: usize) {
+    let event_keys: Vec<_> = (0..events_per_version)
+        .map(|_| EventKey::new_from_address(&AccountAddress::random(), 0))
+        .collect();
+    let mut cs = ChangeSet::new();
+    for version in 0..num_versions {
+        let events: Vec<_> = event_keys
+            .iter()
+            .map(|event_key| ContractEvent::new(*event_key, version, TypeTag::Bool, vec![]))
+            .collect();
+        db.event_store
+            .put_events(version, &events, &mut cs)
+            .unwrap();
+    }
+    db.db.write_schemas(cs.batch).unwrap();
+}
+
+/// Puts a single event every `version_interval` versions out of the first `num_versions`.
+pub fn put_sparse_events(db: &AptosDB, num_versions: Version, version_interval: Version) {
+    let event_key = EventKey::new_from_address(&AccountAddress::random(), 0);
+    let mut cs = ChangeSet::new();
+    for (seq_num, version) in (0..num_versions)
+        .step_by(version_interval as usize)
+        .enumerate()
+    {
+        let event = ContractEvent::new(event_key, seq_num as u64, TypeTag::Bool, vec![]);
+        db.event_store
+            .put_events(version, &[event], &mut cs)
+            .unwrap();
+    }
+    db.db.write_schemas(cs.batch).unwrap();
+}
+
+/// Prunes the events in the range of versions [0, end) in rounds of `versions_per_round`, the
+/// way the pruner does, and returns the number of deletions. Nothing is written to the DB.
+pub fn prune_events_in_rounds(db: &AptosDB, end: Version, versions_per_round: Version) -> usize {
+    let mut num_write_ops = 0;
+    for start in (0..end).step_by(versions_per_round as usize) {
+        let mut db_batch = SchemaBatch::new();
+        db.event_store
+            .prune_events(start, min(start + versions_per_round, end), &mut db_batch)
+            .unwrap();
+        num_write_ops += db_batch.len();
+    }
+    num_write_ops
+}
+
+/// Returns the batch of deletions pruning the events in the range of versions [0, end), computed
+/// either in parallel or serially.
+pub fn prune_events(db: &AptosDB, end: Version, parallel: bool) -> SchemaBatch {
+    let options = PruneEventsOptions {
+        parallel,
+        ..PruneEventsOptions::default()
+    };
+    let mut db_batch = SchemaBatch::new();
+    db.event_store
+        .prune_events_with_options(0, end, &options, &mut db_batch)
+        .unwrap();
+    db_batch
+}
+
+/// Flushes the memtables to SST files, which reads go through the block cache for.
+pub fn flush_all(db: &AptosDB) {
+    db.db.flush_all().unwrap();
+}
+
+/// Returns the bytes the event schema currently holds in the block cache.
+pub fn event_block_cache_usage(db: &AptosDB) -> u64 {
+    db.db
+        .get_property(EVENT_CF_NAME, "rocksdb.block-cache-usage")
+        .unwrap()
+}
+
+/// Goes through the events the pruner considers for the range of versions [0, end), reading the
+/// DB either the way the pruner does by default or filling the block cache like a regular read,
+/// and returns the number of events.
+pub fn scan_pruning_candidates(db: &AptosDB, end: Version, fill_cache: bool) -> usize {
+    let prune_read_options = if fill_cache {
+        PruneReadOptions {
+            fill_cache: true,
+            ..PruneReadOptions::default()
+        }
+    } else {
+        PruneReadOptions::default()
+    };
+    let event_store =
+        EventStore::new(Arc::clone(&db.db)).with_prune_read_options(prune_read_options);
+    event_store
+        .pruning_candidates(0, end)
+        .unwrap()
+        .map(Result::unwrap)
+        .count()
+}
+
+/// Prunes the events of the range of versions [0, end) in one batch, deleted in interleaved
+/// chunks if `interleaved` or in a single range otherwise, and commits it.
+pub fn prune_event_schema(db: &AptosDB, end: Version, interleaved: bool) {
+    let deletion_order = if interleaved {
+        DeletionOrder::Interleaved
+    } else {
+        DeletionOrder::Ascending
+    };
+    let event_store = EventStore::new(Arc::clone(&db.db)).with_deletion_order(deletion_order);
+    let mut db_batch = SchemaBatch::new();
+    event_store
+        .prune_event_schema(0, end, &mut db_batch)
+        .unwrap();
+    db.db.write_schemas(db_batch).unwrap();
+}
+
+/// Seeks the first event left from version 0 on, going over the ones pruned, and returns its
+/// version, `None` if there is none.
+pub fn seek_first_event(db: &AptosDB) -> Option<Version> {
+    let mut iter = db.db.iter::<EventSchema>(ReadOptions::default()).unwrap();
+    iter.seek(&(0_u64, 0_u64)).unwrap();
+    iter.next()
+        .map(|res| res.map(|((version, _index), _event)| version).unwrap())
+}
+
 pub fn test_sync_transactions_impl(
     input: Vec<(Vec<TransactionToCommit>, LedgerInfoWithSignatures)>,
 ) {
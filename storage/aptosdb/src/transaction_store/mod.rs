@@ -150,6 +150,22 @@ impl TransactionStore {
         Err(AptosDbError::NotFound(format!("BlockMetadata preceding version {}", version)).into())
     }
 
+    /// Returns the timestamp of the first block starting at or after `version`, which is no earlier
+    /// than that of the block `version` is in and serves as an estimate of it where the block
+    /// start is pruned. Returns None if no block starts in the versions searched.
+    pub fn get_next_block_timestamp(&self, version: Version) -> Result<Option<u64>> {
+        const MAX_VERSIONS_TO_SEARCH: usize = 1000 * 100;
+
+        let mut iter = self.db.iter::<TransactionSchema>(Default::default())?;
+        iter.seek(&version)?;
+        for res in iter.take(MAX_VERSIONS_TO_SEARCH) {
+            if let (_, Transaction::BlockMetadata(block_meta)) = res? {
+                return Ok(Some(block_meta.timestamp_usecs()));
+            }
+        }
+        Ok(None)
+    }
+
     /// Save signed transaction at `version`
     pub fn put_transaction(
         &self,
@@ -223,6 +239,26 @@ impl TransactionStore {
         iter.next().map(|res| res.map(|(v, _)| v)).transpose()
     }
 
+    /// Copies the write sets left in the range of versions [begin, end) into `db_batch`, e.g. to
+    /// archive them into another DB before they are pruned.
+    pub fn copy_write_sets(
+        &self,
+        begin: Version,
+        end: Version,
+        db_batch: &mut SchemaBatch,
+    ) -> Result<()> {
+        let mut iter = self.db.iter::<WriteSetSchema>(ReadOptions::default())?;
+        iter.seek(&begin)?;
+        for item in iter {
+            let (version, write_set) = item?;
+            if version >= end {
+                break;
+            }
+            db_batch.put::<WriteSetSchema>(&version, &write_set)?;
+        }
+        Ok(())
+    }
+
     /// Save executed transaction vm output given `version`
     pub fn put_write_set(
         &self,
@@ -286,7 +322,12 @@ impl TransactionStore {
         Ok(())
     }
 
-    /// Prune the transaction schema store between a range of version in [begin, end)
+    /// Prune the write set store between a range of version in [begin, end)
+    ///
+    /// Write sets are only indexed by version, so there is nothing else to delete along with
+    /// them. `StateValueIndexSchema`, although keyed by the state keys written, is maintained by
+    /// the state store off the merkle tree leaves, and belongs to the state history kept by the
+    /// state store pruner rather than to the write sets.
     pub fn prune_write_set(
         &self,
         begin: Version,
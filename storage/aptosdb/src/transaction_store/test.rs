@@ -166,6 +166,41 @@ proptest! {
         }
     }
 
+    #[test]
+    fn test_get_next_block_timestamp(
+        txns in vec(
+            prop_oneof![
+                any::<BlockMetadata>().prop_map(Transaction::BlockMetadata),
+                any::<SignedTransaction>().prop_map(Transaction::UserTransaction),
+            ],
+            1..100,
+        )
+    ) {
+        let tmp_dir = TempPath::new();
+        let db = AptosDB::new_for_test(&tmp_dir);
+        let store = &db.transaction_store;
+
+        let mut cs = ChangeSet::new();
+        for (ver, txn) in txns.iter().enumerate() {
+            store
+                .put_transaction(ver as Version, txn, &mut cs)
+                .unwrap();
+        }
+        store.db.write_schemas(cs.batch).unwrap();
+
+        // Walking backwards, the last block seen is the next one.
+        let mut next_timestamp = None;
+        for (ver, txn) in txns.into_iter().enumerate().rev() {
+            if let Transaction::BlockMetadata(b) = txn {
+                next_timestamp = Some(b.timestamp_usecs());
+            }
+            prop_assert_eq!(
+                store.get_next_block_timestamp(ver as Version).unwrap(),
+                next_timestamp
+            );
+        }
+    }
+
     #[test]
     fn test_get_account_transaction_version_iter(
         universe in any_with::<AccountInfoUniverse>(5),
@@ -121,6 +121,82 @@ impl SchemaBatch {
             .push(WriteOp::DeletionRangeInclusive { begin, end });
         Ok(())
     }
+
+    /// Returns the number of write operations held in the batch, across all column families.
+    pub fn len(&self) -> usize {
+        self.rows.values().map(Vec::len).sum()
+    }
+
+    /// Returns the number of write operations held in the batch for each column family.
+    pub fn len_by_column_family(&self) -> HashMap<ColumnFamilyName, usize> {
+        self.rows
+            .iter()
+            .map(|(cf_name, write_ops)| (*cf_name, write_ops.len()))
+            .collect()
+    }
+
+    /// Returns the size of the keys and values held in the batch, which approximates how much the
+    /// batch writes to the DB, short of a few bytes of framing per write operation and of the end
+    /// keys inclusive range deletions delete again. See `DB::serialized_size()` for the actual
+    /// size.
+    pub fn size_in_bytes(&self) -> usize {
+        self.rows
+            .values()
+            .flatten()
+            .map(|write_op| match write_op {
+                WriteOp::Value { key, value } => key.len() + value.len(),
+                WriteOp::Deletion { key } => key.len(),
+                WriteOp::DeletionRange { begin, end }
+                | WriteOp::DeletionRangeInclusive { begin, end } => begin.len() + end.len(),
+            })
+            .sum()
+    }
+
+    /// Returns the keys of schema `S` the batch deletes one by one, in the order the deletions
+    /// were added. Range deletions are left out.
+    pub fn deleted_keys<S: Schema>(&self) -> Result<Vec<S::Key>> {
+        self.rows
+            .get(S::COLUMN_FAMILY_NAME)
+            .into_iter()
+            .flatten()
+            .filter_map(|write_op| match write_op {
+                WriteOp::Deletion { key } => Some(<S::Key as KeyCodec<S>>::decode_key(key)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns `true` if the batch holds no write operation.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Moves all the write operations of `other` to the end of this batch, keeping their order.
+    pub fn merge(&mut self, other: SchemaBatch) {
+        for (cf_name, write_ops) in other.rows {
+            self.rows
+                .entry(cf_name)
+                .or_insert_with(Vec::new)
+                .extend(write_ops);
+        }
+    }
+}
+
+/// Tells whether `error` is a RocksDB failure which may go away if the same operation is retried,
+/// i.e. an I/O error, a busy resource, a timeout or a try again, as opposed to e.g. corruption or
+/// an invalid argument. Errors not coming from RocksDB aren't.
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error
+            .downcast_ref::<rocksdb::Error>()
+            .map(rocksdb::Error::kind),
+        Some(
+            rocksdb::ErrorKind::IOError
+                | rocksdb::ErrorKind::Busy
+                | rocksdb::ErrorKind::TimedOut
+                | rocksdb::ErrorKind::TryAgain
+        )
+    )
 }
 
 pub enum ScanDirection {
@@ -182,6 +258,59 @@ where
         Ok(())
     }
 
+    /// Same as `next()`, but only decodes the key, leaving the value unread for scans that only
+    /// need the keys.
+    pub fn next_key(&mut self) -> Option<Result<S::Key>> {
+        self.next_key_impl().transpose()
+    }
+
+    fn next_key_impl(&mut self) -> Result<Option<S::Key>> {
+        let _timer = APTOS_SCHEMADB_ITER_LATENCY_SECONDS
+            .with_label_values(&[S::COLUMN_FAMILY_NAME])
+            .start_timer();
+
+        if !self.db_iter.valid() {
+            self.db_iter.status()?;
+            return Ok(None);
+        }
+
+        let raw_key = self.db_iter.key().expect("Iterator must be valid.");
+        APTOS_SCHEMADB_ITER_BYTES
+            .with_label_values(&[S::COLUMN_FAMILY_NAME])
+            .observe(raw_key.len() as f64);
+
+        let key = <S::Key as KeyCodec<S>>::decode_key(raw_key)?;
+        self.advance();
+
+        Ok(Some(key))
+    }
+
+    /// Same as `next()`, but an entry failing to decode is moved past rather than failing the
+    /// iteration, after handing its raw key over to `on_undecodable` along with the error, e.g.
+    /// to get through entries written in a legacy layout.
+    pub fn next_skipping_undecodable(
+        &mut self,
+        mut on_undecodable: impl FnMut(&[u8], &anyhow::Error),
+    ) -> Option<Result<(S::Key, S::Value)>> {
+        loop {
+            if !self.db_iter.valid() {
+                return self.db_iter.status().err().map(|e| Err(e.into()));
+            }
+            let raw_key = self.db_iter.key().expect("Iterator must be valid.");
+            let raw_value = self.db_iter.value().expect("Iterator must be valid.");
+            let row = <S::Key as KeyCodec<S>>::decode_key(raw_key).and_then(|key| {
+                <S::Value as ValueCodec<S>>::decode_value(raw_value).map(|value| (key, value))
+            });
+            if let Err(e) = &row {
+                on_undecodable(raw_key, e);
+            }
+            self.advance();
+            if let Ok(row) = row {
+                return Some(Ok(row));
+            }
+        }
+    }
+
     fn next_impl(&mut self) -> Result<Option<(S::Key, S::Value)>> {
         let _timer = APTOS_SCHEMADB_ITER_LATENCY_SECONDS
             .with_label_values(&[S::COLUMN_FAMILY_NAME])
@@ -200,13 +329,16 @@ where
 
         let key = <S::Key as KeyCodec<S>>::decode_key(raw_key)?;
         let value = <S::Value as ValueCodec<S>>::decode_value(raw_value)?;
+        self.advance();
 
+        Ok(Some((key, value)))
+    }
+
+    fn advance(&mut self) {
         match self.direction {
             ScanDirection::Forward => self.db_iter.next(),
             ScanDirection::Backward => self.db_iter.prev(),
         }
-
-        Ok(Some((key, value)))
     }
 }
 
@@ -392,6 +524,18 @@ impl DB {
         Ok(())
     }
 
+    /// Compacts the key range [begin, end) of the column family of schema `S`, e.g. to get rid
+    /// of the tombstones left by deleting the keys in it.
+    pub fn compact_range_for_schema<S: Schema>(&self, begin: &S::Key, end: &S::Key) -> Result<()> {
+        let raw_begin = <S::Key as KeyCodec<S>>::encode_key(begin)?;
+        let raw_end = <S::Key as KeyCodec<S>>::encode_key(end)?;
+        let cf_handle = self.get_cf_handle(S::COLUMN_FAMILY_NAME)?;
+
+        self.inner
+            .compact_range_cf(cf_handle, Some(&raw_begin), Some(&raw_end));
+        Ok(())
+    }
+
     fn iter_with_direction<S: Schema>(
         &self,
         opts: ReadOptions,
@@ -416,27 +560,17 @@ impl DB {
 
     /// Writes a group of records wrapped in a [`SchemaBatch`].
     pub fn write_schemas(&self, batch: SchemaBatch) -> Result<()> {
+        self.write_schemas_ref(&batch)
+    }
+
+    /// Same as [`DB::write_schemas`], but leaves the batch to the caller, e.g. to write it again
+    /// if the write fails.
+    pub fn write_schemas_ref(&self, batch: &SchemaBatch) -> Result<()> {
         let _timer = APTOS_SCHEMADB_BATCH_COMMIT_LATENCY_SECONDS
             .with_label_values(&[self.name])
             .start_timer();
 
-        let mut db_batch = rocksdb::WriteBatch::default();
-        for (cf_name, rows) in &batch.rows {
-            let cf_handle = self.get_cf_handle(cf_name)?;
-            for write_op in rows {
-                match write_op {
-                    WriteOp::Value { key, value } => db_batch.put_cf(cf_handle, key, value),
-                    WriteOp::Deletion { key } => db_batch.delete_cf(cf_handle, key),
-                    WriteOp::DeletionRange { begin, end } => {
-                        db_batch.delete_range_cf(cf_handle, begin, end);
-                    }
-                    WriteOp::DeletionRangeInclusive { begin, end } => {
-                        db_batch.delete_range_cf(cf_handle, begin, end);
-                        db_batch.delete_cf(cf_handle, end);
-                    }
-                }
-            }
-        }
+        let db_batch = self.to_write_batch(batch)?;
         let serialized_size = db_batch.size_in_bytes();
 
         self.inner.write_opt(db_batch, &default_write_options())?;
@@ -473,6 +607,32 @@ impl DB {
         Ok(())
     }
 
+    /// Returns the size `batch` takes serialized as RocksDB writes it, without writing it.
+    pub fn serialized_size(&self, batch: &SchemaBatch) -> Result<usize> {
+        Ok(self.to_write_batch(batch)?.size_in_bytes())
+    }
+
+    fn to_write_batch(&self, batch: &SchemaBatch) -> Result<rocksdb::WriteBatch> {
+        let mut db_batch = rocksdb::WriteBatch::default();
+        for (cf_name, rows) in &batch.rows {
+            let cf_handle = self.get_cf_handle(cf_name)?;
+            for write_op in rows {
+                match write_op {
+                    WriteOp::Value { key, value } => db_batch.put_cf(cf_handle, key, value),
+                    WriteOp::Deletion { key } => db_batch.delete_cf(cf_handle, key),
+                    WriteOp::DeletionRange { begin, end } => {
+                        db_batch.delete_range_cf(cf_handle, begin, end);
+                    }
+                    WriteOp::DeletionRangeInclusive { begin, end } => {
+                        db_batch.delete_range_cf(cf_handle, begin, end);
+                        db_batch.delete_cf(cf_handle, end);
+                    }
+                }
+            }
+        }
+        Ok(db_batch)
+    }
+
     fn get_cf_handle(&self, cf_name: &str) -> Result<&rocksdb::ColumnFamily> {
         self.inner.cf_handle(cf_name).ok_or_else(|| {
             format_err!(
@@ -482,6 +642,19 @@ impl DB {
         })
     }
 
+    /// Returns the names of the column families of the DB at `path`, without opening it.
+    pub fn list_column_families(
+        path: impl AsRef<Path>,
+        db_opts: &rocksdb::Options,
+    ) -> Result<Vec<String>> {
+        Ok(rocksdb::DB::list_cf(db_opts, path)?)
+    }
+
+    /// Returns the column families the DB was opened with.
+    pub fn column_families(&self) -> &[ColumnFamilyName] {
+        &self.column_families
+    }
+
     /// Flushes all memtable data. This is only used for testing `get_approximate_sizes_cf` in unit
     /// tests.
     pub fn flush_all(&self) -> Result<()> {
@@ -504,6 +677,16 @@ impl DB {
             })
     }
 
+    /// Returns the RocksDB estimate of the bytes of live data, i.e. neither deleted nor
+    /// overwritten, over all the column families. Space freed by deletions only shows once they
+    /// are compacted away.
+    pub fn estimate_live_data_size(&self) -> Result<u64> {
+        self.column_families
+            .iter()
+            .map(|cf_name| self.get_property(cf_name, "rocksdb.estimate-live-data-size"))
+            .sum()
+    }
+
     /// Creates new physical DB checkpoint in directory specified by `path`.
     pub fn create_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         rocksdb::checkpoint::Checkpoint::new(&self.inner)?.create_checkpoint(path)?;
@@ -5,7 +5,7 @@ use anyhow::Result;
 use byteorder::{LittleEndian, ReadBytesExt};
 use proptest::{collection::vec, prelude::*};
 use schemadb::{
-    define_schema,
+    define_schema, is_transient_error,
     schema::{KeyCodec, Schema, ValueCodec},
     ColumnFamilyName, SchemaBatch, DB, DEFAULT_CF_NAME,
 };
@@ -308,6 +308,149 @@ fn test_single_schema_batch() {
     );
 }
 
+#[test]
+fn test_schema_batch_len() {
+    let mut db_batch = SchemaBatch::new();
+    assert!(db_batch.is_empty());
+
+    db_batch
+        .put::<TestSchema1>(&TestField(0), &TestField(0))
+        .unwrap();
+    db_batch.delete::<TestSchema2>(&TestField(1)).unwrap();
+    db_batch
+        .delete_range::<TestSchema1>(&TestField(2), &TestField(4))
+        .unwrap();
+    db_batch
+        .delete_range_inclusive::<TestSchema2>(&TestField(5), &TestField(7))
+        .unwrap();
+
+    assert_eq!(db_batch.len(), 4);
+    assert!(!db_batch.is_empty());
+    let len_by_column_family = db_batch.len_by_column_family();
+    assert_eq!(len_by_column_family.len(), 2);
+    assert_eq!(len_by_column_family[TestSchema1::COLUMN_FAMILY_NAME], 2);
+    assert_eq!(len_by_column_family[TestSchema2::COLUMN_FAMILY_NAME], 2);
+    // A put holds a key and a value, a deletion a key and a range deletion two keys, of 4 bytes
+    // each.
+    assert_eq!(db_batch.size_in_bytes(), 4 * (2 + 1 + 2 + 2));
+}
+
+#[test]
+fn test_schema_batch_size_in_bytes() {
+    let db = TestDB::new();
+    let mut db_batch = SchemaBatch::new();
+    assert_eq!(db.serialized_size(&db_batch).unwrap(), 12);
+    for i in 0..100 {
+        db_batch
+            .put::<TestSchema1>(&TestField(i), &TestField(i))
+            .unwrap();
+        db_batch.delete::<TestSchema2>(&TestField(i)).unwrap();
+    }
+    db_batch
+        .delete_range::<TestSchema1>(&TestField(0), &TestField(50))
+        .unwrap();
+    db_batch
+        .delete_range_inclusive::<TestSchema2>(&TestField(0), &TestField(50))
+        .unwrap();
+
+    // Short of the header of the batch, and of a few bytes of framing per write operation, plus
+    // the end key an inclusive range deletion deletes again.
+    let estimate = db_batch.size_in_bytes();
+    let serialized_size = db.serialized_size(&db_batch).unwrap();
+    assert!(estimate < serialized_size);
+    assert!(serialized_size <= estimate + 12 + 8 * db_batch.len());
+
+    // Which is what gets written.
+    db.write_schemas(db_batch).unwrap();
+    assert_eq!(collect_values::<TestSchema1>(&db).len(), 50);
+}
+
+#[test]
+fn test_schema_batch_deleted_keys() {
+    let mut db_batch = SchemaBatch::new();
+    assert!(db_batch.deleted_keys::<TestSchema1>().unwrap().is_empty());
+
+    db_batch.delete::<TestSchema1>(&TestField(3)).unwrap();
+    db_batch
+        .put::<TestSchema1>(&TestField(0), &TestField(0))
+        .unwrap();
+    db_batch.delete::<TestSchema2>(&TestField(1)).unwrap();
+    db_batch
+        .delete_range::<TestSchema1>(&TestField(4), &TestField(6))
+        .unwrap();
+    db_batch.delete::<TestSchema1>(&TestField(2)).unwrap();
+
+    assert_eq!(
+        db_batch.deleted_keys::<TestSchema1>().unwrap(),
+        vec![TestField(3), TestField(2)]
+    );
+    assert_eq!(
+        db_batch.deleted_keys::<TestSchema2>().unwrap(),
+        vec![TestField(1)]
+    );
+}
+
+#[test]
+fn test_schema_batch_merge() {
+    let db = TestDB::new();
+
+    let mut db_batch = SchemaBatch::new();
+    db_batch
+        .put::<TestSchema1>(&TestField(0), &TestField(0))
+        .unwrap();
+    db_batch
+        .put::<TestSchema1>(&TestField(1), &TestField(1))
+        .unwrap();
+    let mut other_batch = SchemaBatch::new();
+    other_batch.delete::<TestSchema1>(&TestField(0)).unwrap();
+    other_batch
+        .put::<TestSchema2>(&TestField(2), &TestField(2))
+        .unwrap();
+    db_batch.merge(other_batch);
+    assert_eq!(db_batch.len(), 4);
+    db.write_schemas(db_batch).unwrap();
+
+    // The deletion merged in comes after the put it overrides.
+    assert_eq!(
+        collect_values::<TestSchema1>(&db),
+        gen_expected_values(&[(1, 1)]),
+    );
+    assert_eq!(
+        collect_values::<TestSchema2>(&db),
+        gen_expected_values(&[(2, 2)]),
+    );
+}
+
+#[test]
+fn test_is_transient_error() {
+    // Opening a DB already open fails on its lock, an I/O error.
+    let tmpdir = aptos_temppath::TempPath::new();
+    let _db = open_db(&tmpdir);
+    let mut db_opts = rocksdb::Options::default();
+    db_opts.create_if_missing(true);
+    let error = DB::open(&tmpdir.path(), "test", get_column_families(), &db_opts)
+        .err()
+        .unwrap();
+    assert!(is_transient_error(&error));
+
+    // Opening a DB that doesn't exist without creating it is an invalid argument.
+    let missing_dir = aptos_temppath::TempPath::new();
+    let error = DB::open(
+        &missing_dir.path(),
+        "test",
+        get_column_families(),
+        &rocksdb::Options::default(),
+    )
+    .err()
+    .unwrap();
+    assert!(!is_transient_error(&error));
+
+    // Only RocksDB errors are told apart, whatever the message.
+    assert!(!is_transient_error(&anyhow::format_err!(
+        "IO error: No space left on device"
+    )));
+}
+
 #[test]
 fn test_two_schema_batches() {
     let db = TestDB::new();
@@ -437,6 +580,42 @@ fn test_report_size() {
             .unwrap(),
         0
     );
+    assert_eq!(
+        db.estimate_live_data_size().unwrap(),
+        db.get_property("TestCF1", "rocksdb.estimate-live-data-size")
+            .unwrap()
+            + db.get_property("TestCF2", "rocksdb.estimate-live-data-size")
+                .unwrap()
+    );
+}
+
+#[test]
+fn test_compact_range_for_schema() {
+    let db = TestDB::new();
+
+    let mut db_batch = SchemaBatch::new();
+    for i in 0..100 {
+        db_batch
+            .put::<TestSchema1>(&TestField(i), &TestField(i))
+            .unwrap();
+    }
+    db.write_schemas(db_batch).unwrap();
+    db.flush_all().unwrap();
+
+    let mut db_batch = SchemaBatch::new();
+    db_batch
+        .delete_range::<TestSchema1>(&TestField(0), &TestField(50))
+        .unwrap();
+    db.write_schemas(db_batch).unwrap();
+    db.compact_range_for_schema::<TestSchema1>(&TestField(0), &TestField(50))
+        .unwrap();
+
+    for i in 0..100 {
+        assert_eq!(
+            db.get::<TestSchema1>(&TestField(i)).unwrap(),
+            if i < 50 { None } else { Some(TestField(i)) },
+        );
+    }
 }
 
 #[test]
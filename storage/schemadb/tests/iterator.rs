@@ -47,6 +47,31 @@ impl ValueCodec<TestSchema> for TestValue {
     }
 }
 
+// Shares the column family of `TestSchema`, with keys `TestSchema` fails to decode.
+define_schema!(LegacyTestSchema, u32, TestValue, "TestCF");
+
+impl KeyCodec<LegacyTestSchema> for u32 {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        let mut reader = std::io::Cursor::new(data);
+        Ok(reader.read_u32::<BigEndian>()?)
+    }
+}
+
+impl ValueCodec<LegacyTestSchema> for TestValue {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(self.0.to_be_bytes().to_vec())
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        let mut reader = std::io::Cursor::new(data);
+        Ok(TestValue(reader.read_u32::<BigEndian>()?))
+    }
+}
+
 pub struct KeyPrefix1(u32);
 
 impl SeekKeyCodec<TestSchema> for KeyPrefix1 {
@@ -265,3 +290,54 @@ fn test_seek_for_prev_by_2prefix() {
     iter.seek_for_prev(&KeyPrefix2(2, 0)).unwrap();
     assert_eq!(collect_values(iter), [114, 112, 110, 104, 102, 100]);
 }
+
+#[test]
+fn test_next_key() {
+    let db = TestDB::new();
+
+    let mut iter = db.iter();
+    iter.seek(&KeyPrefix2(1, 1)).unwrap();
+    assert_eq!(iter.next_key().unwrap().unwrap(), TestKey(1, 1, 0));
+    // Keys and rows can be read interchangeably.
+    assert_eq!(
+        iter.next().unwrap().unwrap(),
+        (TestKey(1, 1, 2), TestValue(112))
+    );
+    assert_eq!(iter.next_key().unwrap().unwrap(), TestKey(1, 1, 4));
+
+    let mut iter = db.rev_iter();
+    iter.seek_to_last();
+    assert_eq!(iter.next_key().unwrap().unwrap(), TestKey(2, 0, 2));
+    assert_eq!(iter.next_key().unwrap().unwrap(), TestKey(2, 0, 0));
+
+    let mut iter = db.iter();
+    iter.seek(&KeyPrefix1(3)).unwrap();
+    assert!(iter.next_key().is_none());
+}
+
+#[test]
+fn test_next_skipping_undecodable() {
+    let db = TestDB::new();
+    db.db.put::<LegacyTestSchema>(&1, &TestValue(1)).unwrap();
+    db.db.put::<LegacyTestSchema>(&2, &TestValue(2)).unwrap();
+
+    // The legacy keys sort right before the keys with the same prefix.
+    let mut iter = db.iter();
+    iter.seek_to_first();
+    assert!(iter.next().unwrap().is_err());
+
+    let mut undecodable_keys = vec![];
+    let mut iter = db.iter();
+    iter.seek_to_first();
+    let mut values = vec![];
+    while let Some(row) = iter.next_skipping_undecodable(|raw_key, _error| {
+        undecodable_keys.push(raw_key.to_vec());
+    }) {
+        values.push((row.unwrap().1).0);
+    }
+    assert_eq!(values, [100, 102, 104, 110, 112, 114, 200, 202]);
+    assert_eq!(
+        undecodable_keys,
+        vec![1u32.to_be_bytes().to_vec(), 2u32.to_be_bytes().to_vec()]
+    );
+}